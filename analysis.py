@@ -25,6 +25,7 @@ PATTERN_LABELS = {
     "lifo": "LIFO",
     "fifo": "FIFO",
     "random": "Random",
+    "hot": "Hot",
 }
 
 
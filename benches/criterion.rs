@@ -0,0 +1,50 @@
+// run_benchmarkと同じマトリクス（スレッドごとのアロケータ × パターン × サイズ）をCriterionで計測する
+// 外れ値の分析やHTMLレポート（target/criterion/report/index.html）が欲しいとき向けで、
+// HPCのバッチ実行や結果ファイル（parquet等）への書き出しはこれまで通りmemalloc-bench本体で行う
+//
+// cargo bench --bench criterion                     # 全セル
+// cargo bench --bench criterion -- 'box/immediate'  # 名前で絞り込む（<allocator>/<pattern>/<size>）
+//
+// 共有アロケータ（slab_mutex / bufpool_lockfree等）はスレッドを立てて計測するので含めない
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use memalloc_bench::{run_benchmark, Allocator, Pattern, OPS_PER_ITERATION, SIZES};
+use quanta::Clock;
+use rand::SeedableRng;
+use std::time::Duration;
+
+fn matrix(c: &mut Criterion) {
+    let clock = Clock::new();
+    for &allocator in Allocator::all() {
+        if allocator.is_shared() {
+            continue;
+        }
+        for &pattern in Pattern::all() {
+            if !allocator.supports(pattern) {
+                continue;
+            }
+            let mut group =
+                c.benchmark_group(format!("{}/{}", allocator.as_str(), pattern.as_str()));
+            // 1イテレーション = INNER_LOOP × BATCH_SIZE 回の alloc + dealloc
+            group.throughput(Throughput::Elements(OPS_PER_ITERATION as u64));
+            for &size in SIZES {
+                let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+                group.bench_with_input(BenchmarkId::from_parameter(size), &size, |b, &size| {
+                    // run_benchmarkが計測したINNER_LOOP回の合計時間をそのまま使う（セットアップを含めない）
+                    b.iter_custom(|iters| {
+                        let total_ns: u64 = (0..iters)
+                            .map(|_| {
+                                run_benchmark(&clock, allocator, pattern, size, &mut rng).total_ns
+                            })
+                            .sum();
+                        Duration::from_nanos(total_ns)
+                    });
+                });
+            }
+            group.finish();
+        }
+    }
+}
+
+criterion_group!(benches, matrix);
+criterion_main!(benches);
@@ -0,0 +1,255 @@
+use crate::compare::load;
+use crate::plot::Metric;
+use log::{info, warn};
+use memalloc_results::stats::{cell_id_of, group_by_cell_with, Summary};
+use memalloc_results::{INNER_LOOP, OPS_PER_ITERATION};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::path::PathBuf;
+
+#[derive(clap::Args, Debug)]
+pub struct AnalyzeArgs {
+    /// Result file or dataset directory
+    input: PathBuf,
+
+    /// Which measurement to summarize
+    #[arg(long, value_enum, default_value_t = Metric::Total)]
+    metric: Metric,
+
+    /// Allocator to compare every other allocator against (adds a per-cell speedup column)
+    #[arg(long, value_name = "ALLOCATOR")]
+    baseline: Option<String>,
+
+    /// Output format
+    #[arg(long, value_enum, default_value_t = AnalyzeFormat::Table)]
+    format: AnalyzeFormat,
+
+    /// Write the statistics to this file instead of stdout
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub enum AnalyzeFormat {
+    /// Aligned columns for reading in a terminal
+    Table,
+    /// One row per cell with a header (for spreadsheets and pandas)
+    Csv,
+    /// Array of objects, one per cell
+    Json,
+}
+
+// 1セル分の統計量（ns/op）
+#[derive(Serialize)]
+struct CellStats {
+    cell_id: String,
+    allocator: String,
+    pattern: String,
+    size_bytes: u32,
+    threads: u32,
+    numa: String,
+    smt: String,
+    // サンプル数（イテレーション数 x スレッド数）
+    count: usize,
+    mean: f64,
+    median: f64,
+    stddev: f64,
+    p99: f64,
+    // --baselineのアロケータの同じセルの中央値 / このセルの中央値（1より大きければbaselineより速い）
+    // --baselineを指定しないか、baselineに同じセルがなければNone
+    #[serde(skip_serializing_if = "Option::is_none")]
+    speedup: Option<f64>,
+}
+
+const CSV_HEADER: &str =
+    "cell_id,allocator,pattern,size_bytes,threads,numa,smt,count,mean,median,stddev,p99";
+
+// セルごとにmetricの値を1操作あたりに直して要約する
+fn analyze(args: &AnalyzeArgs) -> Result<Vec<CellStats>, Box<dyn std::error::Error>> {
+    let results = load(&args.input)?;
+
+    // latencyは1バッチ分 (BATCH_SIZE回の alloc + dealloc) の時間
+    let (samples, ops) = match args.metric {
+        Metric::Total => (
+            group_by_cell_with(&results, |r| r.total_ns),
+            OPS_PER_ITERATION as f64,
+        ),
+        Metric::Latency => (
+            group_by_cell_with(&results, |r| r.latency_ns),
+            (OPS_PER_ITERATION / INNER_LOOP) as f64,
+        ),
+    };
+
+    let mut cells: Vec<CellStats> = samples
+        .iter()
+        .map(|(key, samples)| {
+            let s = Summary::of(samples).per(ops);
+            CellStats {
+                cell_id: cell_id_of(key),
+                allocator: key.0.clone(),
+                pattern: key.1.clone(),
+                size_bytes: key.2,
+                threads: key.3,
+                numa: key.4.clone(),
+                smt: key.5.clone(),
+                count: s.count,
+                mean: s.mean,
+                median: s.median,
+                stddev: s.stddev,
+                p99: s.p99,
+                speedup: None,
+            }
+        })
+        .collect();
+
+    if let Some(baseline) = &args.baseline {
+        speedup(&mut cells, baseline)?;
+    }
+    Ok(cells)
+}
+
+// アロケータ以外が同じセル（pattern, size, threads, numa, smt）のbaselineの中央値と比べる
+fn speedup(cells: &mut [CellStats], baseline: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let key = |c: &CellStats| {
+        (
+            c.pattern.clone(),
+            c.size_bytes,
+            c.threads,
+            c.numa.clone(),
+            c.smt.clone(),
+        )
+    };
+    let medians: BTreeMap<_, f64> = cells
+        .iter()
+        .filter(|c| c.allocator == baseline)
+        .map(|c| (key(c), c.median))
+        .collect();
+    if medians.is_empty() {
+        return Err(format!("baseline allocator '{}' is not in the results", baseline).into());
+    }
+
+    let mut missing = 0;
+    for c in cells.iter_mut() {
+        c.speedup = medians.get(&key(c)).map(|base| base / c.median);
+        missing += usize::from(c.speedup.is_none());
+    }
+    if missing > 0 {
+        warn!(
+            "{} cells have no matching '{}' cell, their speedup is left empty",
+            missing, baseline
+        );
+    }
+    Ok(())
+}
+
+// baselineを指定した場合はspeedupの列を足す（baselineに同じセルがなければ "-"）
+fn table(cells: &[CellStats], baseline: bool) -> Result<String, Box<dyn std::error::Error>> {
+    let mut out = String::new();
+    write!(
+        out,
+        "{:<16} {:<10} {:>6} {:>7} {:<6} {:<8} {:>6} {:>9} {:>9} {:>9} {:>9}",
+        "allocator",
+        "pattern",
+        "size",
+        "threads",
+        "numa",
+        "smt",
+        "n",
+        "mean",
+        "median",
+        "stddev",
+        "p99"
+    )?;
+    if baseline {
+        write!(out, " {:>8}", "speedup")?;
+    }
+    writeln!(out)?;
+    for c in cells {
+        write!(
+            out,
+            "{:<16} {:<10} {:>6} {:>7} {:<6} {:<8} {:>6} {:>9.3} {:>9.3} {:>9.3} {:>9.3}",
+            c.allocator,
+            c.pattern,
+            c.size_bytes,
+            c.threads,
+            c.numa,
+            c.smt,
+            c.count,
+            c.mean,
+            c.median,
+            c.stddev,
+            c.p99
+        )?;
+        if baseline {
+            match c.speedup {
+                Some(speedup) => write!(out, " {:>7.3}x", speedup)?,
+                None => write!(out, " {:>8}", "-")?,
+            }
+        }
+        writeln!(out)?;
+    }
+    Ok(out)
+}
+
+// 列の値にカンマや引用符は入らない（アロケータ名などは計測側で決めた識別子）
+// baselineを指定した場合はspeedupの列を足す（baselineに同じセルがなければ空）
+fn csv(cells: &[CellStats], baseline: bool) -> Result<String, Box<dyn std::error::Error>> {
+    let mut out = String::new();
+    write!(out, "{}", CSV_HEADER)?;
+    if baseline {
+        write!(out, ",speedup")?;
+    }
+    writeln!(out)?;
+    for c in cells {
+        write!(
+            out,
+            "{},{},{},{},{},{},{},{},{},{},{},{}",
+            c.cell_id,
+            c.allocator,
+            c.pattern,
+            c.size_bytes,
+            c.threads,
+            c.numa,
+            c.smt,
+            c.count,
+            c.mean,
+            c.median,
+            c.stddev,
+            c.p99
+        )?;
+        if baseline {
+            write!(
+                out,
+                ",{}",
+                c.speedup.map(|s| s.to_string()).unwrap_or_default()
+            )?;
+        }
+        writeln!(out)?;
+    }
+    Ok(out)
+}
+
+pub fn run(args: &AnalyzeArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let cells = analyze(args)?;
+    let baseline = args.baseline.is_some();
+    let out = match args.format {
+        AnalyzeFormat::Table => table(&cells, baseline)?,
+        AnalyzeFormat::Csv => csv(&cells, baseline)?,
+        AnalyzeFormat::Json => serde_json::to_string_pretty(&cells)? + "\n",
+    };
+
+    match &args.output {
+        Some(path) => {
+            std::fs::write(path, out)?;
+            info!(
+                "Statistics of {} cells written to {}",
+                cells.len(),
+                path.display()
+            );
+        }
+        None => print!("{}", out),
+    }
+
+    Ok(())
+}
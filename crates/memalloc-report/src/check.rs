@@ -0,0 +1,160 @@
+use crate::compare::load;
+use log::{info, warn};
+use memalloc_results::stats::{cell_id_of, group_by_cell, Summary};
+use serde::Serialize;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+#[derive(clap::Args, Debug)]
+pub struct CheckArgs {
+    /// Baseline result file
+    #[arg(long)]
+    baseline: PathBuf,
+
+    /// Candidate result file
+    candidate: PathBuf,
+
+    /// Maximum allowed slowdown of the median per cell (e.g. "5%" or "5")
+    #[arg(long, default_value = "5%", value_parser = parse_percent)]
+    threshold: f64,
+
+    /// Write the JSON report to this file instead of stdout
+    #[arg(long)]
+    report: Option<PathBuf>,
+
+    /// Pass even when baseline cells are missing from the candidate (or no cell matches at all)
+    #[arg(long)]
+    allow_missing: bool,
+}
+
+// "5%" / "5" -> 5.0
+fn parse_percent(s: &str) -> Result<f64, String> {
+    let value: f64 = s
+        .trim()
+        .trim_end_matches('%')
+        .parse()
+        .map_err(|_| format!("invalid percentage: {}", s))?;
+    if value < 0.0 {
+        return Err(format!("threshold must not be negative: {}", s));
+    }
+    Ok(value)
+}
+
+#[derive(Serialize)]
+struct CellReport {
+    cell_id: String,
+    allocator: String,
+    pattern: String,
+    size_bytes: u32,
+    threads: u32,
+    numa: String,
+    smt: String,
+    baseline_median_ns: f64,
+    candidate_median_ns: f64,
+    // 正の値は遅くなったことを表す
+    change_pct: f64,
+    regressed: bool,
+}
+
+#[derive(Serialize)]
+struct Report {
+    baseline: String,
+    candidate: String,
+    threshold_pct: f64,
+    cells: usize,
+    regressions: usize,
+    // baselineにだけあるセル（candidateが途中で落ちた、アロケータの名前が変わったなど）
+    // --allow-missingを指定しなければリグレッションと同じく失敗にする
+    missing: Vec<String>,
+    passed: bool,
+    results: Vec<CellReport>,
+}
+
+// リグレッションも足りないセルもなければtrueを返す
+// 突き合わせられるセルが1つもなければ（別のファイルを渡したなど）、--allow-missingを指定しない限りエラーにする
+pub fn run(args: &CheckArgs) -> Result<bool, Box<dyn std::error::Error>> {
+    let baseline = group_by_cell(&load(&args.baseline)?);
+    let candidate = group_by_cell(&load(&args.candidate)?);
+
+    let mut results = Vec::new();
+    let mut missing = Vec::new();
+    for (key, base) in &baseline {
+        let Some(cand) = candidate.get(key) else {
+            missing.push(cell_id_of(key));
+            continue;
+        };
+        let base_median = Summary::of(base).median;
+        let cand_median = Summary::of(cand).median;
+        let change_pct = (cand_median / base_median - 1.0) * 100.0;
+        let regressed = change_pct > args.threshold;
+        if regressed {
+            warn!(
+                "Regression: {}: {:+.2}% (threshold {}%)",
+                cell_id_of(key),
+                change_pct,
+                args.threshold
+            );
+        }
+        results.push(CellReport {
+            cell_id: cell_id_of(key),
+            allocator: key.0.clone(),
+            pattern: key.1.clone(),
+            size_bytes: key.2,
+            threads: key.3,
+            numa: key.4.clone(),
+            smt: key.5.clone(),
+            baseline_median_ns: base_median,
+            candidate_median_ns: cand_median,
+            change_pct,
+            regressed,
+        });
+    }
+
+    if results.is_empty() && !args.allow_missing {
+        return Err(format!(
+            "no cell of {} is in {} (pass --allow-missing to accept)",
+            args.baseline.display(),
+            args.candidate.display()
+        )
+        .into());
+    }
+    for id in &missing {
+        warn!("Missing: {} is not in the candidate", id);
+    }
+
+    let regressions = results.iter().filter(|r| r.regressed).count();
+    let passed = regressions == 0 && (missing.is_empty() || args.allow_missing);
+    let report = Report {
+        baseline: args.baseline.display().to_string(),
+        candidate: args.candidate.display().to_string(),
+        threshold_pct: args.threshold,
+        cells: results.len(),
+        regressions,
+        missing,
+        passed,
+        results,
+    };
+
+    match &args.report {
+        Some(path) => {
+            let mut writer = BufWriter::new(File::create(path)?);
+            serde_json::to_writer_pretty(&mut writer, &report)?;
+            writer.flush()?;
+            info!("Report written to {}", path.display());
+        }
+        None => {
+            serde_json::to_writer_pretty(std::io::stdout().lock(), &report)?;
+            println!();
+        }
+    }
+
+    info!(
+        "{} of {} cells regressed beyond {}%, {} baseline cells missing",
+        regressions,
+        report.cells,
+        args.threshold,
+        report.missing.len()
+    );
+    Ok(passed)
+}
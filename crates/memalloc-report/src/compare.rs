@@ -0,0 +1,145 @@
+use log::{info, warn};
+use memalloc_results::output::{self, Format};
+use memalloc_results::stats::{group_by_cell, holm_adjust, mann_whitney_u, Summary};
+use memalloc_results::BenchResult;
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+// ns -> ms 変換係数
+const NS_TO_MS: f64 = 1e-6;
+
+#[derive(clap::Args, Debug)]
+pub struct CompareArgs {
+    /// Baseline result file
+    baseline: PathBuf,
+
+    /// Candidate result file
+    candidate: PathBuf,
+
+    /// Significance level of the per-cell Mann-Whitney U test, applied to
+    /// Holm-adjusted p-values across all compared cells
+    #[arg(long, default_value_t = 0.05, value_parser = parse_alpha)]
+    alpha: f64,
+}
+
+fn parse_alpha(s: &str) -> Result<f64, String> {
+    let alpha: f64 = s
+        .parse()
+        .map_err(|_| format!("invalid significance level: {}", s))?;
+    if !(alpha > 0.0 && alpha < 1.0) {
+        return Err("significance level must be between 0 and 1 (exclusive)".to_string());
+    }
+    Ok(alpha)
+}
+
+pub fn load(path: &Path) -> Result<Vec<BenchResult>, Box<dyn std::error::Error>> {
+    // 古い版のファイルは今の版の列に揃えて読む（後から追加した列はデフォルト値で補う）
+    let (results, version) = output::read_results_with_version(path, Format::from_path(path))?;
+    if version < output::SCHEMA_VERSION {
+        info!(
+            "{} has schema version {}, upgraded to {} while reading",
+            path.display(),
+            version,
+            output::SCHEMA_VERSION
+        );
+    }
+    let platforms: BTreeSet<&str> = results.iter().map(|r| r.platform.as_str()).collect();
+    if platforms.len() > 1 {
+        warn!(
+            "{} contains multiple platforms ({:?}), samples will be merged per cell",
+            path.display(),
+            platforms
+        );
+    }
+    info!("Loaded {} records from {}", results.len(), path.display());
+    Ok(results)
+}
+
+pub fn run(args: &CompareArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let baseline = group_by_cell(&load(&args.baseline)?);
+    let candidate = group_by_cell(&load(&args.candidate)?);
+
+    // 全イテレーションの分布を比べ、差がノイズの範囲かを判定する
+    let mut rows = Vec::new();
+    for (key, base) in &baseline {
+        let Some(cand) = candidate.get(key) else {
+            continue;
+        };
+        let p = mann_whitney_u(base, cand);
+        rows.push((key, Summary::of(base), Summary::of(cand), p));
+    }
+    // 比べたセルの数だけ検定を繰り返すので、有意かどうかはHolmの方法で補正したp値で判定する
+    let p_values: Vec<f64> = rows.iter().map(|row| row.3).collect();
+    let adjusted = holm_adjust(&p_values);
+
+    println!(
+        "{:<14} {:<10} {:>6} {:>7} {:<6} {:<8} {:>14} {:>14} {:>9} {:>8} {:>8}",
+        "allocator",
+        "pattern",
+        "size",
+        "threads",
+        "numa",
+        "smt",
+        "baseline(ms)",
+        "candidate(ms)",
+        "speedup",
+        "p",
+        "p_holm"
+    );
+
+    let matched = rows.len();
+    let mut significant = 0;
+    for ((key, base, cand, p), p_holm) in rows.iter().zip(adjusted) {
+        // >1 なら candidate の方が速い
+        let speedup = base.median / cand.median;
+        let marker = if p_holm < args.alpha {
+            significant += 1;
+            "*"
+        } else {
+            ""
+        };
+
+        println!(
+            "{:<14} {:<10} {:>6} {:>7} {:<6} {:<8} {:>14.3} {:>14.3} {:>8.3}x {:>8.4} {:>8.4}{}",
+            key.0,
+            key.1,
+            key.2,
+            key.3,
+            key.4,
+            key.5,
+            base.median * NS_TO_MS,
+            cand.median * NS_TO_MS,
+            speedup,
+            p,
+            p_holm,
+            marker
+        );
+    }
+
+    let only_baseline = baseline
+        .keys()
+        .filter(|k| !candidate.contains_key(*k))
+        .count();
+    let only_candidate = candidate
+        .keys()
+        .filter(|k| !baseline.contains_key(*k))
+        .count();
+    if only_baseline > 0 || only_candidate > 0 {
+        warn!(
+            "{} cells only in baseline, {} cells only in candidate (not compared)",
+            only_baseline, only_candidate
+        );
+    }
+
+    println!();
+    println!(
+        "{} cells compared, {} significantly different. speedup = baseline median / candidate median (>1 means candidate is faster)",
+        matched, significant
+    );
+    println!(
+        "p = two-sided Mann-Whitney U test over all iterations; p_holm = p adjusted for the {} compared cells (Holm); * p_holm < {} (cells without * are within noise)",
+        matched, args.alpha
+    );
+
+    Ok(())
+}
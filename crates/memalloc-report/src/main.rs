@@ -0,0 +1,78 @@
+use clap::{ArgAction, Parser, Subcommand};
+use log::LevelFilter;
+
+mod analyze;
+mod check;
+mod compare;
+mod plot;
+mod report;
+
+#[derive(Parser, Debug)]
+#[command(
+    version,
+    about = "Analyze, compare, check, summarize and plot memalloc-bench result files",
+    after_help = "Example:\n  memalloc-report analyze results/benchmark_local.parquet --format csv -o stats.csv\n  memalloc-report compare old.parquet new.parquet\n  memalloc-report check --baseline old.parquet new.parquet --threshold 5%\n  memalloc-report report results/benchmark_local.parquet\n  memalloc-report plot results/dataset -o results/plot.png"
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+
+    /// Only print warnings and errors
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Print diagnostics (-vv for trace output)
+    #[arg(short, long, global = true, action = ArgAction::Count)]
+    verbose: u8,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print per-cell mean/median/stddev/p99 of ns/op
+    Analyze(analyze::AnalyzeArgs),
+    /// Compare two result files and print per-cell speedup ratios
+    Compare(compare::CompareArgs),
+    /// Fail with a non-zero exit code when any cell regresses beyond a threshold
+    Check(check::CheckArgs),
+    /// Summarize a result file as median/p99 tables by allocator and size
+    Report(report::ReportArgs),
+    /// Render ns/op vs size line charts (PNG or SVG), faceted by pattern
+    Plot(plot::PlotArgs),
+}
+
+impl Cli {
+    fn log_level(&self) -> LevelFilter {
+        if self.quiet {
+            return LevelFilter::Warn;
+        }
+        match self.verbose {
+            0 => LevelFilter::Info,
+            1 => LevelFilter::Debug,
+            _ => LevelFilter::Trace,
+        }
+    }
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let cli = Cli::parse();
+
+    // RUST_LOGが設定されていればそちらを優先する
+    env_logger::Builder::from_env(
+        env_logger::Env::default().default_filter_or(cli.log_level().as_str()),
+    )
+    .format_target(false)
+    .init();
+
+    match &cli.command {
+        Command::Analyze(args) => analyze::run(args),
+        Command::Compare(args) => compare::run(args),
+        Command::Check(args) => {
+            if !check::run(args)? {
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        Command::Report(args) => report::run(args),
+        Command::Plot(args) => plot::run(args),
+    }
+}
@@ -0,0 +1,211 @@
+use crate::compare::load;
+use log::info;
+use memalloc_results::stats::{group_by_cell_with, quantile};
+use memalloc_results::{INNER_LOOP, OPS_PER_ITERATION};
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+
+#[derive(clap::Args, Debug)]
+pub struct PlotArgs {
+    /// Result file or dataset directory
+    input: PathBuf,
+
+    /// Output image path (.png or .svg)
+    #[arg(short, long, default_value = "results/plot.svg")]
+    output: PathBuf,
+
+    /// Which measurement to plot
+    #[arg(long, value_enum, default_value_t = Metric::Total)]
+    metric: Metric,
+
+    /// Image width in pixels
+    #[arg(long, default_value_t = 1600)]
+    width: u32,
+
+    /// Image height in pixels
+    #[arg(long, default_value_t = 1200)]
+    height: u32,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub enum Metric {
+    /// Median total time per iteration, divided by the number of operations
+    Total,
+    /// Median latency of the first batch, divided by the number of operations in a batch
+    Latency,
+}
+
+// pattern -> 系列 (allocator, スレッド数, NUMA配置, SMT) -> [(size, ns/op)]
+type Facets = BTreeMap<String, BTreeMap<String, Vec<(f64, f64)>>>;
+
+pub fn run(args: &PlotArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let results = load(&args.input)?;
+
+    // latencyは1バッチ分 (BATCH_SIZE回の alloc + dealloc) の時間
+    let (samples, ops) = match args.metric {
+        Metric::Total => (
+            group_by_cell_with(&results, |r| r.total_ns),
+            OPS_PER_ITERATION as f64,
+        ),
+        Metric::Latency => (
+            group_by_cell_with(&results, |r| r.latency_ns),
+            (OPS_PER_ITERATION / INNER_LOOP) as f64,
+        ),
+    };
+
+    // 複数のスレッド数・NUMA配置・SMTの使い方を含む場合はそれぞれ線を分ける
+    let multi_threaded = samples
+        .keys()
+        .map(|key| key.3)
+        .collect::<BTreeSet<_>>()
+        .len()
+        > 1;
+    let multi_numa = samples
+        .keys()
+        .map(|key| key.4.as_str())
+        .collect::<BTreeSet<_>>()
+        .len()
+        > 1;
+    let multi_smt = samples
+        .keys()
+        .map(|key| key.5.as_str())
+        .collect::<BTreeSet<_>>()
+        .len()
+        > 1;
+    let mut facets = Facets::new();
+    let mut series_names = BTreeSet::new();
+    for ((allocator, pattern, size, threads, numa, smt), samples) in &samples {
+        let mut name = allocator.clone();
+        if multi_threaded {
+            name.push_str(&format!(" x{}", threads));
+        }
+        if multi_numa {
+            name.push_str(&format!(" {}", numa));
+        }
+        if multi_smt {
+            name.push_str(&format!(" {}", smt));
+        }
+        series_names.insert((
+            allocator.clone(),
+            *threads,
+            numa.clone(),
+            smt.clone(),
+            name.clone(),
+        ));
+        facets
+            .entry(pattern.clone())
+            .or_default()
+            .entry(name)
+            .or_default()
+            .push((*size as f64, quantile(samples, 0.5) / ops));
+    }
+    if facets.is_empty() {
+        return Err(format!("no results in {}", args.input.display()).into());
+    }
+    // 色は系列ごとに全ファセットで揃える
+    let allocators: Vec<String> = series_names
+        .into_iter()
+        .map(|(_, _, _, _, name)| name)
+        .collect();
+
+    if let Some(parent) = args.output.parent() {
+        if !parent.as_os_str().is_empty() {
+            std::fs::create_dir_all(parent)?;
+        }
+    }
+
+    let size = (args.width, args.height);
+    match args.output.extension().and_then(|e| e.to_str()) {
+        Some("png") => draw(
+            BitMapBackend::new(&args.output, size).into_drawing_area(),
+            &facets,
+            &allocators,
+        )?,
+        Some("svg") => draw(
+            SVGBackend::new(&args.output, size).into_drawing_area(),
+            &facets,
+            &allocators,
+        )?,
+        _ => {
+            return Err(format!(
+                "unsupported image format: {} (expected .png or .svg)",
+                args.output.display()
+            )
+            .into())
+        }
+    }
+
+    info!("Plot written to {}", args.output.display());
+    Ok(())
+}
+
+// パターンごとのファセットに、x: サイズ (log) / y: ns/op の折れ線をアロケータごとに描く
+fn draw<DB: DrawingBackend>(
+    root: DrawingArea<DB, Shift>,
+    facets: &Facets,
+    allocators: &[String],
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB::ErrorType: 'static,
+{
+    root.fill(&WHITE)?;
+
+    let cols = (facets.len() as f64).sqrt().ceil() as usize;
+    let rows = facets.len().div_ceil(cols);
+    let areas = root.split_evenly((rows, cols));
+
+    for (area, (pattern, series)) in areas.iter().zip(facets) {
+        let points = series.values().flatten();
+        let (x_min, x_max) = points
+            .clone()
+            .fold((f64::MAX, f64::MIN), |(lo, hi), &(x, _)| {
+                (lo.min(x), hi.max(x))
+            });
+        let y_max = points.fold(0.0f64, |hi, &(_, y)| hi.max(y));
+
+        let mut chart = ChartBuilder::on(area)
+            .caption(pattern, ("sans-serif", 24))
+            .margin(16)
+            .x_label_area_size(40)
+            .y_label_area_size(60)
+            .build_cartesian_2d(
+                ((x_min / 1.5)..(x_max * 1.5)).log_scale(),
+                0.0..(y_max * 1.1),
+            )?;
+        chart
+            .configure_mesh()
+            .x_desc("size (bytes)")
+            .y_desc("ns/op")
+            .x_label_formatter(&|x| format!("{:.0}", x))
+            .draw()?;
+
+        for (allocator, points) in series {
+            let index = allocators.iter().position(|a| a == allocator).unwrap_or(0);
+            let color = Palette99::pick(index).to_rgba();
+            chart
+                .draw_series(LineSeries::new(
+                    points.iter().copied(),
+                    color.stroke_width(2),
+                ))?
+                .label(allocator.as_str())
+                .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color));
+            chart.draw_series(
+                points
+                    .iter()
+                    .map(|&point| Circle::new(point, 3, color.filled())),
+            )?;
+        }
+
+        chart
+            .configure_series_labels()
+            .position(SeriesLabelPosition::UpperLeft)
+            .background_style(WHITE.mix(0.8))
+            .border_style(BLACK)
+            .draw()?;
+    }
+
+    root.present()?;
+    Ok(())
+}
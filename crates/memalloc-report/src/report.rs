@@ -0,0 +1,155 @@
+use crate::compare::load;
+use log::info;
+use memalloc_results::stats::{group_by_cell, Summary};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write as _;
+use std::path::PathBuf;
+
+// ns -> ms 変換係数
+const NS_TO_MS: f64 = 1e-6;
+
+#[derive(clap::Args, Debug)]
+pub struct ReportArgs {
+    /// Result file or dataset directory
+    input: PathBuf,
+
+    /// Report format
+    #[arg(long, value_enum, default_value_t = ReportFormat::Md)]
+    format: ReportFormat,
+
+    /// Write the report to this file instead of stdout
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub enum ReportFormat {
+    /// Markdown tables (for pasting into PRs and issues)
+    Md,
+}
+
+// パターンごとに、行: サイズ (, スレッド数, NUMA配置, SMT) / 列: アロケータ の表を作る
+fn markdown(args: &ReportArgs) -> Result<String, Box<dyn std::error::Error>> {
+    let results = load(&args.input)?;
+    let platforms: BTreeSet<&str> = results.iter().map(|r| r.platform.as_str()).collect();
+    let cells = group_by_cell(&results);
+
+    // pattern -> (size, threads, numa, smt) -> allocator -> samples
+    type Rows<'a> = BTreeMap<(u32, u32, &'a str, &'a str), BTreeMap<&'a str, &'a [u64]>>;
+    let mut patterns: BTreeMap<&str, Rows> = BTreeMap::new();
+    let mut allocators = BTreeSet::new();
+    let mut threads = BTreeSet::new();
+    let mut placements = BTreeSet::new();
+    let mut smt_placements = BTreeSet::new();
+    for ((allocator, pattern, size, thread_count, numa, smt), samples) in &cells {
+        allocators.insert(allocator.as_str());
+        threads.insert(*thread_count);
+        placements.insert(numa.as_str());
+        smt_placements.insert(smt.as_str());
+        patterns
+            .entry(pattern.as_str())
+            .or_default()
+            .entry((*size, *thread_count, numa.as_str(), smt.as_str()))
+            .or_default()
+            .insert(allocator.as_str(), samples.as_slice());
+    }
+    // シングルスレッドのみ・NUMA配置やSMTの使い方が1種類のみの結果では対応する列を省く
+    let multi_threaded = threads.len() > 1;
+    let multi_numa = placements.len() > 1;
+    let multi_smt = smt_placements.len() > 1;
+
+    let mut md = String::new();
+    writeln!(md, "## memalloc-bench results")?;
+    writeln!(md)?;
+    writeln!(
+        md,
+        "- platform: {}",
+        platforms.iter().copied().collect::<Vec<_>>().join(", ")
+    )?;
+    writeln!(md, "- records: {}", results.len())?;
+    writeln!(
+        md,
+        "- values: median / p99 of total time per iteration (ms)"
+    )?;
+
+    for (pattern, sizes) in &patterns {
+        writeln!(md)?;
+        writeln!(md, "### {}", pattern)?;
+        writeln!(md)?;
+
+        write!(md, "| size (bytes) |")?;
+        if multi_threaded {
+            write!(md, " threads |")?;
+        }
+        if multi_numa {
+            write!(md, " numa |")?;
+        }
+        if multi_smt {
+            write!(md, " smt |")?;
+        }
+        for allocator in &allocators {
+            write!(md, " {} |", allocator)?;
+        }
+        writeln!(md)?;
+        write!(md, "|---:|")?;
+        if multi_threaded {
+            write!(md, "---:|")?;
+        }
+        if multi_numa {
+            write!(md, "---|")?;
+        }
+        if multi_smt {
+            write!(md, "---|")?;
+        }
+        for _ in &allocators {
+            write!(md, "---:|")?;
+        }
+        writeln!(md)?;
+
+        for ((size, thread_count, numa, smt), by_allocator) in sizes {
+            write!(md, "| {} |", size)?;
+            if multi_threaded {
+                write!(md, " {} |", thread_count)?;
+            }
+            if multi_numa {
+                write!(md, " {} |", numa)?;
+            }
+            if multi_smt {
+                write!(md, " {} |", smt)?;
+            }
+            for allocator in &allocators {
+                match by_allocator
+                    .get(allocator)
+                    .map(|samples| Summary::of(samples))
+                {
+                    Some(summary) => write!(
+                        md,
+                        " {:.3} / {:.3} |",
+                        summary.median * NS_TO_MS,
+                        summary.p99 * NS_TO_MS
+                    )?,
+                    None => write!(md, " - |")?,
+                }
+            }
+            writeln!(md)?;
+        }
+    }
+
+    Ok(md)
+}
+
+pub fn run(args: &ReportArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let report = match args.format {
+        ReportFormat::Md => markdown(args)?,
+    };
+
+    match &args.output {
+        Some(path) => {
+            std::fs::write(path, report)?;
+            info!("Report written to {}", path.display());
+        }
+        None => print!("{}", report),
+    }
+
+    Ok(())
+}
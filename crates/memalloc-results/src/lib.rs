@@ -0,0 +1,61 @@
+//! memalloc-benchの結果の行（BenchResult）と、結果ファイル（parquet / csv / jsonl / sqlite）の読み書き（output）、
+//! セルごとの要約統計量（stats）
+//! 計測するmemalloc-benchと分析するmemalloc-reportの両方がこのクレートを使うので、列を足すときはここだけを変えればよい
+
+pub mod output;
+pub mod stats;
+
+// 測定パラメータ（total_nsとlatency_nsが何回分の時間かを決めるので、分析側もこの値で1操作あたりに直す）
+pub const BATCH_SIZE: usize = 100;
+pub const INNER_LOOP: usize = 1000; // 1回の測定で何回アロケーションするか
+
+// 1測定 = INNER_LOOP × BATCH_SIZE 回の alloc + dealloc
+pub const OPS_PER_ITERATION: usize = INNER_LOOP * BATCH_SIZE * 2;
+
+// 測定結果
+pub struct BenchResult {
+    pub platform: String,
+    pub allocator: String,
+    pub pattern: String,
+    pub size_bytes: u32,
+    pub threads: u32,              // 同時に実行したスレッド数
+    pub thread: u32,               // スレッド番号 (0..threads)
+    pub numa: String,              // NUMA配置 (none / local / remote)
+    pub smt: String,               // SMTの使い方 (any / siblings / separate)
+    pub core: Option<u32>,         // スレッドを固定したコア (固定していなければNone)
+    pub core_type: Option<String>, // 固定したコアの種類 (performance / efficiency、ハイブリッドCPUでなければNone)
+    pub iteration: u32,
+    pub total_ns: u64,   // INNER_LOOP回の合計時間
+    pub latency_ns: u64, // 1回目のレイテンシ
+}
+
+impl BenchResult {
+    // この行のセルのID（cell_id列に書き出す値）
+    pub fn cell_id(&self) -> String {
+        cell_id(
+            &self.allocator,
+            &self.pattern,
+            self.size_bytes,
+            self.threads,
+            &self.numa,
+            &self.smt,
+        )
+    }
+}
+
+// セルのID "allocator/pattern/size_bytes/threads/numa/smt"（例: "slab_warm/random/64/4/none/any"）
+// 計測するセルを決める列の値だけで決まるので、バージョンをまたいでも同じセルは同じIDになる
+// セルを決める列を足すときは、デフォルト値のセルのIDが変わらないよう末尾に足す
+pub fn cell_id(
+    allocator: &str,
+    pattern: &str,
+    size_bytes: u32,
+    threads: u32,
+    numa: &str,
+    smt: &str,
+) -> String {
+    format!(
+        "{}/{}/{}/{}/{}/{}",
+        allocator, pattern, size_bytes, threads, numa, smt
+    )
+}
@@ -0,0 +1,1778 @@
+use crate::BenchResult;
+#[cfg(feature = "arrow")]
+use arrow::array::{Array, ArrayRef, StringArray, UInt32Array, UInt64Array};
+#[cfg(feature = "arrow")]
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+#[cfg(feature = "arrow")]
+use arrow::record_batch::RecordBatch;
+use clap::ValueEnum;
+#[cfg(feature = "arrow")]
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+#[cfg(feature = "arrow")]
+use parquet::arrow::ArrowWriter;
+#[cfg(feature = "arrow")]
+use parquet::basic::{Compression as ParquetCompression, ZstdLevel};
+#[cfg(feature = "arrow")]
+use parquet::file::metadata::KeyValue;
+#[cfg(feature = "arrow")]
+use parquet::file::properties::{EnabledStatistics, WriterProperties};
+#[cfg(feature = "arrow")]
+use std::collections::btree_map::Entry;
+use std::collections::BTreeMap;
+#[cfg(feature = "arrow")]
+use std::fs::File;
+#[cfg(feature = "arrow")]
+use std::io::{BufRead, BufReader, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+#[cfg(feature = "arrow")]
+use std::sync::Arc;
+
+// 出力フォーマット（arrow featureなしではcsvとjsonlを1行ずつ書くだけになり、parquetは選べない）
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum Format {
+    #[cfg(feature = "arrow")]
+    Parquet,
+    Csv,
+    Jsonl,
+    #[cfg(feature = "sqlite")]
+    Sqlite,
+}
+
+impl Format {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            #[cfg(feature = "arrow")]
+            Format::Parquet => "parquet",
+            Format::Csv => "csv",
+            Format::Jsonl => "jsonl",
+            #[cfg(feature = "sqlite")]
+            Format::Sqlite => "sqlite",
+        }
+    }
+
+    // --upload でのContent-Type
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            #[cfg(feature = "arrow")]
+            Format::Parquet => "application/vnd.apache.parquet",
+            Format::Csv => "text/csv",
+            Format::Jsonl => "application/x-ndjson",
+            #[cfg(feature = "sqlite")]
+            Format::Sqlite => "application/vnd.sqlite3",
+        }
+    }
+
+    // 既存の内容に追記する形式か（ファイル形式は毎回全体を書き直す）
+    pub fn appends(&self) -> bool {
+        match self {
+            #[cfg(feature = "sqlite")]
+            Format::Sqlite => true,
+            _ => false,
+        }
+    }
+
+    // 拡張子からフォーマットを推定する（不明な場合はデフォルトのフォーマット）
+    pub fn from_path(path: &Path) -> Format {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("csv") => Format::Csv,
+            Some("jsonl") | Some("json") => Format::Jsonl,
+            #[cfg(feature = "sqlite")]
+            Some("sqlite") | Some("sqlite3") | Some("db") => Format::Sqlite,
+            _ => Format::default(),
+        }
+    }
+}
+
+// --formatのデフォルト（arrow featureがあればparquet、なければcsv）
+impl Default for Format {
+    #[cfg(feature = "arrow")]
+    fn default() -> Self {
+        Format::Parquet
+    }
+
+    #[cfg(not(feature = "arrow"))]
+    fn default() -> Self {
+        Format::Csv
+    }
+}
+
+// 出力レイアウト
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Layout {
+    /// Single file at --output
+    File,
+    /// Hive-style partitioned dataset under --output (platform=<name>/part-<n>.<ext>)
+    Hive,
+}
+
+// parquetの圧縮方式
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum Compression {
+    Uncompressed,
+    Snappy,
+    Lz4,
+    Zstd,
+}
+
+// parquetの統計情報の粒度
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum Statistics {
+    None,
+    Chunk,
+    Page,
+}
+
+// parquetの書き出し設定（csv / jsonlやarrow featureなしでは無視される）
+#[derive(clap::Args, Debug, Clone)]
+pub struct ParquetOptions {
+    /// Parquet compression codec
+    #[arg(long, value_enum, default_value_t = Compression::Zstd)]
+    compression: Compression,
+
+    /// Maximum number of rows per parquet row group
+    #[arg(long, default_value_t = 1024 * 1024)]
+    row_group_size: usize,
+
+    /// Level of parquet column statistics to write
+    #[arg(long, value_enum, default_value_t = Statistics::Chunk)]
+    statistics: Statistics,
+}
+
+// ハーネスの外（bufpool::stats_log）で書き出すときの設定（コマンドラインのデフォルトと同じ）
+impl Default for ParquetOptions {
+    fn default() -> Self {
+        ParquetOptions {
+            compression: Compression::Zstd,
+            row_group_size: 1024 * 1024,
+            statistics: Statistics::Chunk,
+        }
+    }
+}
+
+#[cfg(feature = "arrow")]
+impl ParquetOptions {
+    pub fn writer_properties(&self, metadata: &BTreeMap<String, String>) -> WriterProperties {
+        let compression = match self.compression {
+            Compression::Uncompressed => ParquetCompression::UNCOMPRESSED,
+            Compression::Snappy => ParquetCompression::SNAPPY,
+            Compression::Lz4 => ParquetCompression::LZ4_RAW,
+            Compression::Zstd => ParquetCompression::ZSTD(ZstdLevel::default()),
+        };
+        let statistics = match self.statistics {
+            Statistics::None => EnabledStatistics::None,
+            Statistics::Chunk => EnabledStatistics::Chunk,
+            Statistics::Page => EnabledStatistics::Page,
+        };
+        WriterProperties::builder()
+            .set_compression(compression)
+            .set_max_row_group_size(self.row_group_size)
+            .set_statistics_enabled(statistics)
+            .set_key_value_metadata(Some(
+                metadata
+                    .iter()
+                    .map(|(key, value)| KeyValue::new(key.clone(), value.clone()))
+                    .collect(),
+            ))
+            .build()
+    }
+}
+
+// Hiveレイアウトでディレクトリに切り出せる列（切り出した列はファイル内からは除く）
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum PartitionColumn {
+    Platform,
+    Allocator,
+    Pattern,
+}
+
+impl PartitionColumn {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PartitionColumn::Platform => "platform",
+            PartitionColumn::Allocator => "allocator",
+            PartitionColumn::Pattern => "pattern",
+        }
+    }
+
+    #[cfg(feature = "arrow")]
+    fn value<'a>(&self, result: &'a BenchResult) -> &'a str {
+        match self {
+            PartitionColumn::Platform => &result.platform,
+            PartitionColumn::Allocator => &result.allocator,
+            PartitionColumn::Pattern => &result.pattern,
+        }
+    }
+}
+
+#[cfg(feature = "arrow")]
+fn schema() -> Schema {
+    Schema::new(vec![
+        Field::new("platform", DataType::Utf8, false),
+        Field::new("allocator", DataType::Utf8, false),
+        Field::new("pattern", DataType::Utf8, false),
+        Field::new("size_bytes", DataType::UInt32, false),
+        Field::new("threads", DataType::UInt32, false),
+        Field::new("thread", DataType::UInt32, false),
+        Field::new("numa", DataType::Utf8, false),
+        Field::new("smt", DataType::Utf8, false),
+        Field::new("core", DataType::UInt32, true),
+        Field::new("core_type", DataType::Utf8, true),
+        Field::new("iteration", DataType::UInt32, false),
+        Field::new("total_ns", DataType::UInt64, false),
+        Field::new("latency_ns", DataType::UInt64, false),
+        Field::new("cell_id", DataType::Utf8, false),
+    ])
+}
+
+#[cfg(feature = "arrow")]
+fn to_record_batch(results: &[BenchResult]) -> Result<RecordBatch, Box<dyn std::error::Error>> {
+    let platforms: Vec<&str> = results.iter().map(|r| r.platform.as_str()).collect();
+    let allocators: Vec<&str> = results.iter().map(|r| r.allocator.as_str()).collect();
+    let patterns: Vec<&str> = results.iter().map(|r| r.pattern.as_str()).collect();
+    let sizes: Vec<u32> = results.iter().map(|r| r.size_bytes).collect();
+    let threads: Vec<u32> = results.iter().map(|r| r.threads).collect();
+    let thread: Vec<u32> = results.iter().map(|r| r.thread).collect();
+    let numa: Vec<&str> = results.iter().map(|r| r.numa.as_str()).collect();
+    let smt: Vec<&str> = results.iter().map(|r| r.smt.as_str()).collect();
+    let cores: Vec<Option<u32>> = results.iter().map(|r| r.core).collect();
+    let core_types: Vec<Option<&str>> = results.iter().map(|r| r.core_type.as_deref()).collect();
+    let iterations: Vec<u32> = results.iter().map(|r| r.iteration).collect();
+    let total: Vec<u64> = results.iter().map(|r| r.total_ns).collect();
+    let latency: Vec<u64> = results.iter().map(|r| r.latency_ns).collect();
+    let cell_ids: Vec<String> = results.iter().map(BenchResult::cell_id).collect();
+
+    let batch = RecordBatch::try_new(
+        Arc::new(schema()),
+        vec![
+            Arc::new(StringArray::from(platforms)) as ArrayRef,
+            Arc::new(StringArray::from(allocators)) as ArrayRef,
+            Arc::new(StringArray::from(patterns)) as ArrayRef,
+            Arc::new(UInt32Array::from(sizes)) as ArrayRef,
+            Arc::new(UInt32Array::from(threads)) as ArrayRef,
+            Arc::new(UInt32Array::from(thread)) as ArrayRef,
+            Arc::new(StringArray::from(numa)) as ArrayRef,
+            Arc::new(StringArray::from(smt)) as ArrayRef,
+            Arc::new(UInt32Array::from(cores)) as ArrayRef,
+            Arc::new(StringArray::from(core_types)) as ArrayRef,
+            Arc::new(UInt32Array::from(iterations)) as ArrayRef,
+            Arc::new(UInt64Array::from(total)) as ArrayRef,
+            Arc::new(UInt64Array::from(latency)) as ArrayRef,
+            Arc::new(StringArray::from(cell_ids)) as ArrayRef,
+        ],
+    )?;
+
+    Ok(batch)
+}
+
+// 結果の形式のバージョン（列を足したり意味を変えたりしたら上げ、upgradeに1つ前の版から揃える処理を足す）
+// 1: schema_versionを記録する前の形式（threads以降の列は書いた時期によってあったりなかったりする）
+// 2: cell_id列を追加し、schema_versionを記録するようにした
+pub const SCHEMA_VERSION: u32 = 2;
+
+// 結果の形式のバージョンを記録するparquetのメタデータのキー（sqliteではPRAGMA user_versionに記録する）
+pub const SCHEMA_VERSION_KEY: &str = "memalloc_bench.schema_version";
+
+// 計測を最後まで終えたかを記録するparquetのメタデータのキー（"true" / "false"。シグナルで止めたファイルは"false"）
+pub const COMPLETE_KEY: &str = "memalloc_bench.complete";
+
+// 結果をセル単位で逐次書き出すライター
+// File / Hiveレイアウトでは一時ファイルに書き、finishでリネームする
+// arrow featureなしではFileレイアウトのcsv / jsonlだけを書ける（parquetの設定とメタデータは使わない）
+pub struct ResultWriter {
+    sink: Sink,
+    written: usize,
+}
+
+enum Sink {
+    #[cfg(feature = "arrow")]
+    File(Box<FileWriter>),
+    #[cfg(feature = "arrow")]
+    Hive {
+        root: PathBuf,
+        format: Format,
+        partition_by: Vec<PartitionColumn>,
+        properties: Box<WriterProperties>,
+        writers: BTreeMap<PathBuf, FileWriter>,
+    },
+    #[cfg(not(feature = "arrow"))]
+    Rows(Box<rows::RowWriter>),
+    #[cfg(feature = "sqlite")]
+    Sqlite {
+        conn: rusqlite::Connection,
+        path: PathBuf,
+    },
+}
+
+impl ResultWriter {
+    pub fn create(
+        path: &Path,
+        layout: Layout,
+        format: Format,
+        partition_by: &[PartitionColumn],
+        options: &ParquetOptions,
+        metadata: &BTreeMap<String, String>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        #[cfg(not(feature = "arrow"))]
+        let _ = (partition_by, options, metadata);
+        #[cfg(feature = "arrow")]
+        let metadata = &{
+            let mut metadata = metadata.clone();
+            metadata.insert(SCHEMA_VERSION_KEY.to_string(), SCHEMA_VERSION.to_string());
+            metadata
+        };
+        let sink = match layout {
+            #[cfg(feature = "sqlite")]
+            Layout::File if matches!(format, Format::Sqlite) => {
+                create_parent_dir(path)?;
+                Sink::Sqlite {
+                    conn: sqlite::open(path)?,
+                    path: path.to_path_buf(),
+                }
+            }
+            #[cfg(feature = "arrow")]
+            Layout::File => {
+                create_parent_dir(path)?;
+                Sink::File(Box::new(FileWriter::create(
+                    path.to_path_buf(),
+                    format,
+                    Arc::new(schema()),
+                    &options.writer_properties(metadata),
+                )?))
+            }
+            #[cfg(feature = "arrow")]
+            Layout::Hive => Sink::Hive {
+                root: path.to_path_buf(),
+                format,
+                partition_by: partition_by.to_vec(),
+                properties: Box::new(options.writer_properties(metadata)),
+                writers: BTreeMap::new(),
+            },
+            #[cfg(not(feature = "arrow"))]
+            Layout::File => {
+                create_parent_dir(path)?;
+                Sink::Rows(Box::new(rows::RowWriter::create(
+                    path.to_path_buf(),
+                    format,
+                )?))
+            }
+            #[cfg(not(feature = "arrow"))]
+            Layout::Hive => return Err("--layout hive needs the arrow feature".into()),
+        };
+
+        Ok(ResultWriter { sink, written: 0 })
+    }
+
+    // 書き込んだ行数
+    pub fn written(&self) -> usize {
+        self.written
+    }
+
+    // 計測を最後まで終えたかをparquetのメタデータ（COMPLETE_KEY）に記録する（finishの前に呼ぶ）
+    // メタデータを持たないcsv / jsonl / sqliteでは何もせずfalseを返す
+    pub fn set_complete(&mut self, complete: bool) -> bool {
+        #[cfg(not(feature = "arrow"))]
+        let _ = complete;
+        match &mut self.sink {
+            #[cfg(feature = "arrow")]
+            Sink::File(writer) => writer.set_complete(complete),
+            #[cfg(feature = "arrow")]
+            Sink::Hive {
+                format, writers, ..
+            } => {
+                for writer in writers.values_mut() {
+                    writer.set_complete(complete);
+                }
+                matches!(format, Format::Parquet)
+            }
+            #[allow(unreachable_patterns)]
+            _ => false,
+        }
+    }
+
+    pub fn write(&mut self, results: &[BenchResult]) -> Result<(), Box<dyn std::error::Error>> {
+        if results.is_empty() {
+            return Ok(());
+        }
+
+        match &mut self.sink {
+            #[cfg(feature = "arrow")]
+            Sink::File(writer) => writer.write(&to_record_batch(results)?)?,
+            #[cfg(feature = "arrow")]
+            Sink::Hive {
+                root,
+                format,
+                partition_by,
+                properties,
+                writers,
+            } => {
+                let mut partitions: BTreeMap<PathBuf, Vec<u32>> = BTreeMap::new();
+                for (i, r) in results.iter().enumerate() {
+                    let mut dir = root.clone();
+                    for column in partition_by.iter() {
+                        dir.push(format!(
+                            "{}={}",
+                            column.name(),
+                            escape_partition_value(column.value(r))
+                        ));
+                    }
+                    partitions.entry(dir).or_default().push(i as u32);
+                }
+
+                // パーティション列はファイルに含めない
+                let full = to_record_batch(results)?;
+                let projection: Vec<usize> = (0..full.num_columns())
+                    .filter(|&i| {
+                        let name = full.schema().field(i).name().clone();
+                        !partition_by.iter().any(|c| c.name() == name)
+                    })
+                    .collect();
+                let batch = full.project(&projection)?;
+
+                for (dir, rows) in partitions {
+                    let writer = match writers.entry(dir) {
+                        Entry::Occupied(entry) => entry.into_mut(),
+                        Entry::Vacant(entry) => {
+                            std::fs::create_dir_all(entry.key())?;
+                            let path = next_part_path(entry.key(), *format)?;
+                            entry.insert(FileWriter::create(
+                                path,
+                                *format,
+                                batch.schema(),
+                                properties,
+                            )?)
+                        }
+                    };
+                    let indices = UInt32Array::from(rows);
+                    let columns = batch
+                        .columns()
+                        .iter()
+                        .map(|c| arrow::compute::take(c, &indices, None))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    writer.write(&RecordBatch::try_new(batch.schema(), columns)?)?;
+                }
+            }
+            #[cfg(not(feature = "arrow"))]
+            Sink::Rows(writer) => writer.write(results)?,
+            #[cfg(feature = "sqlite")]
+            Sink::Sqlite { conn, .. } => sqlite::insert_results(conn, results)?,
+        }
+
+        self.written += results.len();
+        Ok(())
+    }
+
+    // ここまでに書いた行を、途中で落ちても読めるファイルとして置く
+    // Fileレイアウトでは出力ファイルを未完了（COMPLETE_KEYがfalse）のまま置き換え、
+    // Hiveレイアウトでは開いているpartを閉じて、以降の行は新しいpartに書く（sqliteは書くたびにコミット済み）
+    pub fn checkpoint(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        match &mut self.sink {
+            #[cfg(feature = "arrow")]
+            Sink::File(writer) => {
+                writer.set_complete(false);
+                writer.checkpoint()?;
+            }
+            #[cfg(feature = "arrow")]
+            Sink::Hive { writers, .. } => {
+                for writer in std::mem::take(writers).into_values() {
+                    writer.finish()?;
+                }
+            }
+            #[cfg(not(feature = "arrow"))]
+            Sink::Rows(writer) => writer.checkpoint()?,
+            #[cfg(feature = "sqlite")]
+            Sink::Sqlite { .. } => {}
+        }
+        Ok(())
+    }
+
+    // 書き込みを完了し、書き出したファイルのパスを返す
+    pub fn finish(self) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+        match self.sink {
+            #[cfg(feature = "arrow")]
+            Sink::File(writer) => Ok(vec![writer.finish()?]),
+            #[cfg(feature = "arrow")]
+            Sink::Hive { writers, .. } => writers.into_values().map(|w| w.finish()).collect(),
+            #[cfg(not(feature = "arrow"))]
+            Sink::Rows(writer) => Ok(vec![writer.finish()?]),
+            #[cfg(feature = "sqlite")]
+            Sink::Sqlite { path, .. } => Ok(vec![path]),
+        }
+    }
+}
+
+// 1ファイル分のライター（memalloc-benchのbufpool::stats_logでも使う）
+#[cfg(feature = "arrow")]
+pub struct FileWriter {
+    encoder: Encoder,
+    path: PathBuf,
+    tmp_path: PathBuf,
+    // checkpointで次の区間を書く一時ファイル（tmp_pathと交互に使う）
+    spare_path: PathBuf,
+    // checkpointで閉じた区間（path.part-0001, ...）。finishで1つのファイルにまとめる
+    parts: Vec<PathBuf>,
+    // 最後のcheckpointから書いた行数
+    pending: usize,
+    complete: bool,
+    format: Format,
+    schema: SchemaRef,
+    properties: WriterProperties,
+}
+
+// ファイルごとに1つしか作らないので、バリアントのサイズ差は気にしない
+#[allow(clippy::large_enum_variant)]
+#[cfg(feature = "arrow")]
+enum Encoder {
+    Parquet(ArrowWriter<File>),
+    Csv(arrow::csv::Writer<BufWriter<File>>),
+    Jsonl(arrow::json::LineDelimitedWriter<BufWriter<File>>),
+}
+
+#[cfg(feature = "arrow")]
+impl Encoder {
+    fn create(
+        path: &Path,
+        format: Format,
+        schema: SchemaRef,
+        properties: &WriterProperties,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let file = File::create(path)?;
+        Ok(match format {
+            Format::Parquet => Encoder::Parquet(ArrowWriter::try_new(
+                file,
+                schema,
+                Some(properties.clone()),
+            )?),
+            Format::Csv => Encoder::Csv(arrow::csv::Writer::new(BufWriter::new(file))),
+            Format::Jsonl => {
+                Encoder::Jsonl(arrow::json::LineDelimitedWriter::new(BufWriter::new(file)))
+            }
+            #[cfg(feature = "sqlite")]
+            Format::Sqlite => return Err("sqlite output cannot be written as a file".into()),
+        })
+    }
+
+    fn write(&mut self, batch: &RecordBatch) -> Result<(), Box<dyn std::error::Error>> {
+        match self {
+            // --row-group-size 行たまるごとにrow groupとして書き出される
+            Encoder::Parquet(writer) => writer.write(batch)?,
+            Encoder::Csv(writer) => writer.write(batch)?,
+            Encoder::Jsonl(writer) => writer.write(batch)?,
+        }
+        Ok(())
+    }
+
+    fn set_complete(&mut self, complete: bool) -> bool {
+        let Encoder::Parquet(writer) = self else {
+            return false;
+        };
+        writer.append_key_value_metadata(KeyValue::new(
+            COMPLETE_KEY.to_string(),
+            complete.to_string(),
+        ));
+        true
+    }
+
+    fn close(self) -> Result<(), Box<dyn std::error::Error>> {
+        match self {
+            Encoder::Parquet(writer) => {
+                writer.close()?;
+            }
+            Encoder::Csv(writer) => writer.into_inner().flush()?,
+            Encoder::Jsonl(mut writer) => {
+                writer.finish()?;
+                writer.into_inner().flush()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// path に suffix を付けたパス
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+// checkpointでn番目に閉じた区間のパス（path.part-0001, ...）
+fn part_path(path: &Path, n: usize) -> PathBuf {
+    with_suffix(path, &format!(".part-{:04}", n))
+}
+
+// 前の実行が途中で落ちて残ったpathの区間を、番号順に並べる
+// 区間が残っていれば、それらが（pathより新しい）結果のすべてを持っている
+fn checkpoint_parts(path: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let (Some(dir), Some(name)) = (path.parent(), path.file_name().and_then(|n| n.to_str())) else {
+        return Ok(Vec::new());
+    };
+    let dir = if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    };
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let prefix = format!("{}.part-", name);
+    let mut parts = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_name = entry.file_name();
+        let Some(n) = file_name
+            .to_str()
+            .and_then(|f| f.strip_prefix(&prefix))
+            .and_then(|n| n.parse::<usize>().ok())
+        else {
+            continue;
+        };
+        parts.push((n, path.with_file_name(file_name)));
+    }
+    parts.sort();
+    Ok(parts.into_iter().map(|(_, part)| part).collect())
+}
+
+#[cfg(feature = "arrow")]
+impl FileWriter {
+    // 一時ファイルに書いてからリネームするので、書き込み途中で落ちても既存のファイルは壊れない
+    // 前の実行が落ちて区間が残っていれば、先にpathへまとめておく（--resumeで読めるように）
+    pub fn create(
+        path: PathBuf,
+        format: Format,
+        schema: SchemaRef,
+        properties: &WriterProperties,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let stale = checkpoint_parts(&path)?;
+        if !stale.is_empty() {
+            merge_parts(&path, &stale, format, &schema, properties, false)?;
+        }
+
+        let tmp_path = with_suffix(&path, ".tmp");
+        let spare_path = with_suffix(&path, ".tmp2");
+        let encoder = Encoder::create(&tmp_path, format, schema.clone(), properties)?;
+
+        Ok(FileWriter {
+            encoder,
+            path,
+            tmp_path,
+            spare_path,
+            parts: Vec::new(),
+            pending: 0,
+            complete: true,
+            format,
+            schema,
+            properties: properties.clone(),
+        })
+    }
+
+    pub fn write(&mut self, batch: &RecordBatch) -> Result<(), Box<dyn std::error::Error>> {
+        self.encoder.write(batch)?;
+        self.pending += batch.num_rows();
+        Ok(())
+    }
+
+    pub fn set_complete(&mut self, complete: bool) -> bool {
+        self.complete = complete;
+        self.encoder.set_complete(complete)
+    }
+
+    // 最後のcheckpointから書いた区間を閉じてpath.part-NNNNとして置き、次の区間を新しい一時ファイルに書く
+    // parquetはフッタを書くまで読めないので区間ごとに閉じる。前の区間は書き直さないので、1回の手間は区間の行数分で済む
+    // 落ちた後はpartを読めばここまでの結果がすべて揃う（read_resultsと次の実行のcreateが読む）
+    pub fn checkpoint(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.pending == 0 {
+            return Ok(());
+        }
+        let mut next = Encoder::create(
+            &self.spare_path,
+            self.format,
+            self.schema.clone(),
+            &self.properties,
+        )?;
+        next.set_complete(self.complete);
+        std::mem::replace(&mut self.encoder, next).close()?;
+        let part = part_path(&self.path, self.parts.len() + 1);
+        std::fs::rename(&self.tmp_path, &part)?;
+        self.parts.push(part);
+        std::mem::swap(&mut self.tmp_path, &mut self.spare_path);
+        self.pending = 0;
+        Ok(())
+    }
+
+    // checkpointしていなければ一時ファイルをそのままpathにし、していれば最後の区間もpartにしてから1つにまとめる
+    pub fn finish(mut self) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        self.encoder.close()?;
+        if self.parts.is_empty() {
+            std::fs::rename(&self.tmp_path, &self.path)?;
+            return Ok(self.path);
+        }
+        let part = part_path(&self.path, self.parts.len() + 1);
+        std::fs::rename(&self.tmp_path, &part)?;
+        self.parts.push(part);
+        merge_parts(
+            &self.path,
+            &self.parts,
+            self.format,
+            &self.schema,
+            &self.properties,
+            self.complete,
+        )?;
+        Ok(self.path)
+    }
+}
+
+// 区間を順に読んでpathに1つのファイルとして書き、区間を消す
+// pathを置き換えてから区間を消すので、途中で落ちても区間かpathのどちらかに結果がすべて残る
+#[cfg(feature = "arrow")]
+fn merge_parts(
+    path: &Path,
+    parts: &[PathBuf],
+    format: Format,
+    schema: &SchemaRef,
+    properties: &WriterProperties,
+    complete: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let merged = with_suffix(path, ".merge");
+    let mut encoder = Encoder::create(&merged, format, schema.clone(), properties)?;
+    encoder.set_complete(complete);
+    for part in parts {
+        for batch in read_batches(part, format, schema)? {
+            encoder.write(&batch?)?;
+        }
+    }
+    encoder.close()?;
+    std::fs::rename(&merged, path)?;
+    for part in parts {
+        std::fs::remove_file(part)?;
+    }
+    Ok(())
+}
+
+#[cfg(feature = "arrow")]
+type Batches = Box<dyn Iterator<Item = Result<RecordBatch, arrow::error::ArrowError>>>;
+
+// FileWriterが書いたファイルを、書いたときのスキーマでバッチとして読む
+#[cfg(feature = "arrow")]
+fn read_batches(
+    path: &Path,
+    format: Format,
+    schema: &SchemaRef,
+) -> Result<Batches, Box<dyn std::error::Error>> {
+    let file = File::open(path)?;
+    Ok(match format {
+        Format::Parquet => Box::new(ParquetRecordBatchReaderBuilder::try_new(file)?.build()?),
+        Format::Csv => Box::new(
+            arrow::csv::ReaderBuilder::new(schema.clone())
+                .with_header(true)
+                .build(file)?,
+        ),
+        Format::Jsonl => {
+            Box::new(arrow::json::ReaderBuilder::new(schema.clone()).build(BufReader::new(file))?)
+        }
+        #[cfg(feature = "sqlite")]
+        Format::Sqlite => return Err("sqlite output is never written as a file".into()),
+    })
+}
+
+pub fn create_parent_dir(path: &Path) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        if !parent.as_os_str().is_empty() {
+            std::fs::create_dir_all(parent)?;
+        }
+    }
+    Ok(())
+}
+
+// ディレクトリ内で未使用の part-<n>.<ext> のパスを返す
+#[cfg(feature = "arrow")]
+fn next_part_path(dir: &Path, format: Format) -> std::io::Result<PathBuf> {
+    let mut next = 0;
+    for entry in std::fs::read_dir(dir)? {
+        let name = entry?.file_name();
+        let Some(stem) = Path::new(&name).file_stem().and_then(|s| s.to_str()) else {
+            continue;
+        };
+        if let Some(n) = stem
+            .strip_prefix("part-")
+            .and_then(|n| n.parse::<usize>().ok())
+        {
+            next = next.max(n + 1);
+        }
+    }
+    Ok(dir.join(format!("part-{}.{}", next, format.extension())))
+}
+
+// パーティション値をディレクトリ名に使えるようにエスケープする
+#[cfg(feature = "arrow")]
+fn escape_partition_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '%' | '/' | '\\' | '=' => escaped.push_str(&format!("%{:02X}", c as u32)),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(feature = "arrow")]
+fn unescape_partition_value(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = value.get(i + 1..i + 3);
+        match hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+            Some(b) if bytes[i] == b'%' => {
+                decoded.push(b);
+                i += 3;
+            }
+            _ => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+// schema_version 1のファイルにはないことがある列（nullを許して読み、upgradeで補う）
+// cell_idは他の列から決まるので読まない
+#[cfg(feature = "arrow")]
+const OPTIONAL_COLUMNS: &[&str] = &[
+    "threads",
+    "thread",
+    "numa",
+    "smt",
+    "core",
+    "core_type",
+    "cell_id",
+];
+
+#[cfg(feature = "arrow")]
+fn column<'a, T: Array + 'static>(
+    batch: &'a RecordBatch,
+    name: &str,
+) -> Result<&'a T, Box<dyn std::error::Error>> {
+    batch
+        .column_by_name(name)
+        .and_then(|c| c.as_any().downcast_ref::<T>())
+        .ok_or_else(|| format!("column '{}' is missing or has an unexpected type", name).into())
+}
+
+// 文字列列の値（Hiveレイアウトではパーティションのパスから、古い結果ファイルではデフォルト値で補う）
+#[cfg(feature = "arrow")]
+enum StrColumn<'a> {
+    Array(&'a StringArray),
+    Constant(&'a str),
+}
+
+#[cfg(feature = "arrow")]
+impl StrColumn<'_> {
+    fn value(&self, i: usize) -> &str {
+        match self {
+            StrColumn::Array(array) => array.value(i),
+            StrColumn::Constant(value) => value,
+        }
+    }
+}
+
+#[cfg(feature = "arrow")]
+fn str_column<'a>(
+    batch: &'a RecordBatch,
+    partitions: &'a [(String, String)],
+    name: &str,
+) -> Result<StrColumn<'a>, Box<dyn std::error::Error>> {
+    match partitions.iter().find(|(key, _)| key == name) {
+        Some((_, value)) => Ok(StrColumn::Constant(value)),
+        None => column::<StringArray>(batch, name).map(StrColumn::Array),
+    }
+}
+
+#[cfg(feature = "arrow")]
+fn has_values(batch: &RecordBatch, name: &str) -> bool {
+    batch
+        .column_by_name(name)
+        .is_some_and(|c| c.null_count() < c.len())
+}
+
+// csv / jsonlはメタデータを持たないので、cell_id列があるかで版を決める
+#[cfg(feature = "arrow")]
+fn infer_schema_version(batch: &RecordBatch) -> u32 {
+    if has_values(batch, "cell_id") {
+        2
+    } else {
+        1
+    }
+}
+
+// schema_versionがversionのファイルから読んだバッチを、今の版（SCHEMA_VERSION）の列に揃える
+// パーティション列はファイルに含まれず、パスから補うので揃えない
+#[cfg(feature = "arrow")]
+fn upgrade(
+    batch: RecordBatch,
+    version: u32,
+    partitions: &[(String, String)],
+) -> Result<RecordBatch, Box<dyn std::error::Error>> {
+    let mut batch = batch;
+    if version < 2 {
+        // 1 -> 2: 版を記録する前に追加した列は、古いファイルにはない（jsonlではすべてnullになる）
+        let rows = batch.num_rows();
+        let defaults: [(&str, ArrayRef); 6] = [
+            ("threads", Arc::new(UInt32Array::from(vec![1; rows]))),
+            ("thread", Arc::new(UInt32Array::from(vec![0; rows]))),
+            ("numa", Arc::new(StringArray::from(vec!["none"; rows]))),
+            ("smt", Arc::new(StringArray::from(vec!["any"; rows]))),
+            (
+                "core",
+                arrow::array::new_null_array(&DataType::UInt32, rows),
+            ),
+            (
+                "core_type",
+                arrow::array::new_null_array(&DataType::Utf8, rows),
+            ),
+        ];
+        for (name, default) in defaults {
+            if partitions.iter().any(|(key, _)| key == name) || has_values(&batch, name) {
+                continue;
+            }
+            batch = with_column(&batch, name, default)?;
+        }
+    }
+    Ok(batch)
+}
+
+// nameの列をvaluesで置き換える（なければ末尾に足す）
+#[cfg(feature = "arrow")]
+fn with_column(
+    batch: &RecordBatch,
+    name: &str,
+    values: ArrayRef,
+) -> Result<RecordBatch, Box<dyn std::error::Error>> {
+    let schema = batch.schema();
+    let mut fields: Vec<Field> = Vec::with_capacity(schema.fields().len() + 1);
+    let mut columns: Vec<ArrayRef> = Vec::with_capacity(schema.fields().len() + 1);
+    for (field, column) in schema.fields().iter().zip(batch.columns()) {
+        if field.name() != name {
+            fields.push(field.as_ref().clone());
+            columns.push(column.clone());
+        }
+    }
+    fields.push(Field::new(name, values.data_type().clone(), true));
+    columns.push(values);
+    Ok(RecordBatch::try_new(
+        Arc::new(Schema::new(fields)),
+        columns,
+    )?)
+}
+
+// upgradeで今の版の列に揃えたバッチを読む
+#[cfg(feature = "arrow")]
+fn from_record_batch(
+    batch: &RecordBatch,
+    partitions: &[(String, String)],
+    results: &mut Vec<BenchResult>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let platforms = str_column(batch, partitions, "platform")?;
+    let allocators = str_column(batch, partitions, "allocator")?;
+    let patterns = str_column(batch, partitions, "pattern")?;
+    let sizes = column::<UInt32Array>(batch, "size_bytes")?;
+    let threads = column::<UInt32Array>(batch, "threads")?;
+    let thread = column::<UInt32Array>(batch, "thread")?;
+    let numa = str_column(batch, partitions, "numa")?;
+    let smt = str_column(batch, partitions, "smt")?;
+    let cores = column::<UInt32Array>(batch, "core")?;
+    let core_types = column::<StringArray>(batch, "core_type")?;
+    let iterations = column::<UInt32Array>(batch, "iteration")?;
+    let total = column::<UInt64Array>(batch, "total_ns")?;
+    let latency = column::<UInt64Array>(batch, "latency_ns")?;
+
+    for i in 0..batch.num_rows() {
+        results.push(BenchResult {
+            platform: platforms.value(i).to_string(),
+            allocator: allocators.value(i).to_string(),
+            pattern: patterns.value(i).to_string(),
+            size_bytes: sizes.value(i),
+            threads: threads.value(i),
+            thread: thread.value(i),
+            numa: numa.value(i).to_string(),
+            smt: smt.value(i).to_string(),
+            core: cores.is_valid(i).then(|| cores.value(i)),
+            core_type: core_types
+                .is_valid(i)
+                .then(|| core_types.value(i).to_string()),
+            iteration: iterations.value(i),
+            total_ns: total.value(i),
+            latency_ns: latency.value(i),
+        });
+    }
+
+    Ok(())
+}
+
+// 既存の結果を読み込む（--resume, compare用）
+// ディレクトリを渡した場合はHive形式のデータセットとして配下のファイルをすべて読む
+pub fn read_results(
+    path: &Path,
+    format: Format,
+) -> Result<Vec<BenchResult>, Box<dyn std::error::Error>> {
+    read_results_with_version(path, format).map(|(results, _)| results)
+}
+
+// read_resultsと同じだが、読んだファイルのうち最も古いschema_versionも返す
+// 古い版のファイルは今の版（SCHEMA_VERSION）に揃えて読み、新しい版のファイルはエラーにする
+pub fn read_results_with_version(
+    path: &Path,
+    format: Format,
+) -> Result<(Vec<BenchResult>, u32), Box<dyn std::error::Error>> {
+    #[cfg(feature = "sqlite")]
+    if matches!(format, Format::Sqlite) && !path.is_dir() {
+        return sqlite::select_results(path);
+    }
+
+    read_arrow(path, format)
+}
+
+// parquet / csv / jsonlのファイルか、それらを並べたHive形式のデータセットを読む
+#[cfg(feature = "arrow")]
+fn read_arrow(
+    path: &Path,
+    format: Format,
+) -> Result<(Vec<BenchResult>, u32), Box<dyn std::error::Error>> {
+    let mut results = Vec::new();
+    let version = if path.is_dir() {
+        read_dataset(path, &mut Vec::new(), &mut results)?
+    } else {
+        // checkpointの区間が残っていれば実行が途中で落ちている（pathは前の実行の古い内容）
+        let parts = checkpoint_parts(path)?;
+        if parts.is_empty() {
+            read_file(path, format, &[], &mut results)?
+        } else {
+            let mut version = SCHEMA_VERSION;
+            for part in parts {
+                version = version.min(read_file(&part, format, &[], &mut results)?);
+            }
+            version
+        }
+    };
+    Ok((results, version))
+}
+
+#[cfg(not(feature = "arrow"))]
+fn read_arrow(
+    _path: &Path,
+    format: Format,
+) -> Result<(Vec<BenchResult>, u32), Box<dyn std::error::Error>> {
+    Err(format!(
+        "reading {} results needs the arrow feature",
+        format.extension()
+    )
+    .into())
+}
+
+#[cfg(feature = "arrow")]
+fn read_dataset(
+    dir: &Path,
+    partitions: &mut Vec<(String, String)>,
+    results: &mut Vec<BenchResult>,
+) -> Result<u32, Box<dyn std::error::Error>> {
+    let mut version = SCHEMA_VERSION;
+    let mut entries = std::fs::read_dir(dir)?
+        .map(|e| e.map(|e| e.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    entries.sort();
+
+    for path in entries {
+        let name = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or_default();
+        if path.is_dir() {
+            let Some((key, value)) = name.split_once('=') else {
+                continue;
+            };
+            partitions.push((key.to_string(), unescape_partition_value(value)));
+            version = version.min(read_dataset(&path, partitions, results)?);
+            partitions.pop();
+        } else if !name.starts_with('.') {
+            let format = Format::from_path(&path);
+            if format.extension() == path.extension().and_then(|e| e.to_str()).unwrap_or("") {
+                version = version.min(read_file(&path, format, partitions, results)?);
+            }
+        }
+    }
+
+    Ok(version)
+}
+
+#[cfg(feature = "arrow")]
+fn read_file(
+    path: &Path,
+    format: Format,
+    partitions: &[(String, String)],
+    results: &mut Vec<BenchResult>,
+) -> Result<u32, Box<dyn std::error::Error>> {
+    let mut file = File::open(path)?;
+    // パーティション列はファイルに含まれない
+    // 古い版のファイルにはない列は、nullを許して読んでからupgradeで補う
+    let full = schema();
+    let fields: Vec<Field> = full
+        .fields()
+        .iter()
+        .filter(|f| !partitions.iter().any(|(key, _)| key == f.name()))
+        .map(|f| {
+            let optional = OPTIONAL_COLUMNS.contains(&f.name().as_str());
+            f.as_ref().clone().with_nullable(optional)
+        })
+        .collect();
+
+    // 揃えた版のうち最も古いもの（空のファイルは今の版とみなす）
+    let mut oldest = SCHEMA_VERSION;
+    let mut read = |batch: RecordBatch, version: u32| {
+        if version > SCHEMA_VERSION {
+            return Err(format!(
+                "{} has schema version {}, but this build reads up to {} (update memalloc-bench)",
+                path.display(),
+                version,
+                SCHEMA_VERSION
+            )
+            .into());
+        }
+        oldest = oldest.min(version);
+        from_record_batch(&upgrade(batch, version, partitions)?, partitions, results)
+    };
+
+    match format {
+        Format::Parquet => {
+            let builder = ParquetRecordBatchReaderBuilder::try_new(file)?;
+            // schema_versionを記録していないファイルは版1
+            let version = builder
+                .metadata()
+                .file_metadata()
+                .key_value_metadata()
+                .and_then(|kv| kv.iter().find(|kv| kv.key == SCHEMA_VERSION_KEY))
+                .and_then(|kv| kv.value.as_deref())
+                .map(|v| v.parse::<u32>())
+                .transpose()
+                .map_err(|e| {
+                    format!(
+                        "invalid {} in {}: {}",
+                        SCHEMA_VERSION_KEY,
+                        path.display(),
+                        e
+                    )
+                })?
+                .unwrap_or(1);
+            for batch in builder.build()? {
+                read(batch?, version)?;
+            }
+        }
+        Format::Csv => {
+            // CSVは列の位置で読むので、ヘッダにある列だけをその順序で並べる
+            let mut header = String::new();
+            BufReader::new(&mut file).read_line(&mut header)?;
+            file.seek(SeekFrom::Start(0))?;
+            let columns: Vec<&str> = header.trim_end().split(',').collect();
+            let fields: Vec<Field> = columns
+                .iter()
+                .map(|name| {
+                    fields
+                        .iter()
+                        .find(|f| f.name() == name)
+                        .cloned()
+                        .ok_or_else(|| format!("unknown column '{}' in {}", name, path.display()))
+                })
+                .collect::<Result<_, _>>()?;
+            let reader = arrow::csv::ReaderBuilder::new(Arc::new(Schema::new(fields)))
+                .with_header(true)
+                .build(file)?;
+            for batch in reader {
+                let batch = batch?;
+                let version = infer_schema_version(&batch);
+                read(batch, version)?;
+            }
+        }
+        Format::Jsonl => {
+            let reader = arrow::json::ReaderBuilder::new(Arc::new(Schema::new(fields)))
+                .build(BufReader::new(file))?;
+            for batch in reader {
+                let batch = batch?;
+                let version = infer_schema_version(&batch);
+                read(batch, version)?;
+            }
+        }
+        #[cfg(feature = "sqlite")]
+        Format::Sqlite => return Err("sqlite databases cannot be part of a dataset".into()),
+    }
+
+    Ok(oldest)
+}
+
+#[cfg(feature = "sqlite")]
+mod sqlite {
+    use super::SCHEMA_VERSION;
+    use crate::BenchResult;
+    use rusqlite::{params, Connection};
+    use std::path::Path;
+
+    pub fn open(path: &Path) -> Result<Connection, Box<dyn std::error::Error>> {
+        let conn = Connection::open(path)?;
+        upgrade(&conn, path)?;
+        Ok(conn)
+    }
+
+    // テーブルを今の版（SCHEMA_VERSION）に揃え、揃える前の版を返す
+    // 版はPRAGMA user_versionに記録する（記録する前に作ったデータベースは0なので版1とみなす）
+    fn upgrade(conn: &Connection, path: &Path) -> Result<u32, Box<dyn std::error::Error>> {
+        let version = match conn.query_row("PRAGMA user_version", [], |row| row.get::<_, u32>(0))? {
+            0 => 1,
+            version => version,
+        };
+        if version > SCHEMA_VERSION {
+            return Err(format!(
+                "{} has schema version {}, but this build reads up to {} (update memalloc-bench)",
+                path.display(),
+                version,
+                SCHEMA_VERSION
+            )
+            .into());
+        }
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS results (
+                platform   TEXT    NOT NULL,
+                allocator  TEXT    NOT NULL,
+                pattern    TEXT    NOT NULL,
+                size_bytes INTEGER NOT NULL,
+                threads    INTEGER NOT NULL DEFAULT 1,
+                thread     INTEGER NOT NULL DEFAULT 0,
+                numa       TEXT    NOT NULL DEFAULT 'none',
+                smt        TEXT    NOT NULL DEFAULT 'any',
+                core       INTEGER,
+                core_type  TEXT,
+                iteration  INTEGER NOT NULL,
+                total_ns   INTEGER NOT NULL,
+                latency_ns INTEGER NOT NULL,
+                cell_id    TEXT
+            );",
+        )?;
+
+        // 後から追加した列がない古いデータベースには列を追加する
+        let columns = conn
+            .prepare("SELECT name FROM pragma_table_info('results')")?
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        for (name, definition) in [
+            ("threads", "INTEGER NOT NULL DEFAULT 1"),
+            ("thread", "INTEGER NOT NULL DEFAULT 0"),
+            ("numa", "TEXT NOT NULL DEFAULT 'none'"),
+            ("smt", "TEXT NOT NULL DEFAULT 'any'"),
+            ("core", "INTEGER"),
+            ("core_type", "TEXT"),
+            ("cell_id", "TEXT"),
+        ] {
+            if !columns.iter().any(|c| c == name) {
+                conn.execute_batch(&format!(
+                    "DROP INDEX IF EXISTS results_cell;
+                    ALTER TABLE results ADD COLUMN {} {};",
+                    name, definition
+                ))?;
+            }
+        }
+        conn.execute_batch(
+            "CREATE INDEX IF NOT EXISTS results_cell
+                ON results (platform, allocator, pattern, size_bytes, threads, numa, smt);",
+        )?;
+        conn.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+        Ok(version)
+    }
+
+    pub fn insert_results(
+        conn: &mut Connection,
+        results: &[BenchResult],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let tx = conn.transaction()?;
+        {
+            let mut stmt = tx.prepare(
+                "INSERT INTO results
+                    (platform, allocator, pattern, size_bytes, threads, thread, numa, smt,
+                     core, core_type, iteration, total_ns, latency_ns, cell_id)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+            )?;
+            for r in results {
+                // SQLiteのINTEGERはi64
+                stmt.execute(params![
+                    r.platform,
+                    r.allocator,
+                    r.pattern,
+                    r.size_bytes,
+                    r.threads,
+                    r.thread,
+                    r.numa,
+                    r.smt,
+                    r.core,
+                    r.core_type,
+                    r.iteration,
+                    r.total_ns as i64,
+                    r.latency_ns as i64,
+                    r.cell_id(),
+                ])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn select_results(
+        path: &Path,
+    ) -> Result<(Vec<BenchResult>, u32), Box<dyn std::error::Error>> {
+        let conn = Connection::open(path)?;
+        let version = upgrade(&conn, path)?;
+        let mut stmt = conn.prepare(
+            "SELECT platform, allocator, pattern, size_bytes, threads, thread, numa, smt,
+                    core, core_type, iteration, total_ns, latency_ns
+             FROM results",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(BenchResult {
+                platform: row.get(0)?,
+                allocator: row.get(1)?,
+                pattern: row.get(2)?,
+                size_bytes: row.get(3)?,
+                threads: row.get(4)?,
+                thread: row.get(5)?,
+                numa: row.get(6)?,
+                smt: row.get(7)?,
+                core: row.get(8)?,
+                core_type: row.get(9)?,
+                iteration: row.get(10)?,
+                total_ns: row.get::<_, i64>(11)? as u64,
+                latency_ns: row.get::<_, i64>(12)? as u64,
+            })
+        })?;
+        Ok((rows.collect::<Result<_, _>>()?, version))
+    }
+}
+
+// arrow featureなしでcsv / jsonlを書くライター
+// 列の並びと値の書き方はarrowのライターに合わせるので、arrowでビルドしたmemalloc-reportでそのまま読める
+#[cfg(not(feature = "arrow"))]
+mod rows {
+    use super::Format;
+    use crate::BenchResult;
+    use std::fs::File;
+    use std::io::{BufRead, BufReader, BufWriter, Write};
+    use std::path::{Path, PathBuf};
+
+    const COLUMNS: &[&str] = &[
+        "platform",
+        "allocator",
+        "pattern",
+        "size_bytes",
+        "threads",
+        "thread",
+        "numa",
+        "smt",
+        "core",
+        "core_type",
+        "iteration",
+        "total_ns",
+        "latency_ns",
+        "cell_id",
+    ];
+
+    // 1行分の値（nullはNone）
+    enum Value<'a> {
+        Str(&'a str),
+        Int(u64),
+    }
+
+    fn values<'a>(r: &'a BenchResult, cell_id: &'a str) -> [Option<Value<'a>>; 14] {
+        [
+            Some(Value::Str(&r.platform)),
+            Some(Value::Str(&r.allocator)),
+            Some(Value::Str(&r.pattern)),
+            Some(Value::Int(r.size_bytes as u64)),
+            Some(Value::Int(r.threads as u64)),
+            Some(Value::Int(r.thread as u64)),
+            Some(Value::Str(&r.numa)),
+            Some(Value::Str(&r.smt)),
+            r.core.map(|c| Value::Int(c as u64)),
+            r.core_type.as_deref().map(Value::Str),
+            Some(Value::Int(r.iteration as u64)),
+            Some(Value::Int(r.total_ns)),
+            Some(Value::Int(r.latency_ns)),
+            Some(Value::Str(cell_id)),
+        ]
+    }
+
+    pub struct RowWriter {
+        out: BufWriter<File>,
+        format: Format,
+        path: PathBuf,
+        tmp_path: PathBuf,
+        // checkpointで次の区間を書く一時ファイル（tmp_pathと交互に使う）
+        spare_path: PathBuf,
+        // checkpointで閉じた区間（path.part-0001, ...）
+        parts: Vec<PathBuf>,
+        // 最後のcheckpointから書いた行数
+        pending: usize,
+    }
+
+    impl RowWriter {
+        // FileWriterと同じく一時ファイルに書いてからリネームし、前の実行が残した区間は先にpathへまとめる
+        pub fn create(path: PathBuf, format: Format) -> Result<Self, Box<dyn std::error::Error>> {
+            #[cfg(feature = "sqlite")]
+            if let Format::Sqlite = format {
+                return Err("sqlite output cannot be written as a file".into());
+            }
+            let stale = super::checkpoint_parts(&path)?;
+            if !stale.is_empty() {
+                merge_parts(&path, &stale, format)?;
+            }
+            let tmp_path = super::with_suffix(&path, ".tmp");
+            let spare_path = super::with_suffix(&path, ".tmp2");
+            Ok(RowWriter {
+                out: open(&tmp_path, format)?,
+                format,
+                path,
+                tmp_path,
+                spare_path,
+                parts: Vec::new(),
+                pending: 0,
+            })
+        }
+
+        pub fn write(&mut self, results: &[BenchResult]) -> std::io::Result<()> {
+            for r in results {
+                match self.format {
+                    Format::Jsonl => write_json(&mut self.out, r)?,
+                    _ => write_csv(&mut self.out, r)?,
+                }
+            }
+            self.pending += results.len();
+            Ok(())
+        }
+
+        // FileWriterと同じく、最後のcheckpointから書いた区間をpath.part-NNNNとして置く
+        pub fn checkpoint(&mut self) -> std::io::Result<()> {
+            if self.pending == 0 {
+                return Ok(());
+            }
+            let next = open(&self.spare_path, self.format)?;
+            std::mem::replace(&mut self.out, next).flush()?;
+            let part = super::part_path(&self.path, self.parts.len() + 1);
+            std::fs::rename(&self.tmp_path, &part)?;
+            self.parts.push(part);
+            std::mem::swap(&mut self.tmp_path, &mut self.spare_path);
+            self.pending = 0;
+            Ok(())
+        }
+
+        pub fn finish(mut self) -> std::io::Result<PathBuf> {
+            self.out.flush()?;
+            drop(self.out);
+            if self.parts.is_empty() {
+                std::fs::rename(&self.tmp_path, &self.path)?;
+                return Ok(self.path);
+            }
+            let part = super::part_path(&self.path, self.parts.len() + 1);
+            std::fs::rename(&self.tmp_path, &part)?;
+            self.parts.push(part);
+            merge_parts(&self.path, &self.parts, self.format)?;
+            Ok(self.path)
+        }
+    }
+
+    // 区間ごとに単独で読めるよう、csvはどの区間にもヘッダを書く
+    fn open(path: &Path, format: Format) -> std::io::Result<BufWriter<File>> {
+        let mut out = BufWriter::new(File::create(path)?);
+        if let Format::Csv = format {
+            writeln!(out, "{}", COLUMNS.join(","))?;
+        }
+        Ok(out)
+    }
+
+    // 区間をつなげてpathに置いてから区間を消す（csvのヘッダは最初の区間のものだけ残す）
+    fn merge_parts(path: &Path, parts: &[PathBuf], format: Format) -> std::io::Result<()> {
+        let merged = super::with_suffix(path, ".merge");
+        let mut out = BufWriter::new(File::create(&merged)?);
+        for (i, part) in parts.iter().enumerate() {
+            let mut input = BufReader::new(File::open(part)?);
+            if i > 0 && matches!(format, Format::Csv) {
+                input.read_line(&mut String::new())?;
+            }
+            std::io::copy(&mut input, &mut out)?;
+        }
+        out.flush()?;
+        drop(out);
+        std::fs::rename(&merged, path)?;
+        for part in parts {
+            std::fs::remove_file(part)?;
+        }
+        Ok(())
+    }
+
+    // nullは空欄、区切り文字や引用符を含む文字列だけを引用符で囲む
+    fn write_csv(out: &mut impl Write, r: &BenchResult) -> std::io::Result<()> {
+        for (i, value) in values(r, &r.cell_id()).into_iter().enumerate() {
+            if i > 0 {
+                out.write_all(b",")?;
+            }
+            match value {
+                Some(Value::Str(s)) if s.contains([',', '"', '\n', '\r']) => {
+                    write!(out, "\"{}\"", s.replace('"', "\"\""))?
+                }
+                Some(Value::Str(s)) => out.write_all(s.as_bytes())?,
+                Some(Value::Int(n)) => write!(out, "{}", n)?,
+                None => {}
+            }
+        }
+        out.write_all(b"\n")
+    }
+
+    // nullの列は書かない
+    fn write_json(out: &mut impl Write, r: &BenchResult) -> std::io::Result<()> {
+        out.write_all(b"{")?;
+        let mut first = true;
+        for (name, value) in COLUMNS.iter().zip(values(r, &r.cell_id())) {
+            let Some(value) = value else {
+                continue;
+            };
+            if !first {
+                out.write_all(b",")?;
+            }
+            first = false;
+            write!(out, "\"{}\":", name)?;
+            match value {
+                Value::Str(s) => write_json_str(out, s)?,
+                Value::Int(n) => write!(out, "{}", n)?,
+            }
+        }
+        out.write_all(b"}\n")
+    }
+
+    fn write_json_str(out: &mut impl Write, s: &str) -> std::io::Result<()> {
+        out.write_all(b"\"")?;
+        for c in s.chars() {
+            match c {
+                '"' => out.write_all(b"\\\"")?,
+                '\\' => out.write_all(b"\\\\")?,
+                '\n' => out.write_all(b"\\n")?,
+                '\r' => out.write_all(b"\\r")?,
+                '\t' => out.write_all(b"\\t")?,
+                c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32)?,
+                c => write!(out, "{}", c)?,
+            }
+        }
+        out.write_all(b"\"")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // テストごとの空の一時ディレクトリ
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("memalloc-results-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn result(allocator: &str, iteration: u32) -> BenchResult {
+        BenchResult {
+            platform: "test".to_string(),
+            allocator: allocator.to_string(),
+            pattern: "lifo".to_string(),
+            size_bytes: 64,
+            threads: 1,
+            thread: 0,
+            numa: "none".to_string(),
+            smt: "any".to_string(),
+            core: None,
+            core_type: None,
+            iteration,
+            total_ns: 1000 + iteration as u64,
+            latency_ns: 10,
+        }
+    }
+
+    fn create(path: &Path, format: Format) -> ResultWriter {
+        ResultWriter::create(
+            path,
+            Layout::File,
+            format,
+            &[],
+            &ParquetOptions::default(),
+            &BTreeMap::new(),
+        )
+        .unwrap()
+    }
+
+    // 2回checkpointしてからfinishせずに落ちた（最後のcheckpoint以降のc/0は失われる）
+    fn crash(path: &Path, format: Format) {
+        let mut writer = create(path, format);
+        writer.write(&[result("a", 0), result("a", 1)]).unwrap();
+        writer.checkpoint().unwrap();
+        writer.write(&[result("b", 0)]).unwrap();
+        writer.checkpoint().unwrap();
+        writer.write(&[result("c", 0)]).unwrap();
+        drop(writer);
+        assert_eq!(checkpoint_parts(path).unwrap().len(), 2);
+    }
+
+    #[cfg(feature = "arrow")]
+    const FORMATS: [Format; 3] = [Format::Parquet, Format::Csv, Format::Jsonl];
+
+    #[cfg(feature = "arrow")]
+    fn keys(results: &[BenchResult]) -> Vec<(String, u32)> {
+        results
+            .iter()
+            .map(|r| (r.allocator.clone(), r.iteration))
+            .collect()
+    }
+
+    #[cfg(feature = "arrow")]
+    fn expected(allocators: &[(&str, u32)]) -> Vec<(String, u32)> {
+        allocators
+            .iter()
+            .map(|&(a, i)| (a.to_string(), i))
+            .collect()
+    }
+
+    // 落ちた後はcheckpointの区間から、それまでの行がすべて読める
+    #[cfg(feature = "arrow")]
+    #[test]
+    fn read_checkpoint_parts_after_crash() {
+        let dir = temp_dir("crash");
+        for format in FORMATS {
+            let path = dir.join(format!("out.{}", format.extension()));
+            crash(&path, format);
+            let results = read_results(&path, format).unwrap();
+            assert_eq!(
+                keys(&results),
+                expected(&[("a", 0), ("a", 1), ("b", 0)]),
+                "{:?}",
+                format
+            );
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // --resumeと同じく、次の実行はcheckpointの区間を1つのファイルにまとめてから読み、続きを書く
+    #[cfg(feature = "arrow")]
+    #[test]
+    fn resume_merges_checkpoint_parts() {
+        let dir = temp_dir("resume");
+        for format in FORMATS {
+            let path = dir.join(format!("out.{}", format.extension()));
+            crash(&path, format);
+
+            let mut writer = create(&path, format);
+            assert!(checkpoint_parts(&path).unwrap().is_empty());
+            let existing = read_results(&path, format).unwrap();
+            assert_eq!(keys(&existing), expected(&[("a", 0), ("a", 1), ("b", 0)]));
+            writer.write(&existing).unwrap();
+            writer.write(&[result("c", 0)]).unwrap();
+            writer.checkpoint().unwrap();
+            writer.set_complete(true);
+            writer.finish().unwrap();
+
+            assert!(checkpoint_parts(&path).unwrap().is_empty());
+            let results = read_results(&path, format).unwrap();
+            assert_eq!(
+                keys(&results),
+                expected(&[("a", 0), ("a", 1), ("b", 0), ("c", 0)]),
+                "{:?}",
+                format
+            );
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // schema_versionを記録する前（版1）の、threads以降の列とcell_idがないバッチ
+    #[cfg(feature = "arrow")]
+    fn v1_batch() -> RecordBatch {
+        let string = |name| Field::new(name, DataType::Utf8, false);
+        let schema = Schema::new(vec![
+            string("platform"),
+            string("allocator"),
+            string("pattern"),
+            Field::new("size_bytes", DataType::UInt32, false),
+            Field::new("iteration", DataType::UInt32, false),
+            Field::new("total_ns", DataType::UInt64, false),
+            Field::new("latency_ns", DataType::UInt64, false),
+        ]);
+        RecordBatch::try_new(
+            Arc::new(schema),
+            vec![
+                Arc::new(StringArray::from(vec!["old"; 2])),
+                Arc::new(StringArray::from(vec!["box"; 2])),
+                Arc::new(StringArray::from(vec!["lifo"; 2])),
+                Arc::new(UInt32Array::from(vec![64; 2])),
+                Arc::new(UInt32Array::from(vec![0, 1])),
+                Arc::new(UInt64Array::from(vec![1000, 1001])),
+                Arc::new(UInt64Array::from(vec![10, 11])),
+            ],
+        )
+        .unwrap()
+    }
+
+    // 版1のファイルは版1として報告し、ない列をデフォルト値で補って読む
+    #[cfg(feature = "arrow")]
+    #[test]
+    fn read_v1_file_with_defaults() {
+        let dir = temp_dir("v1");
+        let batch = v1_batch();
+        for format in FORMATS {
+            let path = dir.join(format!("v1.{}", format.extension()));
+            let file = File::create(&path).unwrap();
+            match format {
+                Format::Parquet => {
+                    let mut writer = ArrowWriter::try_new(file, batch.schema(), None).unwrap();
+                    writer.write(&batch).unwrap();
+                    writer.close().unwrap();
+                }
+                Format::Csv => arrow::csv::Writer::new(file).write(&batch).unwrap(),
+                _ => arrow::json::LineDelimitedWriter::new(file)
+                    .write(&batch)
+                    .unwrap(),
+            }
+
+            let (results, version) = read_results_with_version(&path, format).unwrap();
+            assert_eq!(version, 1, "{:?}", format);
+            assert_eq!(results.len(), 2);
+            for (i, r) in results.iter().enumerate() {
+                assert_eq!((r.allocator.as_str(), r.iteration), ("box", i as u32));
+                assert_eq!((r.threads, r.thread), (1, 0));
+                assert_eq!((r.numa.as_str(), r.smt.as_str()), ("none", "any"));
+                assert_eq!((r.core, r.core_type.as_deref()), (None, None));
+                assert_eq!(r.cell_id(), "box/lifo/64/1/none/any");
+            }
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // arrow featureなしのライターも、次の実行で区間を1つのファイルにまとめる（csvのヘッダは1行だけ）
+    #[cfg(not(feature = "arrow"))]
+    #[test]
+    fn row_writer_merges_checkpoint_parts() {
+        let dir = temp_dir("rows");
+        for format in [Format::Csv, Format::Jsonl] {
+            let path = dir.join(format!("out.{}", format.extension()));
+            crash(&path, format);
+            let writer = create(&path, format);
+            assert!(checkpoint_parts(&path).unwrap().is_empty());
+            let text = std::fs::read_to_string(&path).unwrap();
+            let header = usize::from(matches!(format, Format::Csv));
+            assert_eq!(text.lines().count(), header + 3, "{:?}", format);
+            assert_eq!(
+                text.lines().filter(|l| l.starts_with("platform,")).count(),
+                header
+            );
+            writer.finish().unwrap();
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
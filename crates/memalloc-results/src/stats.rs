@@ -0,0 +1,280 @@
+use crate::BenchResult;
+use std::collections::BTreeMap;
+
+// 信頼区間の幅を決める標準正規分布の分位点（95%）
+const Z_95: f64 = 1.959964;
+
+// (allocator, pattern, size_bytes, threads, numa, smt)
+pub type CellKey = (String, String, u32, u32, String, String);
+
+// セルのキーに対応するcell_id
+pub fn cell_id_of(key: &CellKey) -> String {
+    crate::cell_id(&key.0, &key.1, key.2, key.3, &key.4, &key.5)
+}
+
+// セルごとのtotal_nsを昇順に並べたもの
+pub fn group_by_cell<'a>(
+    results: impl IntoIterator<Item = &'a BenchResult>,
+) -> BTreeMap<CellKey, Vec<u64>> {
+    group_by_cell_with(results, |r| r.total_ns)
+}
+
+// セルごとに指定した値を昇順に並べたもの
+pub fn group_by_cell_with<'a>(
+    results: impl IntoIterator<Item = &'a BenchResult>,
+    value: impl Fn(&BenchResult) -> u64,
+) -> BTreeMap<CellKey, Vec<u64>> {
+    let mut cells: BTreeMap<CellKey, Vec<u64>> = BTreeMap::new();
+    for r in results {
+        cells
+            .entry((
+                r.allocator.clone(),
+                r.pattern.clone(),
+                r.size_bytes,
+                r.threads,
+                r.numa.clone(),
+                r.smt.clone(),
+            ))
+            .or_default()
+            .push(value(r));
+    }
+    for samples in cells.values_mut() {
+        samples.sort_unstable();
+    }
+    cells
+}
+
+// セルごとのtotal_nsの要約
+pub fn summarize<'a>(
+    results: impl IntoIterator<Item = &'a BenchResult>,
+) -> BTreeMap<CellKey, Summary> {
+    group_by_cell(results)
+        .into_iter()
+        .map(|(key, samples)| (key, Summary::of(&samples)))
+        .collect()
+}
+
+// ソート済みサンプルの分位点（線形補間）
+pub fn quantile(sorted: &[u64], q: f64) -> f64 {
+    let pos = (sorted.len() - 1) as f64 * q;
+    let lo = pos.floor() as usize;
+    let hi = pos.ceil() as usize;
+    let frac = pos - lo as f64;
+    sorted[lo] as f64 * (1.0 - frac) + sorted[hi] as f64 * frac
+}
+
+// 1セル分のサンプルの要約統計量（単位はサンプルと同じ）
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Summary {
+    pub count: usize,
+    pub mean: f64,
+    pub stddev: f64, // 標本標準偏差（n-1で割る）
+    pub min: f64,
+    pub p25: f64,
+    pub median: f64,
+    pub p75: f64,
+    pub p99: f64,
+    pub max: f64,
+    pub mean_ci: (f64, f64),   // 平均の95%信頼区間（正規近似）
+    pub median_ci: (f64, f64), // 中央値の95%信頼区間（順序統計量から求めるので分布を仮定しない）
+}
+
+impl Summary {
+    // ソート済みのサンプル（1つ以上）から求める
+    pub fn of(sorted: &[u64]) -> Summary {
+        assert!(!sorted.is_empty(), "cannot summarize an empty sample");
+        let n = sorted.len() as f64;
+        let mean = sorted.iter().map(|&v| v as f64).sum::<f64>() / n;
+        let variance = if sorted.len() > 1 {
+            sorted
+                .iter()
+                .map(|&v| (v as f64 - mean).powi(2))
+                .sum::<f64>()
+                / (n - 1.0)
+        } else {
+            0.0
+        };
+        let stddev = variance.sqrt();
+        let half_width = Z_95 * stddev / n.sqrt();
+
+        Summary {
+            count: sorted.len(),
+            mean,
+            stddev,
+            min: sorted[0] as f64,
+            p25: quantile(sorted, 0.25),
+            median: quantile(sorted, 0.5),
+            p75: quantile(sorted, 0.75),
+            p99: quantile(sorted, 0.99),
+            max: sorted[sorted.len() - 1] as f64,
+            mean_ci: (mean - half_width, mean + half_width),
+            median_ci: median_ci(sorted),
+        }
+    }
+
+    // 1操作あたりなど、すべての値をopsで割った要約（件数はそのまま）
+    pub fn per(&self, ops: f64) -> Summary {
+        Summary {
+            count: self.count,
+            mean: self.mean / ops,
+            stddev: self.stddev / ops,
+            min: self.min / ops,
+            p25: self.p25 / ops,
+            median: self.median / ops,
+            p75: self.p75 / ops,
+            p99: self.p99 / ops,
+            max: self.max / ops,
+            mean_ci: (self.mean_ci.0 / ops, self.mean_ci.1 / ops),
+            median_ci: (self.median_ci.0 / ops, self.median_ci.1 / ops),
+        }
+    }
+
+    // 中央値に対する信頼区間の幅（相対値）
+    pub fn median_ci_width(&self) -> f64 {
+        (self.median_ci.1 - self.median_ci.0) / self.median
+    }
+}
+
+// 中央値の95%信頼区間
+// 中央値より小さいサンプルの数は二項分布B(n, 1/2)に従うので、その正規近似で区間の両端になる順位を決める
+fn median_ci(sorted: &[u64]) -> (f64, f64) {
+    let n = sorted.len() as f64;
+    let half_width = Z_95 * n.sqrt() / 2.0;
+    let lo = ((n / 2.0 - half_width).floor().max(1.0) as usize).min(sorted.len());
+    let hi = ((n / 2.0 + half_width).ceil() as usize + 1).min(sorted.len());
+    (sorted[lo - 1] as f64, sorted[hi - 1] as f64)
+}
+
+// Mann-WhitneyのU検定（両側）のp値
+// 2つのサンプルが同じ分布から取られたという帰無仮説のもとで、Uの分布を正規近似する（同順位と連続性の補正つき）
+// 分布の形を仮定しないので、外れ値の多い計測値にも使える。a, bはどちらも昇順に並んでいること
+pub fn mann_whitney_u(a: &[u64], b: &[u64]) -> f64 {
+    let (n1, n2) = (a.len() as f64, b.len() as f64);
+    if a.is_empty() || b.is_empty() {
+        return 1.0;
+    }
+
+    // 2つをマージしながら順位を付け、aの順位の合計と同順位の補正項 Σ(t^3 - t) を求める
+    let (mut i, mut j) = (0, 0);
+    let mut rank_sum = 0.0;
+    let mut ties = 0.0;
+    let mut rank = 0.0;
+    while i < a.len() || j < b.len() {
+        let value = match (a.get(i), b.get(j)) {
+            (Some(&x), Some(&y)) => x.min(y),
+            (Some(&x), None) => x,
+            (None, Some(&y)) => y,
+            (None, None) => unreachable!(),
+        };
+        let in_a = a[i..].iter().take_while(|&&x| x == value).count();
+        let in_b = b[j..].iter().take_while(|&&y| y == value).count();
+        let t = (in_a + in_b) as f64;
+        // 同じ値には順位の平均を付ける
+        rank_sum += in_a as f64 * (rank + (t + 1.0) / 2.0);
+        ties += t * t * t - t;
+        rank += t;
+        i += in_a;
+        j += in_b;
+    }
+
+    let u = rank_sum - n1 * (n1 + 1.0) / 2.0;
+    let n = n1 + n2;
+    let variance = n1 * n2 / 12.0 * ((n + 1.0) - ties / (n * (n - 1.0)));
+    if variance <= 0.0 {
+        // すべて同じ値
+        return 1.0;
+    }
+    let z = ((u - n1 * n2 / 2.0).abs() - 0.5).max(0.0) / variance.sqrt();
+    erfc(z / std::f64::consts::SQRT_2).min(1.0)
+}
+
+// Holmの方法で補正したp値（pと同じ順）
+// 小さい方からk番目（0始まり）のp値に(m - k)を掛け、それより小さいp値の補正値を下回らないようにして1で打ち切る
+// 補正したp値が有意水準を下回るものだけを有意とすれば、m個のセルを比べても1つでも誤って有意とする確率が有意水準以下に収まる
+pub fn holm_adjust(p: &[f64]) -> Vec<f64> {
+    let m = p.len();
+    let mut order: Vec<usize> = (0..m).collect();
+    order.sort_by(|&x, &y| p[x].total_cmp(&p[y]));
+    let mut adjusted = vec![0.0; m];
+    let mut running: f64 = 0.0;
+    for (k, &i) in order.iter().enumerate() {
+        running = running.max(((m - k) as f64 * p[i]).min(1.0));
+        adjusted[i] = running;
+    }
+    adjusted
+}
+
+// 相補誤差関数（x >= 0、相対誤差1.2e-7以下のチェビシェフ近似）
+fn erfc(x: f64) -> f64 {
+    let t = 1.0 / (1.0 + 0.5 * x);
+    t * (-x * x - 1.26551223
+        + t * (1.00002368
+            + t * (0.37409196
+                + t * (0.09678418
+                    + t * (-0.18628806
+                        + t * (0.27886807
+                            + t * (-1.13520398
+                                + t * (1.48851587 + t * (-0.82215223 + t * 0.17087277)))))))))
+        .exp()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summary_of_uniform_samples() {
+        let samples: Vec<u64> = (1..=100).collect();
+        let s = Summary::of(&samples);
+        assert_eq!(s.count, 100);
+        assert_eq!(s.mean, 50.5);
+        assert_eq!(s.median, 50.5);
+        assert_eq!((s.min, s.max), (1.0, 100.0));
+        assert!((s.stddev - 29.011).abs() < 1e-3);
+        assert!(s.mean_ci.0 < s.mean && s.mean < s.mean_ci.1);
+        assert!(s.median_ci.0 < s.median && s.median < s.median_ci.1);
+        // n=100の95%区間は40番目から61番目の順序統計量
+        assert_eq!(s.median_ci, (40.0, 61.0));
+    }
+
+    #[test]
+    fn summary_of_single_sample() {
+        let s = Summary::of(&[7]);
+        assert_eq!(s.stddev, 0.0);
+        assert_eq!(s.median_ci, (7.0, 7.0));
+        assert_eq!(s.per(7.0).median, 1.0);
+    }
+
+    #[test]
+    fn mann_whitney_separated_samples() {
+        let a: Vec<u64> = (1..=10).collect();
+        let b: Vec<u64> = (11..=20).collect();
+        // U = 0, z = (50 - 0.5) / sqrt(175) = 3.742
+        let p = mann_whitney_u(&a, &b);
+        assert!((p - 1.83e-4).abs() < 1e-5, "p = {}", p);
+        assert_eq!(p, mann_whitney_u(&b, &a));
+    }
+
+    #[test]
+    fn mann_whitney_same_samples() {
+        let a: Vec<u64> = (1..=100).collect();
+        assert!(mann_whitney_u(&a, &a) > 0.99);
+        assert_eq!(mann_whitney_u(&[5; 10], &[5; 10]), 1.0);
+        // 交互に並ぶサンプルには差がない
+        let odd: Vec<u64> = (0..50).map(|i| 2 * i + 1).collect();
+        let even: Vec<u64> = (0..50).map(|i| 2 * i + 2).collect();
+        assert!(mann_whitney_u(&odd, &even) > 0.5);
+    }
+
+    #[test]
+    fn holm_adjusted_p_values() {
+        // 小さい順に 0.005*4, 0.01*3, 0.03*2, 0.04*1（単調にして0.06）
+        let adjusted = holm_adjust(&[0.01, 0.04, 0.03, 0.005]);
+        let expected = [0.03, 0.06, 0.06, 0.02];
+        for (a, e) in adjusted.iter().zip(expected) {
+            assert!((a - e).abs() < 1e-12, "{:?}", adjusted);
+        }
+        assert_eq!(holm_adjust(&[0.6, 0.9]), vec![1.0, 1.0]);
+        assert!(holm_adjust(&[]).is_empty());
+    }
+}
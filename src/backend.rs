@@ -0,0 +1,414 @@
+use crate::bufpool::{BufferPool, FixedBufferPool, FixedLease, Lease};
+use crate::pattern::{self, measure, remote_round_trip, Pattern as _};
+use crate::touch::{touch, touched, Touch};
+use crate::{BenchTiming, DataAllocator, Pattern, BATCH_SIZE};
+use quanta::Clock;
+use slab::Slab;
+use std::cell::UnsafeCell;
+use std::hint::black_box;
+use std::marker::PhantomData;
+use std::sync::mpsc;
+
+// スレッドごとにインスタンスを持つアロケータ（box / slab_* / bufpool_* / vec_reuse）
+// パターンはpatternモジュールがBenchAllocatorの上に1度だけ書いているので、バックエンドを足すときは
+// BenchAllocatorを実装し、run_benchmarkでAllocatorとの対応を1行足せばよい
+
+/// 計測するアロケータのバックエンド
+/// ラウンドごとにsetupでインスタンス（スラブやプール）を作り、allocとdeallocを繰り返してteardownで捨てる
+pub trait BenchAllocator {
+    /// setupで作るインスタンス
+    type Instance;
+    /// 確保したオブジェクトへのハンドル（Box、スラブのキー、プールのリースなど）
+    type Handle<'a>
+    where
+        Self: 'a;
+
+    /// アロケータの名前（結果のallocator列に書く名前）
+    fn name(&self) -> &'static str;
+
+    /// インスタンスを作る。liveはラウンドの中で同時に確保したままになる最大数
+    fn setup(&self, live: usize) -> Self::Instance;
+
+    /// 1つ確保する。確保したオブジェクトはtouch::touchで触ってから返す
+    /// （触らないとLLVMが使われない確保と解放の組を消し、計測が速く見えることがある）
+    fn alloc<'a>(&self, instance: &'a Self::Instance) -> Self::Handle<'a>
+    where
+        Self: 'a;
+
+    fn dealloc<'a>(&self, instance: &'a Self::Instance, handle: Self::Handle<'a>)
+    where
+        Self: 'a;
+
+    /// インスタンスを捨てる（既定ではdropするだけ）
+    fn teardown(&self, _instance: Self::Instance) {}
+
+    /// Immediateで確保のたびにインスタンスを作り直すか（slab_cold / bufpool_cold）
+    fn cold(&self) -> bool {
+        false
+    }
+
+    /// Remoteを計測する
+    /// 既定では所有側がハンドルを持ったままスロット番号を解放側スレッドと往復させ、送り返された順に所有側で解放する
+    /// 別のスレッドで直接解放できるアロケータは上書きする
+    fn remote(&self, clock: &Clock) -> BenchTiming
+    where
+        Self: Sized,
+    {
+        remote_round_trip(clock, self)
+    }
+}
+
+/// allocatorでpatternを1イテレーション（INNER_LOOP回のラウンド）計測する
+pub fn bench<A: BenchAllocator>(
+    clock: &Clock,
+    allocator: &A,
+    pattern: Pattern,
+    rng: &mut rand::rngs::StdRng,
+) -> BenchTiming {
+    match pattern {
+        Pattern::Immediate => pattern::Immediate.run(clock, allocator),
+        Pattern::Lifo => pattern::Lifo.run(clock, allocator),
+        Pattern::Fifo => pattern::Fifo.run(clock, allocator),
+        Pattern::Random => pattern::Random(rng).run(clock, allocator),
+        Pattern::Remote => pattern::Remote.run(clock, allocator),
+        Pattern::Hot => pattern::Hot.run(clock, allocator),
+    }
+}
+
+// 1つのスレッドからだけ使うスラブ
+// 計測にRefCellの借用フラグの確認を入れないよう、&selfから直接挿入・削除する
+pub(crate) struct LocalSlab<T>(UnsafeCell<Slab<T>>);
+
+impl<T> LocalSlab<T> {
+    #[inline(always)]
+    fn insert(&self, value: T) -> usize {
+        // SAFETY: UnsafeCellを持つのでSyncではなく、他のスレッドからは触れない
+        // &mutはこの呼び出しの中だけで、外から渡されたコードを呼ばないので重ならない
+        unsafe { (*self.0.get()).insert(value) }
+    }
+
+    #[inline(always)]
+    fn remove(&self, key: usize) -> T {
+        // SAFETY: insertと同じ
+        unsafe { (*self.0.get()).remove(key) }
+    }
+
+    // 挿入した値に触る
+    #[inline(always)]
+    fn touch(&self, key: usize)
+    where
+        T: Touch,
+    {
+        // SAFETY: insertと同じ（touchに渡した参照はこの呼び出しの外へ出さない）
+        let slab = unsafe { &mut *self.0.get() };
+        touch(&mut slab[key]);
+    }
+}
+
+// Box::new / drop
+pub(crate) struct BoxBackend<T>(PhantomData<T>);
+
+impl<T> BoxBackend<T> {
+    pub(crate) fn new() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<T: Default + Send + Touch> BenchAllocator for BoxBackend<T> {
+    type Instance = ();
+    type Handle<'a>
+        = Box<T>
+    where
+        Self: 'a;
+
+    fn name(&self) -> &'static str {
+        "box"
+    }
+
+    // Boxはグローバルアロケータを使うので、作るインスタンスはない
+    fn setup(&self, _live: usize) {}
+
+    #[inline(always)]
+    fn alloc<'a>(&self, _instance: &'a ()) -> Box<T>
+    where
+        Self: 'a,
+    {
+        touched(Box::new(T::default()))
+    }
+
+    #[inline(always)]
+    fn dealloc<'a>(&self, _instance: &'a (), handle: Box<T>)
+    where
+        Self: 'a,
+    {
+        drop(handle);
+    }
+
+    // Boxはそのまま解放側スレッドへ渡して、そこで解放する
+    // 確保したものをSPSCチャネル (sync_channel) で送り、バッチ分の解放完了を待つ
+    fn remote(&self, clock: &Clock) -> BenchTiming {
+        let (tx, rx) = mpsc::sync_channel::<Option<Box<T>>>(BATCH_SIZE);
+        let (done_tx, done_rx) = mpsc::sync_channel::<()>(1);
+        std::thread::scope(move |s| {
+            // 解放側スレッド: Noneでバッチの終わりを受け取り、完了を通知する
+            s.spawn(move || {
+                for msg in rx {
+                    match msg {
+                        Some(b) => drop(black_box(b)),
+                        None => {
+                            if done_tx.send(()).is_err() {
+                                break;
+                            }
+                        }
+                    }
+                }
+            });
+
+            let timing = measure(clock, || {
+                for _ in 0..BATCH_SIZE {
+                    tx.send(Some(touched(Box::new(T::default())))).unwrap();
+                }
+                tx.send(None).unwrap();
+                done_rx.recv().unwrap();
+            });
+
+            // 送信側を閉じて解放側スレッドを終了させる
+            drop(tx);
+            timing
+        })
+    }
+}
+
+// slab_cold / slab_warm
+// coldは空のスラブから、warmはlive個分の容量を確保済みのスラブから始める
+pub(crate) struct SlabBackend<T, const WARM: bool>(PhantomData<T>);
+
+impl<T, const WARM: bool> SlabBackend<T, WARM> {
+    pub(crate) fn new() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<T: Default + Touch, const WARM: bool> BenchAllocator for SlabBackend<T, WARM> {
+    type Instance = LocalSlab<T>;
+    type Handle<'a>
+        = usize
+    where
+        Self: 'a;
+
+    fn name(&self) -> &'static str {
+        if WARM {
+            "slab_warm"
+        } else {
+            "slab_cold"
+        }
+    }
+
+    #[inline(always)]
+    fn setup(&self, live: usize) -> LocalSlab<T> {
+        let slab = if WARM {
+            Slab::with_capacity(live)
+        } else {
+            Slab::new()
+        };
+        LocalSlab(UnsafeCell::new(slab))
+    }
+
+    #[inline(always)]
+    fn alloc<'a>(&self, slab: &'a LocalSlab<T>) -> usize
+    where
+        Self: 'a,
+    {
+        let key = slab.insert(T::default());
+        slab.touch(key);
+        key
+    }
+
+    #[inline(always)]
+    fn dealloc<'a>(&self, slab: &'a LocalSlab<T>, key: usize)
+    where
+        Self: 'a,
+    {
+        let _ = black_box(slab.remove(key));
+    }
+
+    fn cold(&self) -> bool {
+        !WARM
+    }
+}
+
+// bufpool_cold / bufpool_warm
+// coldは作ったばかりのプールから、warmはlive個確保して返却したプールから借りる
+pub(crate) struct BufpoolBackend<T, const WARM: bool>(PhantomData<T>);
+
+impl<T, const WARM: bool> BufpoolBackend<T, WARM> {
+    pub(crate) fn new() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<T: Default + Touch, const WARM: bool> BenchAllocator for BufpoolBackend<T, WARM> {
+    type Instance = BufferPool<DataAllocator<T>>;
+    type Handle<'a>
+        = Lease<'a, DataAllocator<T>>
+    where
+        Self: 'a;
+
+    fn name(&self) -> &'static str {
+        if WARM {
+            "bufpool_warm"
+        } else {
+            "bufpool_cold"
+        }
+    }
+
+    #[inline(always)]
+    fn setup(&self, live: usize) -> Self::Instance {
+        let pool = BufferPool::new(DataAllocator::new());
+        if WARM {
+            // ウォームアップ: live個確保して返却（1個ならVecを使わない）
+            match live {
+                1 => drop(pool.lease().unwrap()),
+                _ => drop((0..live).map(|_| pool.lease().unwrap()).collect::<Vec<_>>()),
+            }
+        }
+        pool
+    }
+
+    #[inline(always)]
+    fn alloc<'a>(&self, pool: &'a Self::Instance) -> Self::Handle<'a>
+    where
+        Self: 'a,
+    {
+        let mut lease = pool.lease().unwrap();
+        touch(&mut *lease);
+        lease
+    }
+
+    #[inline(always)]
+    fn dealloc<'a>(&self, _pool: &'a Self::Instance, lease: Self::Handle<'a>)
+    where
+        Self: 'a,
+    {
+        drop(lease);
+    }
+
+    fn cold(&self) -> bool {
+        !WARM
+    }
+}
+
+// bufpool_fixed: スロットの表もバッファもインラインに持つFixedBufferPool<T, BATCH_SIZE>
+// 作るときにすべてのバッファを用意するので、warmと同じく確保済みのプールから借りる
+pub(crate) struct FixedBufpoolBackend<T>(PhantomData<T>);
+
+impl<T> FixedBufpoolBackend<T> {
+    pub(crate) fn new() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<T: Default + Touch> BenchAllocator for FixedBufpoolBackend<T> {
+    type Instance = FixedBufferPool<T, BATCH_SIZE>;
+    type Handle<'a>
+        = FixedLease<'a, T, BATCH_SIZE>
+    where
+        Self: 'a;
+
+    fn name(&self) -> &'static str {
+        "bufpool_fixed"
+    }
+
+    #[inline(always)]
+    fn setup(&self, _live: usize) -> Self::Instance {
+        FixedBufferPool::default()
+    }
+
+    #[inline(always)]
+    fn alloc<'a>(&self, pool: &'a Self::Instance) -> Self::Handle<'a>
+    where
+        Self: 'a,
+    {
+        let mut lease = pool.lease().unwrap();
+        touch(&mut *lease);
+        lease
+    }
+
+    #[inline(always)]
+    fn dealloc<'a>(&self, _pool: &'a Self::Instance, lease: Self::Handle<'a>)
+    where
+        Self: 'a,
+    {
+        drop(lease);
+    }
+}
+
+// vec_reuse: プールを使わずに、空きバッファのVec<u8>をスタックに積んで使い回す基準
+pub(crate) struct VecReuseBackend<T>(PhantomData<T>);
+
+impl<T> VecReuseBackend<T> {
+    pub(crate) fn new() -> Self {
+        Self(PhantomData)
+    }
+}
+
+// 1つのスレッドからだけ使う空きバッファのスタック（LocalSlabと同じく&selfから出し入れする）
+pub(crate) struct FreeList {
+    len: usize,
+    free: UnsafeCell<Vec<Vec<u8>>>,
+}
+
+impl FreeList {
+    #[inline(always)]
+    fn pop(&self) -> Vec<u8> {
+        // SAFETY: LocalSlab::insertと同じ
+        unsafe { (*self.free.get()).pop() }.unwrap_or_else(|| vec![0; self.len])
+    }
+
+    #[inline(always)]
+    fn push(&self, buf: Vec<u8>) {
+        // SAFETY: LocalSlab::insertと同じ
+        unsafe { (*self.free.get()).push(buf) }
+    }
+}
+
+impl<T> BenchAllocator for VecReuseBackend<T> {
+    type Instance = FreeList;
+    type Handle<'a>
+        = Vec<u8>
+    where
+        Self: 'a;
+
+    fn name(&self) -> &'static str {
+        "vec_reuse"
+    }
+
+    // 同じ大きさのバッファをlive個積んでおく
+    fn setup(&self, live: usize) -> FreeList {
+        let len = std::mem::size_of::<T>();
+        let mut free = Vec::with_capacity(live);
+        free.resize_with(live, || vec![0; len]);
+        FreeList {
+            len,
+            free: UnsafeCell::new(free),
+        }
+    }
+
+    #[inline(always)]
+    fn alloc<'a>(&self, free: &'a FreeList) -> Vec<u8>
+    where
+        Self: 'a,
+    {
+        let mut buf = free.pop();
+        touch(buf.as_mut_slice());
+        buf
+    }
+
+    #[inline(always)]
+    fn dealloc<'a>(&self, free: &'a FreeList, buf: Vec<u8>)
+    where
+        Self: 'a,
+    {
+        free.push(buf);
+    }
+}
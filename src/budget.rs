@@ -0,0 +1,95 @@
+// --max-duration: 計測全体が時間の予算に収まるように、セルごとのイテレーション数を決める
+// ジョブスケジューラの実行時間の上限に収めるためのもので、計測が進むごとに実際の所要時間から見積もりを直し、
+// 残りの時間を残りのセルで等分する。どのセルもMIN_ITERATIONS回は計測する（予算を超えても減らさない）
+
+use crate::{ITERATIONS, OPS_PER_ITERATION};
+use std::time::{Duration, Instant};
+
+/// 予算が足りなくても、1セルあたりこれだけは計測する
+pub const MIN_ITERATIONS: u32 = 10;
+
+/// --max-durationの秒数を記録するparquetのメタデータのキー
+pub const MAX_DURATION_KEY: &str = "memalloc_bench.max_duration";
+
+pub struct Budget {
+    limit: Option<Duration>,
+    start: Instant,
+    // 残りのセルの枠（--jobsで並行に計測するセルは1/jobs枠）
+    remaining_slots: f64,
+    // これまでに計測した 枠 x イテレーション数 の合計と、その間の経過時間から求める1枠1イテレーションあたりの秒数
+    done_slot_iterations: f64,
+    initial_secs_per_iteration: f64,
+}
+
+impl Budget {
+    /// limitがNoneなら常にITERATIONS回計測する
+    /// slotsは計測するセルの枠の数、ns_per_opは計測前の1操作あたりの時間の見積もり
+    pub fn new(limit: Option<Duration>, slots: f64, ns_per_op: f64) -> Self {
+        Budget {
+            limit,
+            start: Instant::now(),
+            remaining_slots: slots,
+            done_slot_iterations: 0.0,
+            initial_secs_per_iteration: OPS_PER_ITERATION as f64 * ns_per_op * 1e-9,
+        }
+    }
+
+    fn secs_per_iteration(&self) -> f64 {
+        if self.done_slot_iterations > 0.0 {
+            self.start.elapsed().as_secs_f64() / self.done_slot_iterations
+        } else {
+            self.initial_secs_per_iteration
+        }
+    }
+
+    /// 次に計測するセルのイテレーション数
+    pub fn iterations(&self) -> u32 {
+        let Some(limit) = self.limit else {
+            return ITERATIONS;
+        };
+        let remaining = limit.saturating_sub(self.start.elapsed()).as_secs_f64();
+        let per_slot = remaining / self.remaining_slots.max(1.0);
+        let n = per_slot / self.secs_per_iteration();
+        (n as u32).clamp(MIN_ITERATIONS, ITERATIONS)
+    }
+
+    /// weight枠のセルをiterations回計測し終えたことを記録する
+    pub fn finish(&mut self, weight: f64, iterations: u32) {
+        self.remaining_slots = (self.remaining_slots - weight).max(0.0);
+        self.done_slot_iterations += weight * iterations as f64;
+    }
+
+    /// 予算を超えたか（MIN_ITERATIONSを守るために超えることがある）
+    pub fn exceeded(&self) -> bool {
+        self.limit.is_some_and(|limit| self.start.elapsed() > limit)
+    }
+}
+
+// "30m" / "1h30m" / "90s" / "2h" -> Duration（単位のない数は秒）
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let invalid = || format!("invalid duration: {} (e.g. 30m, 1h30m, 90s)", s);
+    let mut total = 0u64;
+    let mut digits = String::new();
+    for c in s.trim().chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+        let unit = match c {
+            'h' => 3600,
+            'm' => 60,
+            's' => 1,
+            _ => return Err(invalid()),
+        };
+        let n: u64 = digits.parse().map_err(|_| invalid())?;
+        total += n * unit;
+        digits.clear();
+    }
+    if !digits.is_empty() {
+        total += digits.parse::<u64>().map_err(|_| invalid())?;
+    }
+    if total == 0 {
+        return Err("duration must be greater than 0".to_string());
+    }
+    Ok(Duration::from_secs(total))
+}
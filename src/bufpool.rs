@@ -1,13 +1,207 @@
+use crossbeam_channel::{Receiver, Sender};
+use crossbeam_queue::{ArrayQueue, SegQueue};
+use thread_local::ThreadLocal;
+// loomのテストではMutexBufferPoolのロックをloomのものに差し替える
+#[cfg(all(test, feature = "loom"))]
+use loom::sync::Mutex;
+#[cfg(not(all(test, feature = "loom")))]
+use std::sync::Mutex;
 use std::{
-    cell::UnsafeCell,
+    alloc::{self, Layout},
+    cell::{Cell, UnsafeCell},
+    future::Future,
     marker::PhantomData,
-    ops::{Deref, DerefMut},
+    mem::{ManuallyDrop, MaybeUninit},
+    ops::{Deref, DerefMut, Index, IndexMut},
+    pin::Pin,
+    ptr::NonNull,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{Context, Poll, Waker},
+    time::{Duration, Instant},
 };
 
 pub trait Buffer {
+    /// バッファの先頭（Cのライブラリやシステムコールに渡すとき。通常はas_slice / as_mut_sliceを使う）
+    /// &selfから取るので、[u8; N]のようにバッファを値で持つ型では読むためにしか使えない。書き込むポインタはas_mut_ptrで取る
+    ///
+    /// # Safety
+    /// 返したポインタはバッファが動くか解放されるまでしか使えず、その間に参照と重ねて書き込まないこと
     unsafe fn ptr(&self) -> *mut u8;
-    unsafe fn size(&self) -> usize;
+
+    /// 書き込みに使えるバッファの先頭（readやDMAの書き込み先として渡すとき）
+    /// デフォルトはptrを返すので、ptrから書けない型（バッファを値で持つ型）は実装し直す
+    fn as_mut_ptr(&mut self) -> *mut u8 {
+        // SAFETY: アドレスを取るだけ
+        unsafe { self.ptr() }
+    }
+
+    /// 初期化済みで読み書きできるバイト数（as_sliceの長さ）
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// バッファが確保している全体のバイト数（lenより短くならない）
+    /// 最初からすべて初期化済みのバッファではlenと同じ
+    fn capacity(&self) -> usize {
+        self.len()
+    }
+
     fn reset(&mut self) {}
+
+    /// 失敗を報告できるreset（マップしたファイルやデバイスメモリなど、再利用の準備に失敗しうるバッファ向け）
+    /// プールはresetの代わりにこちらを呼び、失敗したバッファは捨ててleaseでエラーを返す
+    /// デフォルトはresetを呼んで成功を返す
+    fn try_reset(&mut self) -> std::io::Result<()> {
+        self.reset();
+        Ok(())
+    }
+
+    /// バッファの中身をスライスとして読む（ptrとsizeを直接扱わずに済む）
+    #[inline]
+    fn as_slice(&self) -> &[u8] {
+        // SAFETY: ptrからlenバイトはバッファが所有していて初期化済み
+        unsafe { std::slice::from_raw_parts(self.ptr(), self.len()) }
+    }
+
+    /// バッファの中身をスライスとして書く
+    #[inline]
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        let len = self.len();
+        // SAFETY: as_mut_ptrからlenバイトはバッファが所有していて初期化済み、&mut selfで排他
+        unsafe { std::slice::from_raw_parts_mut(self.as_mut_ptr(), len) }
+    }
+
+    /// 初期化済みかどうかに関わらず、バッファ全体を書き込み先として見る
+    /// UninitBufferのように未初期化のまま貸し出すバッファは、as_sliceより長い範囲を返す
+    ///
+    /// # Safety
+    /// 初期化済みの位置にMaybeUninit::uninit()を書き込まないこと（as_sliceで読める範囲が未初期化になる）
+    #[inline]
+    unsafe fn as_uninit_slice(&mut self) -> &mut [MaybeUninit<u8>] {
+        let capacity = self.capacity();
+        // SAFETY: as_mut_ptrからcapacityバイトはバッファが所有していて、&mut selfで排他
+        unsafe { std::slice::from_raw_parts_mut(self.as_mut_ptr().cast(), capacity) }
+    }
+
+    /// 先頭lenバイトを初期化したことを記録する（as_sliceがlenバイトを返すようになる）
+    /// 最初からすべて初期化済みのバッファでは何もしない
+    ///
+    /// # Safety
+    /// as_uninit_sliceの先頭lenバイトに値を書き込んであること
+    #[inline]
+    unsafe fn assume_init_to(&mut self, _len: usize) {}
+
+    /// dataを先頭に書き込み、書き込んだ範囲を初期化済みにして書き込んだバイト数を返す
+    /// 未初期化のバッファにもunsafeなしで書ける
+    fn init_from_slice(&mut self, data: &[u8]) -> usize {
+        // SAFETY: 初期化済みのu8だけを書き込み、書き込んだ長さだけを初期化済みにする
+        unsafe {
+            let dst = self.as_uninit_slice();
+            let n = data.len().min(dst.len());
+            std::ptr::copy_nonoverlapping(data.as_ptr(), dst.as_mut_ptr().cast::<u8>(), n);
+            self.assume_init_to(n);
+            n
+        }
+    }
+}
+
+/// 未初期化のまま貸し出すNバイトのバッファ（main.rsのデータ型と同じく、MaybeUninitでゼロクリアを省く）
+/// 先頭から初期化した長さを覚えておき、as_slice / as_mut_sliceはそこまでだけを返す
+/// 書き込みはinit_from_slice、またはas_uninit_sliceに書いてからassume_init_toで行う
+/// resetすると初期化した長さを0に戻す（中身は消さない）
+pub struct UninitBuffer<const N: usize> {
+    data: MaybeUninit<[u8; N]>,
+    init: usize,
+}
+
+impl<const N: usize> UninitBuffer<N> {
+    pub fn new() -> Self {
+        UninitBuffer {
+            data: MaybeUninit::uninit(),
+            init: 0,
+        }
+    }
+
+    /// 初期化済みのバイト数
+    pub fn initialized(&self) -> usize {
+        self.init
+    }
+}
+
+impl<const N: usize> Default for UninitBuffer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Buffer for UninitBuffer<N> {
+    unsafe fn ptr(&self) -> *mut u8 {
+        self.data.as_ptr() as *mut u8
+    }
+
+    fn as_mut_ptr(&mut self) -> *mut u8 {
+        self.data.as_mut_ptr().cast()
+    }
+
+    fn len(&self) -> usize {
+        self.init
+    }
+
+    fn capacity(&self) -> usize {
+        N
+    }
+
+    fn reset(&mut self) {
+        self.init = 0;
+    }
+
+    #[inline]
+    fn as_slice(&self) -> &[u8] {
+        // SAFETY: 先頭initバイトは初期化済み
+        unsafe { std::slice::from_raw_parts(self.data.as_ptr().cast(), self.init) }
+    }
+
+    #[inline]
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        // SAFETY: 先頭initバイトは初期化済み
+        unsafe { std::slice::from_raw_parts_mut(self.data.as_mut_ptr().cast(), self.init) }
+    }
+
+    #[inline]
+    unsafe fn as_uninit_slice(&mut self) -> &mut [MaybeUninit<u8>] {
+        // SAFETY: MaybeUninit<[u8; N]>と[MaybeUninit<u8>; N]はレイアウトが同じ
+        unsafe { std::slice::from_raw_parts_mut(self.data.as_mut_ptr().cast(), N) }
+    }
+
+    #[inline]
+    unsafe fn assume_init_to(&mut self, len: usize) {
+        assert!(
+            len <= N,
+            "assume_init_to({}) exceeds the buffer size {}",
+            len,
+            N
+        );
+        self.init = len;
+    }
+}
+
+/// UninitBufferを確保するアロケータ（確保時にゼロクリアしない）
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UninitAllocator<const N: usize>;
+
+impl<const N: usize> BufferAllocator for UninitAllocator<N> {
+    type Buffer = UninitBuffer<N>;
+    type Error = std::convert::Infallible;
+
+    fn allocate(&self) -> Result<Self::Buffer, Self::Error> {
+        Ok(UninitBuffer::new())
+    }
 }
 
 impl<const N: usize> Buffer for [u8; N] {
@@ -15,7 +209,11 @@ impl<const N: usize> Buffer for [u8; N] {
         self.as_ptr() as *mut u8
     }
 
-    unsafe fn size(&self) -> usize {
+    fn as_mut_ptr(&mut self) -> *mut u8 {
+        <[u8]>::as_mut_ptr(self)
+    }
+
+    fn len(&self) -> usize {
         N
     }
 
@@ -27,8 +225,12 @@ impl Buffer for [u8] {
         self.as_ptr() as *mut u8
     }
 
-    unsafe fn size(&self) -> usize {
-        self.len()
+    fn as_mut_ptr(&mut self) -> *mut u8 {
+        <[u8]>::as_mut_ptr(self)
+    }
+
+    fn len(&self) -> usize {
+        <[u8]>::len(self)
     }
 
     fn reset(&mut self) {}
@@ -39,8 +241,12 @@ impl Buffer for Vec<u8> {
         self.as_ptr() as *mut _
     }
 
-    unsafe fn size(&self) -> usize {
-        self.len()
+    fn as_mut_ptr(&mut self) -> *mut u8 {
+        Vec::as_mut_ptr(self)
+    }
+
+    fn len(&self) -> usize {
+        Vec::len(self)
     }
 
     fn reset(&mut self) {
@@ -53,13 +259,19 @@ impl Buffer for Box<[u8]> {
         self.as_ptr() as *mut _
     }
 
-    unsafe fn size(&self) -> usize {
-        self.len() as _
+    fn as_mut_ptr(&mut self) -> *mut u8 {
+        <[u8]>::as_mut_ptr(self)
+    }
+
+    fn len(&self) -> usize {
+        <[u8]>::len(self)
     }
 
     fn reset(&mut self) {}
 }
 
+/// 内側のバッファの先頭lenバイトだけをバッファとして見せるラッパー
+/// lenは内側のバッファの長さ（capacity）までの範囲で何度でも変えられる
 pub struct Resize<B> {
     buf: B,
     len: usize,
@@ -70,156 +282,4753 @@ impl<B: Buffer> Buffer for Resize<B> {
         unsafe { self.buf.ptr() }
     }
 
-    unsafe fn size(&self) -> usize {
-        self.len
+    fn as_mut_ptr(&mut self) -> *mut u8 {
+        self.buf.as_mut_ptr()
+    }
+
+    /// 見せている長さ（内側のバッファがresetなどで縮んでいれば、その長さまで）
+    fn len(&self) -> usize {
+        self.len.min(self.buf.len())
+    }
+
+    /// 内側のバッファが確保している長さ（resizeで伸ばせる上限）
+    fn capacity(&self) -> usize {
+        self.buf.capacity()
     }
 
     fn reset(&mut self) {
         self.buf.reset();
     }
+
+    fn try_reset(&mut self) -> std::io::Result<()> {
+        self.buf.try_reset()
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        let bytes = self.buf.as_slice();
+        &bytes[..self.len.min(bytes.len())]
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        let bytes = self.buf.as_mut_slice();
+        let len = self.len.min(bytes.len());
+        &mut bytes[..len]
+    }
+
+    unsafe fn as_uninit_slice(&mut self) -> &mut [MaybeUninit<u8>] {
+        let bytes = unsafe { self.buf.as_uninit_slice() };
+        let len = self.len.min(bytes.len());
+        &mut bytes[..len]
+    }
+
+    unsafe fn assume_init_to(&mut self, len: usize) {
+        unsafe { self.buf.assume_init_to(len.min(self.len)) }
+    }
+}
+
+impl<B: Buffer> AsRef<[u8]> for Resize<B> {
+    #[inline]
+    fn as_ref(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
+
+impl<B: Buffer> AsMut<[u8]> for Resize<B> {
+    #[inline]
+    fn as_mut(&mut self) -> &mut [u8] {
+        self.as_mut_slice()
+    }
 }
 
 impl<B: Buffer> Resize<B> {
     pub fn new(buf: B) -> Self {
         Self {
-            len: unsafe { buf.size() },
+            len: buf.capacity(),
             buf,
         }
     }
 
     pub fn new_with_size(buf: B, len: usize) -> Self {
         Self {
-            len: unsafe { buf.size().min(len) },
+            len: buf.capacity().min(len),
             buf,
         }
     }
 
+    /// 見せる長さをlenにする（capacityまでなら伸ばすこともできる）
+    /// lenがcapacityを超えるとpanicする
     pub fn resize(&mut self, len: usize) {
-        self.len = self.len.min(len);
+        let capacity = self.capacity();
+        assert!(
+            len <= capacity,
+            "resize({}) exceeds the capacity {}",
+            len,
+            capacity
+        );
+        self.len = len;
     }
 }
 
-pub trait BufferAllocator {
-    type Buffer;
-    type Error;
+/// 先頭がAバイト境界に揃ったNバイトのバッファ（O_DIRECT、SIMD、DMA向け）
+/// 配列の型ではアラインメントをconst genericにできないので、レイアウトを指定してヒープに確保する
+pub struct AlignedBuffer<const N: usize, const A: usize> {
+    ptr: NonNull<u8>,
+}
 
-    fn allocate(&self) -> Result<Self::Buffer, Self::Error>;
+impl<const N: usize, const A: usize> AlignedBuffer<N, A> {
+    /// Aが2のべき乗でない、またはNが0のときはコンパイル時にエラーにする
+    const LAYOUT: Layout = match Layout::from_size_align(N, A) {
+        Ok(layout) if N > 0 => layout,
+        _ => panic!("AlignedBuffer requires N > 0 and a power-of-two alignment A"),
+    };
+
+    /// ゼロ埋めしたバッファを確保する
+    pub fn new() -> Self {
+        let layout = Self::LAYOUT;
+        // SAFETY: LAYOUTのサイズは0でない
+        let ptr = unsafe { alloc::alloc_zeroed(layout) };
+        match NonNull::new(ptr) {
+            Some(ptr) => AlignedBuffer { ptr },
+            None => alloc::handle_alloc_error(layout),
+        }
+    }
 }
 
-/// エントリ: バッファを常に保持し、次の空きインデックスも持つ
-struct Entry<T> {
-    buffer: T,
-    /// 空きの場合、次の空きスロットのインデックス（usize::MAXで終端）
-    next_free: usize,
+impl<const N: usize, const A: usize> Default for AlignedBuffer<N, A> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-const FREE_LIST_END: usize = usize::MAX;
+impl<const N: usize, const A: usize> Drop for AlignedBuffer<N, A> {
+    fn drop(&mut self) {
+        // SAFETY: newで同じレイアウトで確保したもの
+        unsafe { alloc::dealloc(self.ptr.as_ptr(), Self::LAYOUT) };
+    }
+}
 
-/// 内部プール状態（UnsafeCellで包む - シングルスレッド前提）
-struct PoolInner<A: BufferAllocator> {
-    allocator: A,
-    entries: Vec<Entry<A::Buffer>>,
-    /// フリーリストの先頭（usize::MAXで空）
-    free_head: usize,
+// SAFETY: バッファを排他的に所有している
+unsafe impl<const N: usize, const A: usize> Send for AlignedBuffer<N, A> {}
+unsafe impl<const N: usize, const A: usize> Sync for AlignedBuffer<N, A> {}
+
+impl<const N: usize, const A: usize> Deref for AlignedBuffer<N, A> {
+    type Target = [u8; N];
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        // SAFETY: Nバイト確保して初期化済み
+        unsafe { &*(self.ptr.as_ptr() as *const [u8; N]) }
+    }
 }
 
-pub struct BufferPool<A: BufferAllocator> {
-    inner: UnsafeCell<PoolInner<A>>,
+impl<const N: usize, const A: usize> DerefMut for AlignedBuffer<N, A> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        // SAFETY: Nバイト確保して初期化済み
+        unsafe { &mut *(self.ptr.as_ptr() as *mut [u8; N]) }
+    }
 }
 
-pub struct Lease<'a, A: BufferAllocator> {
-    /// プールへの参照（ライフタイムで生存を保証）
-    pool: &'a BufferPool<A>,
-    /// このリースが持っているエントリのインデックス
-    index: usize,
-    /// PhantomDataで不変性を保証
-    _marker: PhantomData<&'a mut A::Buffer>,
+impl<const N: usize, const A: usize> Buffer for AlignedBuffer<N, A> {
+    unsafe fn ptr(&self) -> *mut u8 {
+        self.ptr.as_ptr()
+    }
+
+    fn len(&self) -> usize {
+        N
+    }
 }
 
-impl<A: BufferAllocator> BufferPool<A> {
-    pub fn new(allocator: A) -> Self {
-        BufferPool {
-            inner: UnsafeCell::new(PoolInner {
-                allocator,
-                entries: Vec::new(),
-                free_head: FREE_LIST_END,
-            }),
-        }
+/// AlignedBufferを確保するアロケータ
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AlignedAllocator<const N: usize, const A: usize>;
+
+impl<const N: usize, const A: usize> BufferAllocator for AlignedAllocator<N, A> {
+    type Buffer = AlignedBuffer<N, A>;
+    type Error = std::convert::Infallible;
+
+    fn allocate(&self) -> Result<Self::Buffer, Self::Error> {
+        Ok(AlignedBuffer::new())
     }
+}
 
-    #[inline]
-    pub fn lease(&self) -> Result<Lease<'_, A>, A::Error> {
-        // SAFETY: シングルスレッド前提、&self経由でのみアクセス
-        let inner = unsafe { &mut *self.inner.get() };
+/// 中身を確実にゼロで消すバッファ（鍵や認証情報を置くプール向け）
+/// resetとDropで中身をvolatileに書き込んで消すので、最適化で消去が省かれない
+/// プールではResetPolicy::OnReturnと組み合わせると、返されたバッファはすぐ消去される
+pub struct ZeroizingBuffer<B: Buffer> {
+    buf: B,
+}
 
-        let index = if inner.free_head != FREE_LIST_END {
-            // 空きスロットがある - フリーリストから取得
-            let idx = inner.free_head;
-            inner.free_head = inner.entries[idx].next_free;
-            inner.entries[idx].next_free = FREE_LIST_END; // 使用中マーク
-            idx
-        } else {
-            // 新規アロケーション
-            let buf = inner.allocator.allocate()?;
-            let idx = inner.entries.len();
-            inner.entries.push(Entry {
-                buffer: buf,
-                next_free: FREE_LIST_END,
-            });
-            idx
-        };
+impl<B: Buffer> ZeroizingBuffer<B> {
+    pub fn new(buf: B) -> Self {
+        ZeroizingBuffer { buf }
+    }
 
-        Ok(Lease {
-            pool: self,
-            index,
-            _marker: PhantomData,
-        })
+    /// 中身をゼロで消す（未初期化として扱っている範囲も消す）
+    pub fn zeroize(&mut self) {
+        // SAFETY: 書き込むのは初期化済みの値（0）だけ
+        zeroize(unsafe { self.buf.as_uninit_slice() });
     }
 }
 
-impl<A: BufferAllocator> Drop for Lease<'_, A> {
-    #[inline]
-    fn drop(&mut self) {
-        // SAFETY: ライフタイムで生存保証、シングルスレッド前提
-        let inner = unsafe { &mut *self.pool.inner.get() };
+/// スライスをvolatileな書き込みでゼロにする（先頭と末尾はバイト単位、間はワード単位）
+fn zeroize(bytes: &mut [MaybeUninit<u8>]) {
+    let ptr = bytes.as_mut_ptr().cast::<u8>();
+    let len = bytes.len();
+    let head = ptr.align_offset(std::mem::align_of::<usize>()).min(len);
+    let words = (len - head) / std::mem::size_of::<usize>();
+    // SAFETY: すべての書き込みはbytesの内側で、ワード単位の区間はusizeの境界に揃っている
+    unsafe {
+        for i in 0..head {
+            ptr.add(i).write_volatile(0);
+        }
+        let word_ptr = ptr.add(head).cast::<usize>();
+        for i in 0..words {
+            word_ptr.add(i).write_volatile(0);
+        }
+        for i in head + words * std::mem::size_of::<usize>()..len {
+            ptr.add(i).write_volatile(0);
+        }
+    }
+    std::sync::atomic::compiler_fence(std::sync::atomic::Ordering::SeqCst);
+}
 
-        // フリーリストに追加（バッファは保持したまま）
-        inner.entries[self.index].next_free = inner.free_head;
-        inner.free_head = self.index;
+impl<B: Buffer> Drop for ZeroizingBuffer<B> {
+    fn drop(&mut self) {
+        self.zeroize();
     }
 }
 
-impl<A: BufferAllocator> Deref for Lease<'_, A> {
-    type Target = A::Buffer;
+impl<B: Buffer> Buffer for ZeroizingBuffer<B> {
+    unsafe fn ptr(&self) -> *mut u8 {
+        unsafe { self.buf.ptr() }
+    }
+
+    fn as_mut_ptr(&mut self) -> *mut u8 {
+        self.buf.as_mut_ptr()
+    }
+
+    fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    fn capacity(&self) -> usize {
+        self.buf.capacity()
+    }
+
+    fn reset(&mut self) {
+        self.zeroize();
+        self.buf.reset();
+    }
+
+    fn try_reset(&mut self) -> std::io::Result<()> {
+        self.zeroize();
+        self.buf.try_reset()
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        self.buf.as_slice()
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        self.buf.as_mut_slice()
+    }
 
+    unsafe fn as_uninit_slice(&mut self) -> &mut [MaybeUninit<u8>] {
+        unsafe { self.buf.as_uninit_slice() }
+    }
+
+    unsafe fn assume_init_to(&mut self, len: usize) {
+        unsafe { self.buf.assume_init_to(len) }
+    }
+}
+
+impl<B: Buffer> AsRef<[u8]> for ZeroizingBuffer<B> {
     #[inline]
-    fn deref(&self) -> &Self::Target {
-        let inner = unsafe { &*self.pool.inner.get() };
-        &inner.entries[self.index].buffer
+    fn as_ref(&self) -> &[u8] {
+        self.as_slice()
     }
 }
 
-impl<A: BufferAllocator> DerefMut for Lease<'_, A> {
+impl<B: Buffer> AsMut<[u8]> for ZeroizingBuffer<B> {
     #[inline]
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        let inner = unsafe { &mut *self.pool.inner.get() };
-        &mut inner.entries[self.index].buffer
+    fn as_mut(&mut self) -> &mut [u8] {
+        self.as_mut_slice()
     }
 }
 
-impl<A: BufferAllocator> Buffer for Lease<'_, A>
+/// 別のアロケータのバッファをZeroizingBufferに包むアロケータ
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ZeroizingAllocator<A> {
+    allocator: A,
+}
+
+impl<A> ZeroizingAllocator<A> {
+    pub fn new(allocator: A) -> Self {
+        ZeroizingAllocator { allocator }
+    }
+}
+
+impl<A: BufferAllocator> BufferAllocator for ZeroizingAllocator<A>
 where
     A::Buffer: Buffer,
 {
-    unsafe fn ptr(&self) -> *mut u8 {
-        unsafe { (**self).ptr() }
+    type Buffer = ZeroizingBuffer<A::Buffer>;
+    type Error = A::Error;
+
+    fn allocate(&self) -> Result<Self::Buffer, Self::Error> {
+        self.allocator.allocate().map(ZeroizingBuffer::new)
     }
 
-    fn reset(&mut self) {
-        (**self).reset();
+    // growは転送しない（Vecの再確保では古い領域がゼロ消去されずに解放される）
+}
+
+/// クロージャでオブジェクトを作るアロケータ（ObjectPool用）
+pub struct FnAllocator<T, F> {
+    make: F,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T, F: Fn() -> T> FnAllocator<T, F> {
+    pub fn new(make: F) -> Self {
+        FnAllocator {
+            make,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T, F: Fn() -> T> BufferAllocator for FnAllocator<T, F> {
+    type Buffer = T;
+    type Error = std::convert::Infallible;
+
+    fn allocate(&self) -> Result<T, Self::Error> {
+        Ok((self.make)())
+    }
+}
+
+/// バイト列に限らないオブジェクトのプール（パーサ、接続、作業用の構造体など）
+/// リースやフリーリストはBufferPoolと同じで、返されたオブジェクトの初期化はwith_resetで指定する
+pub type ObjectPool<T, F> = BufferPool<FnAllocator<T, F>>;
+
+impl<T, F: Fn() -> T> ObjectPool<T, F> {
+    /// makeで新しいオブジェクトを作るプール
+    pub fn from_fn(make: F) -> Self {
+        BufferPool::new(FnAllocator::new(make))
+    }
+}
+
+/// lenバイトのゼロ埋めしたVec<u8>を確保するアロケータ
+/// （ResetPolicyでresetするとVecは空になるので、使い回すときは長さを詰め直す）
+#[derive(Debug, Clone, Copy, Default)]
+pub struct VecAllocator {
+    pub len: usize,
+}
+
+impl VecAllocator {
+    pub fn new(len: usize) -> Self {
+        VecAllocator { len }
+    }
+}
+
+impl BufferAllocator for VecAllocator {
+    type Buffer = Vec<u8>;
+    type Error = std::convert::Infallible;
+
+    fn allocate(&self) -> Result<Self::Buffer, Self::Error> {
+        Ok(vec![0; self.len])
+    }
+
+    fn grow(&self, buffer: &mut Self::Buffer, len: usize) -> Result<bool, Self::Error> {
+        if buffer.len() < len {
+            buffer.resize(len, 0);
+        }
+        Ok(true)
+    }
+}
+
+/// lenバイトのゼロ埋めしたBox<[u8]>を確保するアロケータ
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BoxedSliceAllocator {
+    pub len: usize,
+}
+
+impl BoxedSliceAllocator {
+    pub fn new(len: usize) -> Self {
+        BoxedSliceAllocator { len }
+    }
+}
+
+impl BufferAllocator for BoxedSliceAllocator {
+    type Buffer = Box<[u8]>;
+    type Error = std::convert::Infallible;
+
+    fn allocate(&self) -> Result<Self::Buffer, Self::Error> {
+        Ok(vec![0; self.len].into_boxed_slice())
+    }
+}
+
+/// ゼロ埋めした[u8; N]を確保するアロケータ（バッファはプールのエントリに直接入る）
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ArrayAllocator<const N: usize>;
+
+impl<const N: usize> BufferAllocator for ArrayAllocator<N> {
+    type Buffer = [u8; N];
+    type Error = std::convert::Infallible;
+
+    fn allocate(&self) -> Result<Self::Buffer, Self::Error> {
+        Ok([0; N])
+    }
+}
+
+/// mmapで確保するバッファのページの種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageMode {
+    /// 通常の4Kページ（madvise(MADV_NOHUGEPAGE)でTHPも使わない）
+    Base,
+    /// Transparent Huge Pages（2MiB境界に揃えてmadvise(MADV_HUGEPAGE)）
+    Transparent,
+    /// hugetlbfsの予約済みヒュージページ（MAP_HUGETLB、vm.nr_hugepagesの設定が必要）
+    HugeTlb,
+}
+
+/// ヒュージページのサイズ（x86_64 / aarch64の既定値）
+pub const HUGE_PAGE_SIZE: usize = 2 << 20;
+
+/// mmapで確保したバッファ（Dropでmunmapする）
+pub struct MmapBuffer {
+    ptr: NonNull<u8>,
+    len: usize,
+    /// マッピングの先頭（ガードページを置くときはptrより前になる）
+    base: NonNull<u8>,
+    /// マップした長さ（ページサイズに切り上げたもの）
+    mapped: usize,
+}
+
+// SAFETY: マップした領域を排他的に所有している
+unsafe impl Send for MmapBuffer {}
+unsafe impl Sync for MmapBuffer {}
+
+impl Deref for MmapBuffer {
+    type Target = [u8];
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        // SAFETY: len バイトはマップ済みでゼロ初期化されている
+        unsafe { std::slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl DerefMut for MmapBuffer {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        // SAFETY: len バイトはマップ済みでゼロ初期化されている
+        unsafe { std::slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl Buffer for MmapBuffer {
+    unsafe fn ptr(&self) -> *mut u8 {
+        self.ptr.as_ptr()
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for MmapBuffer {
+    fn drop(&mut self) {
+        // SAFETY: mmapで確保した領域をそのまま返す
+        unsafe { libc::munmap(self.base.as_ptr().cast(), self.mapped) };
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+impl Drop for MmapBuffer {
+    fn drop(&mut self) {}
+}
+
+/// ヒュージページでバッファを確保するアロケータ
+/// 大きなI/Oバッファをプールするとき、TLBミスを減らせる
+/// 確保時に全ページへ書き込んでページフォルトを済ませておく
+#[derive(Debug, Clone, Copy)]
+pub struct HugePageAllocator {
+    len: usize,
+    mode: PageMode,
+}
+
+impl HugePageAllocator {
+    pub fn new(len: usize, mode: PageMode) -> Self {
+        HugePageAllocator { len, mode }
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl BufferAllocator for HugePageAllocator {
+    type Buffer = MmapBuffer;
+    type Error = std::io::Error;
+
+    fn allocate(&self) -> Result<Self::Buffer, Self::Error> {
+        let mapped = self.len.max(1).div_ceil(HUGE_PAGE_SIZE) * HUGE_PAGE_SIZE;
+        let mmap = |len: usize, extra_flags: libc::c_int| {
+            // SAFETY: 新しい匿名マッピングを作るだけ
+            let ptr = unsafe {
+                libc::mmap(
+                    std::ptr::null_mut(),
+                    len,
+                    libc::PROT_READ | libc::PROT_WRITE,
+                    libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | extra_flags,
+                    -1,
+                    0,
+                )
+            };
+            if ptr == libc::MAP_FAILED {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(ptr.cast::<u8>())
+        };
+
+        let ptr = match self.mode {
+            PageMode::HugeTlb => mmap(mapped, libc::MAP_HUGETLB)?,
+            PageMode::Base | PageMode::Transparent => {
+                // THPはヒュージページ境界に揃った範囲にしか使われないので、余分にマップして前後を返す
+                let raw = mmap(mapped + HUGE_PAGE_SIZE, 0)?;
+                let head = raw.align_offset(HUGE_PAGE_SIZE);
+                // SAFETY: 揃えた先頭の前後はマップした範囲の内側
+                unsafe {
+                    if head > 0 {
+                        libc::munmap(raw.cast(), head);
+                    }
+                    libc::munmap(raw.add(head + mapped).cast(), HUGE_PAGE_SIZE - head);
+                }
+                let ptr = raw.wrapping_add(head);
+                let advice = match self.mode {
+                    PageMode::Transparent => libc::MADV_HUGEPAGE,
+                    _ => libc::MADV_NOHUGEPAGE,
+                };
+                // SAFETY: ptrからmappedバイトはマップ済み
+                if unsafe { libc::madvise(ptr.cast(), mapped, advice) } != 0 {
+                    let err = std::io::Error::last_os_error();
+                    unsafe { libc::munmap(ptr.cast(), mapped) };
+                    return Err(err);
+                }
+                ptr
+            }
+        };
+
+        // 4Kごとに書き込んでページフォルトを先に済ませる
+        for offset in (0..mapped).step_by(4096) {
+            // SAFETY: offsetはマップした範囲の内側
+            unsafe { ptr.add(offset).write_volatile(0) };
+        }
+        // SAFETY: mmapが成功していればnullではない
+        let ptr = unsafe { NonNull::new_unchecked(ptr) };
+        Ok(MmapBuffer {
+            ptr,
+            len: self.len,
+            base: ptr,
+            mapped,
+        })
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+impl BufferAllocator for HugePageAllocator {
+    type Buffer = MmapBuffer;
+    type Error = std::io::Error;
+
+    fn allocate(&self) -> Result<Self::Buffer, Self::Error> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "huge page buffers are only supported on Linux",
+        ))
+    }
+}
+
+/// 各バッファの直後にアクセスできないガードページを置くアロケータ（デバッグ用）
+/// バッファの末尾をガードページにぴったり付けるので、末尾を越えた読み書きはその場でSIGSEGVになる
+/// （その代わり、lenがページサイズの倍数でなければ先頭はページ境界に揃わない）
+#[derive(Debug, Clone, Copy)]
+pub struct GuardedAllocator {
+    len: usize,
+}
+
+impl GuardedAllocator {
+    pub fn new(len: usize) -> Self {
+        GuardedAllocator { len }
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl BufferAllocator for GuardedAllocator {
+    type Buffer = MmapBuffer;
+    type Error = std::io::Error;
+
+    fn allocate(&self) -> Result<Self::Buffer, Self::Error> {
+        // SAFETY: 引数のないsysconf
+        let page = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+        let data = self.len.max(1).div_ceil(page) * page;
+        let mapped = data + page;
+        // SAFETY: 新しい匿名マッピングを作るだけ
+        let base = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                mapped,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        if base == libc::MAP_FAILED {
+            return Err(std::io::Error::last_os_error());
+        }
+        let base = base.cast::<u8>();
+        // SAFETY: 最後のページはマップした範囲の内側
+        if unsafe { libc::mprotect(base.add(data).cast(), page, libc::PROT_NONE) } != 0 {
+            let err = std::io::Error::last_os_error();
+            unsafe { libc::munmap(base.cast(), mapped) };
+            return Err(err);
+        }
+        Ok(MmapBuffer {
+            // SAFETY: mmapが成功していればnullではなく、data - len はマップした範囲の内側
+            ptr: unsafe { NonNull::new_unchecked(base.add(data - self.len)) },
+            len: self.len,
+            base: unsafe { NonNull::new_unchecked(base) },
+            mapped,
+        })
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+impl BufferAllocator for GuardedAllocator {
+    type Buffer = MmapBuffer;
+    type Error = std::io::Error;
+
+    fn allocate(&self) -> Result<Self::Buffer, Self::Error> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "guarded buffers are only supported on Linux",
+        ))
+    }
+}
+
+/// memmap2のマッピングをそのままバッファにする（mmap feature）
+/// ファイルや共有メモリをマップした領域も、ほかのバッファと同じくプールに入れてリースできる
+/// resetしても中身は消さない（ファイルに書いた内容はそのまま残る）
+#[cfg(feature = "mmap")]
+impl Buffer for memmap2::MmapMut {
+    unsafe fn ptr(&self) -> *mut u8 {
+        self.as_ptr() as *mut u8
+    }
+
+    fn as_mut_ptr(&mut self) -> *mut u8 {
+        <[u8]>::as_mut_ptr(self)
+    }
+
+    fn len(&self) -> usize {
+        <[u8]>::len(self)
+    }
+}
+
+/// memmap2でlenバイトずつマップするアロケータ（mmap feature）
+/// anonは匿名マッピング、fileはファイル（/dev/shmやmemfdなら共有メモリ）を先頭からlenバイトずつ区切ってマップする
+#[cfg(feature = "mmap")]
+#[derive(Debug)]
+pub struct MmapMutAllocator {
+    len: usize,
+    /// マップするファイル（Noneなら匿名マッピング）
+    file: Option<std::fs::File>,
+    /// 次のバッファをマップするファイル中の位置
+    /// ファイルを伸ばすまでロックしておき、同時に確保しても縮めないようにする
+    next_offset: std::sync::Mutex<u64>,
+}
+
+#[cfg(feature = "mmap")]
+impl MmapMutAllocator {
+    /// 匿名マッピングでバッファを確保する
+    pub fn anon(len: usize) -> Self {
+        MmapMutAllocator {
+            len,
+            file: None,
+            next_offset: std::sync::Mutex::new(0),
+        }
+    }
+
+    /// 書き込みできるように開いたfileをlenバイトずつ区切ってマップする
+    /// 足りなければファイルを伸ばす。バッファを捨てても区間は再利用しない（プールに戻ったバッファを使い回す）
+    pub fn file(file: std::fs::File, len: usize) -> Self {
+        MmapMutAllocator {
+            len,
+            file: Some(file),
+            next_offset: std::sync::Mutex::new(0),
+        }
+    }
+}
+
+#[cfg(feature = "mmap")]
+impl BufferAllocator for MmapMutAllocator {
+    type Buffer = memmap2::MmapMut;
+    type Error = std::io::Error;
+
+    fn allocate(&self) -> Result<Self::Buffer, Self::Error> {
+        let mut options = memmap2::MmapOptions::new();
+        options.len(self.len);
+        let Some(file) = &self.file else {
+            return options.map_anon();
+        };
+        let mut next_offset = self.next_offset.lock().unwrap();
+        let offset = *next_offset;
+        let end = offset + self.len as u64;
+        // マップする範囲がファイルの末尾を越えると、そこを読み書きしたときにSIGBUSになる
+        if file.metadata()?.len() < end {
+            file.set_len(end)?;
+        }
+        // SAFETY: 区間は他のバッファと重ならない（ファイルを外から縮めたり書き換えたりしないのは呼び出し側の責任）
+        let buffer = unsafe { options.offset(offset).map_mut(file)? };
+        *next_offset = end;
+        Ok(buffer)
+    }
+}
+
+pub trait BufferAllocator {
+    type Buffer;
+    type Error;
+
+    fn allocate(&self) -> Result<Self::Buffer, Self::Error>;
+
+    /// bufferを少なくともlenバイトまでその場で伸ばす（Vecのように伸ばせるバッファ向け）
+    /// 伸ばせないアロケータは何もせずfalseを返す
+    fn grow(&self, _buffer: &mut Self::Buffer, _len: usize) -> Result<bool, Self::Error> {
+        Ok(false)
+    }
+}
+
+/// エントリ: バッファと次の空きインデックスを持つ
+/// trimでバッファを解放したエントリはNoneになり、空きスロットとして再利用を待つ
+struct Entry<T> {
+    buffer: Option<T>,
+    /// 空きの場合、次の空きスロット（バッファなしなら次のバッファなしスロット）のインデックス（usize::MAXで終端）
+    next_free: usize,
+    /// 貸出中のリースの番号（0なら貸し出していない）。デバッグビルドで古いリースの返却を検出する
+    #[cfg(debug_assertions)]
+    lease_id: u64,
+    /// フリーリストに入っているか。デバッグビルドで同じエントリの二重の返却を検出する
+    #[cfg(debug_assertions)]
+    in_free: bool,
+    /// フリーリストに入れた時刻（with_idle_ttlを設定したときだけ記録する）
+    idle_since: Option<Instant>,
+    /// 貸し出した時刻（貸出中だけSome）
+    #[cfg(feature = "lease-timing")]
+    leased_at: Option<Instant>,
+    /// into_sendのリースにバッファを渡している（バッファはエントリになく、貸出中のまま）
+    sent: bool,
+}
+
+const FREE_LIST_END: usize = usize::MAX;
+
+/// 最初のチャンクのエントリ数（2のべき乗）
+const FIRST_CHUNK: usize = 16;
+
+/// エントリの配列（チャンクに分けて持ち、チャンクkはFIRST_CHUNK << k個のエントリを持つ）
+/// チャンクは確保した容量を超えて伸ばさないので、エントリを追加しても既存のエントリは動かない
+/// [u8; N]のようにエントリの中に置かれるバッファも、貸出中はアドレスが変わらない
+struct Entries<T> {
+    chunks: Vec<Vec<Entry<T>>>,
+    len: usize,
+}
+
+impl<T> Entries<T> {
+    const fn new() -> Self {
+        Entries {
+            chunks: Vec::new(),
+            len: 0,
+        }
+    }
+
+    /// インデックスを（チャンク、チャンク内の位置）に変える
+    #[inline]
+    fn locate(index: usize) -> (usize, usize) {
+        let i = index + FIRST_CHUNK;
+        let chunk = (i.ilog2() - FIRST_CHUNK.ilog2()) as usize;
+        (chunk, i - (FIRST_CHUNK << chunk))
+    }
+
+    #[inline]
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    #[cfg(debug_assertions)]
+    fn get_mut(&mut self, index: usize) -> Option<&mut Entry<T>> {
+        (index < self.len).then(|| &mut self[index])
+    }
+
+    fn last(&self) -> Option<&Entry<T>> {
+        self.len.checked_sub(1).map(|index| &self[index])
+    }
+
+    fn push(&mut self, entry: Entry<T>) {
+        let (chunk, _) = Self::locate(self.len);
+        if chunk == self.chunks.len() {
+            self.chunks.push(Vec::with_capacity(FIRST_CHUNK << chunk));
+        }
+        // チャンクの容量内なので再確保しない
+        self.chunks[chunk].push(entry);
+        self.len += 1;
+    }
+
+    fn pop(&mut self) -> Option<Entry<T>> {
+        let index = self.len.checked_sub(1)?;
+        self.len = index;
+        self.chunks[Self::locate(index).0].pop()
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &Entry<T>> {
+        self.chunks.iter().flatten()
+    }
+
+    fn iter_mut(&mut self) -> impl Iterator<Item = &mut Entry<T>> {
+        self.chunks.iter_mut().flatten()
+    }
+
+    /// 空になった末尾のチャンクを解放する（使っているチャンクは縮めない）
+    fn shrink_to_fit(&mut self) {
+        let used = self
+            .len
+            .checked_sub(1)
+            .map_or(0, |last| Self::locate(last).0 + 1);
+        self.chunks.truncate(used);
+        self.chunks.shrink_to_fit();
+    }
+}
+
+impl<T> Index<usize> for Entries<T> {
+    type Output = Entry<T>;
+
+    #[inline]
+    fn index(&self, index: usize) -> &Entry<T> {
+        let (chunk, offset) = Self::locate(index);
+        let chunk = &self.chunks[chunk];
+        assert!(offset < chunk.len());
+        // チャンクをスライスとして借りず、エントリだけを借りる（貸出中の他のエントリのバッファへの参照を無効にしない）
+        // SAFETY: offsetはチャンクの長さより小さい
+        unsafe { &*chunk.as_ptr().add(offset) }
+    }
+}
+
+impl<T> IndexMut<usize> for Entries<T> {
+    #[inline]
+    fn index_mut(&mut self, index: usize) -> &mut Entry<T> {
+        let (chunk, offset) = Self::locate(index);
+        let chunk = &mut self.chunks[chunk];
+        assert!(offset < chunk.len());
+        // SAFETY: offsetはチャンクの長さより小さい
+        unsafe { &mut *chunk.as_mut_ptr().add(offset) }
+    }
+}
+
+/// 返されたバッファをいつBuffer::resetするか
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ResetPolicy {
+    /// resetしない（前の利用者が書いた内容のまま貸し出す）
+    #[default]
+    Never,
+    /// リースが返されたときにresetする（空きバッファは常にreset済み）
+    OnReturn,
+    /// フリーリストから貸し出すときにresetする（使われないまま解放されるバッファはresetしない）
+    OnLease,
+}
+
+/// 返されたバッファ（オブジェクト）をresetする関数
+enum ResetFn<B> {
+    /// with_reset_policyで設定したBuffer::try_reset
+    Buffer(fn(&mut B) -> std::io::Result<()>),
+    /// with_resetで設定した、失敗しない関数
+    Object(fn(&mut B)),
+}
+
+impl<B> ResetFn<B> {
+    #[inline]
+    fn call(&self, buffer: &mut B) -> std::io::Result<()> {
+        match *self {
+            ResetFn::Buffer(reset) => reset(buffer),
+            ResetFn::Object(reset) => {
+                reset(buffer);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// ポイズンで埋める関数と、埋めた内容が書き換えられた位置を探す関数
+#[cfg(feature = "poison")]
+type PoisonFns<B> = (fn(&mut B), fn(&B) -> Option<usize>);
+
+/// PoolInnerを包むセル
+/// 通常はUnsafeCellで借用を検査しない。checked featureではRefCellにして、プールの中で同時に2度借りたらpanicさせる
+/// （テストやMiriで借用を検査しながら動かし、出荷するビルドは検査なしにする）
+struct PoolCell<T> {
+    #[cfg(not(feature = "checked"))]
+    cell: UnsafeCell<T>,
+    #[cfg(feature = "checked")]
+    cell: std::cell::RefCell<T>,
+}
+
+/// PoolCell::borrow_mutの結果（checked featureではRefMut）
+#[cfg(not(feature = "checked"))]
+struct PoolRefMut<'a, T>(&'a mut T);
+#[cfg(feature = "checked")]
+type PoolRefMut<'a, T> = std::cell::RefMut<'a, T>;
+
+/// PoolCell::borrowの結果（checked featureではRef）
+#[cfg(not(feature = "checked"))]
+struct PoolRef<'a, T>(&'a T);
+#[cfg(feature = "checked")]
+type PoolRef<'a, T> = std::cell::Ref<'a, T>;
+
+#[cfg(not(feature = "checked"))]
+impl<T> Deref for PoolRefMut<'_, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        self.0
+    }
+}
+
+#[cfg(not(feature = "checked"))]
+impl<T> Deref for PoolRef<'_, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        self.0
+    }
+}
+
+#[cfg(not(feature = "checked"))]
+impl<T> DerefMut for PoolRefMut<'_, T> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut T {
+        self.0
+    }
+}
+
+// RefMut・Refと同じく、dropで借用を終える（検査しないので何もしない）
+#[cfg(not(feature = "checked"))]
+impl<T> Drop for PoolRefMut<'_, T> {
+    #[inline]
+    fn drop(&mut self) {}
+}
+
+#[cfg(not(feature = "checked"))]
+impl<T> Drop for PoolRef<'_, T> {
+    #[inline]
+    fn drop(&mut self) {}
+}
+
+impl<T> PoolCell<T> {
+    fn new(value: T) -> Self {
+        PoolCell {
+            #[cfg(not(feature = "checked"))]
+            cell: UnsafeCell::new(value),
+            #[cfg(feature = "checked")]
+            cell: std::cell::RefCell::new(value),
+        }
+    }
+
+    #[inline]
+    fn borrow_mut(&self) -> PoolRefMut<'_, T> {
+        // SAFETY: シングルスレッド前提、借りている間にプールの中から同じセルを借り直さない
+        #[cfg(not(feature = "checked"))]
+        return PoolRefMut(unsafe { &mut *self.cell.get() });
+        #[cfg(feature = "checked")]
+        return self.cell.borrow_mut();
+    }
+
+    #[inline]
+    fn borrow(&self) -> PoolRef<'_, T> {
+        // SAFETY: borrow_mutと同じ
+        #[cfg(not(feature = "checked"))]
+        return PoolRef(unsafe { &*self.cell.get() });
+        #[cfg(feature = "checked")]
+        return self.cell.borrow();
+    }
+
+    #[inline]
+    fn get_mut(&mut self) -> &mut T {
+        self.cell.get_mut()
+    }
+
+    /// 借用を検査せずに中身を指すポインタ（作った後に書き換えないフィールドを読むとき）
+    #[inline]
+    fn as_ptr(&self) -> *mut T {
+        #[cfg(not(feature = "checked"))]
+        return self.cell.get();
+        #[cfg(feature = "checked")]
+        return self.cell.as_ptr();
+    }
+}
+
+/// 内部プール状態（PoolCellで包む - シングルスレッド前提）
+struct PoolInner<A: BufferAllocator> {
+    allocator: A,
+    entries: Entries<A::Buffer>,
+    /// フリーリストの先頭（usize::MAXで空）
+    free_head: usize,
+    /// バッファを解放したエントリのリストの先頭（usize::MAXで空）
+    vacant_head: usize,
+    /// バッファを解放したエントリの数
+    vacant: usize,
+    /// フリーリストにある空きバッファの数
+    idle: usize,
+    /// 貸し出した回数
+    leases: u64,
+    /// フリーリストから貸し出した回数
+    reused: u64,
+    /// 新しく確保したバッファ数（reserveで確保したものを含む）
+    allocated: u64,
+    /// 同時に貸し出したバッファ数の最大（reset_peakで今の貸出数に戻す）
+    peak_live: usize,
+    /// 最後に振ったリースの番号
+    #[cfg(debug_assertions)]
+    last_lease_id: u64,
+    /// 確保するバッファ数の上限（try_lease / lease_asyncはこれを超えて確保しない）
+    max_capacity: usize,
+    /// lease_asyncで空きを待っているタスク
+    waiters: Vec<Waker>,
+    /// 空きバッファを残しておく時間（with_idle_ttlで設定したときだけSome）
+    idle_ttl: Option<Duration>,
+    /// 最も古い空きバッファの期限（空きバッファがなければNone）
+    next_expiry: Option<Instant>,
+    /// 前もって確保しておく空きバッファの数（with_min_idleで設定する。デフォルトは0）
+    min_idle: usize,
+    /// 返されたバッファをresetするタイミング
+    reset_policy: ResetPolicy,
+    /// バッファのreset（A::BufferがBufferを実装しなくても持てるよう関数ポインタにしておく）
+    reset: ResetFn<A::Buffer>,
+    /// 返却時のresetに失敗してバッファを捨てたときのエラー（次のleaseで返す）
+    reset_errors: Vec<std::io::Error>,
+    /// min_idle個まで確保し直すのに失敗したときのエラー（次のleaseで返す）
+    refill_error: Option<A::Error>,
+    /// リースと返却のたびに確かめる機能（返却キュー・期限・min_idle・reset・オブザーバ・待ちタスク・spill）を使っているか
+    /// falseならそれらを一度も確かめない。一度trueにしたら戻さない
+    hooks: bool,
+    /// ポイズン検査（with_poisonで有効にしたときだけSome）
+    #[cfg(feature = "poison")]
+    poison: Option<PoisonFns<A::Buffer>>,
+    /// イベントを知らせるフック（with_observerで設定したときだけSome）
+    observer: Option<Observer<A::Buffer>>,
+    /// バッファのメモリを登録・解除するフック（with_registrarで設定したときだけSome）
+    registrar: Option<Box<dyn MemoryRegistrar<A> + Send>>,
+    /// 別スレッドから返されるリースのキュー（最初のinto_sendで作る）
+    returns: Option<ReturnChannel<A::Buffer>>,
+    /// 空きバッファの上限と、超えた分の渡し先（ThreadLocalBufferPoolのローカルプールだけが設定する）
+    spill: Option<Spill<A>>,
+}
+
+/// リースの返却で空きバッファがmax_idle個を超えたら、超えた分をプールのアロケータと一緒にtoへ渡す
+struct Spill<A: BufferAllocator> {
+    max_idle: usize,
+    to: fn(&A, Vec<A::Buffer>),
+}
+
+/// into_sendのリースに渡す返却キューと、プール側の受け口
+type ReturnChannel<B> = (Sender<Returned<B>>, Receiver<Returned<B>>);
+
+/// プールに設定したPoolObserverと、バッファの長さを読む関数
+struct Observer<B> {
+    hook: Box<dyn PoolObserver + Send>,
+    size: fn(&B) -> usize,
+}
+
+impl<A: BufferAllocator> PoolInner<A> {
+    /// オブザーバがあれば、indexのバッファの長さを添えてイベントを知らせる
+    #[inline]
+    fn observe(&self, index: usize, event: impl FnOnce(&dyn PoolObserver, usize)) {
+        if let (Some(observer), Some(buffer)) = (&self.observer, &self.entries[index].buffer) {
+            event(observer.hook.as_ref(), (observer.size)(buffer));
+        }
+    }
+
+    /// indexのリースが返されたので、貸し出していた時間をオブザーバに知らせる
+    #[cfg(feature = "lease-timing")]
+    #[inline]
+    fn end_hold(&mut self, index: usize) {
+        if let Some(since) = self.entries[index].leased_at.take() {
+            if let Some(observer) = &self.observer {
+                observer.hook.on_hold(since.elapsed());
+            }
+        }
+    }
+
+    /// 登録フックがあれば、プールに入れるバッファを登録する
+    #[inline]
+    fn register(&self, buffer: &mut A::Buffer) -> Result<(), A::Error> {
+        match &self.registrar {
+            Some(registrar) => registrar.register(buffer),
+            None => Ok(()),
+        }
+    }
+
+    /// 登録フックがあれば、プールが手放すindexのバッファの登録を解除する
+    #[inline]
+    fn deregister(&mut self, index: usize) {
+        if let (Some(registrar), Some(buffer)) =
+            (&self.registrar, self.entries[index].buffer.as_mut())
+        {
+            registrar.deregister(buffer);
+        }
+    }
+}
+
+/// leaseを呼んでからリースを返すまでの時間を測る（lease-timingを有効にしなければ何も持たない）
+#[derive(Clone, Copy)]
+struct LeaseClock {
+    #[cfg(feature = "lease-timing")]
+    started: Instant,
+}
+
+impl LeaseClock {
+    #[inline]
+    fn start() -> Self {
+        LeaseClock {
+            #[cfg(feature = "lease-timing")]
+            started: Instant::now(),
+        }
+    }
+}
+
+/// バッファを使い回すシングルスレッド用のプール
+/// 貸出中のバッファはリースが生きている間アドレスが変わらない（プールが伸びてもエントリは動かない）
+/// [u8; N]のようにバッファ自体を値で持つアロケータでも、リースのptrをカーネルやデバイスに渡せる
+pub struct BufferPool<A: BufferAllocator> {
+    inner: PoolCell<PoolInner<A>>,
+}
+
+pub struct Lease<'a, A: BufferAllocator> {
+    /// プールへの参照（ライフタイムで生存を保証）
+    pool: &'a BufferPool<A>,
+    /// このリースが持っているエントリのインデックス
+    index: usize,
+    /// リースの番号（デバッグビルドで古いリースの返却を検出する）
+    #[cfg(debug_assertions)]
+    lease_id: u64,
+    /// PhantomDataで不変性を保証
+    _marker: PhantomData<&'a mut A::Buffer>,
+}
+
+impl<A: BufferAllocator> BufferPool<A> {
+    pub fn new(allocator: A) -> Self {
+        Self::with_max_capacity(allocator, usize::MAX)
+    }
+
+    /// 確保するバッファ数に上限を設けたプール
+    /// 上限に達して空きがなければ、try_leaseはPoolExhaustedを返し、lease_asyncは他のリースが返されるまで待つ
+    /// （leaseは従来どおり上限を超えても確保する）
+    pub fn with_max_capacity(allocator: A, max_capacity: usize) -> Self {
+        BufferPool {
+            inner: PoolCell::new(PoolInner {
+                allocator,
+                entries: Entries::new(),
+                free_head: FREE_LIST_END,
+                vacant_head: FREE_LIST_END,
+                vacant: 0,
+                idle: 0,
+                leases: 0,
+                reused: 0,
+                allocated: 0,
+                peak_live: 0,
+                #[cfg(debug_assertions)]
+                last_lease_id: 0,
+                max_capacity,
+                waiters: Vec::new(),
+                idle_ttl: None,
+                next_expiry: None,
+                min_idle: 0,
+                reset_policy: ResetPolicy::Never,
+                reset: ResetFn::Object(|_| {}),
+                reset_errors: Vec::new(),
+                refill_error: None,
+                // lease-timingは返却のたびに借りられていた時間を測る
+                hooks: cfg!(feature = "lease-timing"),
+                #[cfg(feature = "poison")]
+                poison: None,
+                observer: None,
+                registrar: None,
+                returns: None,
+                spill: None,
+            }),
+        }
+    }
+
+    /// 返されたオブジェクトをresetでpolicyのタイミングに初期化し直す
+    /// Bufferを実装しない型（ObjectPoolのパーサや作業用の構造体など）に使う
+    pub fn with_reset(mut self, policy: ResetPolicy, reset: fn(&mut A::Buffer)) -> Self {
+        let inner = self.inner.get_mut();
+        inner.reset_policy = policy;
+        inner.reset = ResetFn::Object(reset);
+        inner.hooks |= policy != ResetPolicy::Never;
+        self
+    }
+
+    /// ttlより長く使われていない空きバッファを、次のlease（またはmaintain）で解放する
+    /// 一時的に多く借りたあとも、ピーク時のバッファを持ち続けない
+    pub fn with_idle_ttl(mut self, ttl: Duration) -> Self {
+        let inner = self.inner.get_mut();
+        inner.idle_ttl = Some(ttl);
+        inner.hooks = true;
+        self
+    }
+
+    /// 空きバッファがn個を下回ったら、リースの返却時とmaintainで前もってn個まで確保し直す
+    /// バーストのあとの最初のリースが確保を待たない（with_idle_ttlでもn個は解放しない）
+    pub fn with_min_idle(mut self, n: usize) -> Self {
+        let inner = self.inner.get_mut();
+        inner.min_idle = n;
+        inner.hooks = true;
+        self
+    }
+
+    /// プールに入れるバッファをregistrarで登録し、プールから手放す前に登録を解除する
+    /// （RDMAのNICやSPDKのように、I/Oに使うメモリを前もって登録しておくスタック向け）
+    /// 登録したメモリは動かせないので、ensure_capacityはGrowError::Unsupportedを返す
+    /// 確保済みのバッファがあるとpanicする（reserveより前に設定する）
+    pub fn with_registrar(mut self, registrar: impl MemoryRegistrar<A> + Send + 'static) -> Self {
+        let inner = self.inner.get_mut();
+        assert!(
+            inner.entries.len() == inner.vacant,
+            "with_registrar must be set before the pool allocates buffers"
+        );
+        inner.registrar = Some(Box::new(registrar));
+        self
+    }
+
+    /// 期限を過ぎた空きバッファを解放し、解放したバッファ数を返す（with_idle_ttlを設定していなければ何もしない）
+    /// leaseが呼ばれない間もメモリを返したいとき、タイマーなどから定期的に呼ぶ
+    pub fn maintain(&self) -> usize {
+        self.drain_returns();
+        let expired = self.expire_idle(Instant::now());
+        self.refill();
+        expired
+    }
+
+    /// 空きバッファがmin_idleを下回っていれば、min_idle個まで確保する
+    /// 確保に失敗したらエラーを残しておき、次のleaseがLeaseError::Allocateとして返す
+    #[inline]
+    fn refill(&self) {
+        let inner = self.inner.borrow();
+        let min_idle = inner.min_idle;
+        let idle = inner.idle;
+        drop(inner);
+        if idle < min_idle {
+            if let Err(err) = self.reserve(min_idle) {
+                self.inner.borrow_mut().refill_error = Some(err);
+            }
+        }
+    }
+
+    /// 最も古い空きバッファが期限を過ぎていれば、期限切れの空きバッファを解放する
+    #[inline]
+    fn expire_if_due(&self) {
+        let next_expiry = self.inner.borrow().next_expiry;
+        if let Some(at) = next_expiry {
+            let now = Instant::now();
+            if now >= at {
+                self.expire_idle(now);
+            }
+        }
+    }
+
+    /// nowの時点で期限切れの空きバッファを解放する
+    /// フリーリストは新しく返された順に並んでいるので、期限切れは末尾にまとまっている
+    /// min_idle個に満たないうちは期限切れでも残し、期限を延ばす
+    fn expire_idle(&self, now: Instant) -> usize {
+        let mut guard = self.inner.borrow_mut();
+        let inner = &mut *guard;
+        let Some(ttl) = inner.idle_ttl else {
+            return 0;
+        };
+        let min_idle = inner.min_idle;
+        let mut keep = 0;
+        let mut oldest = None;
+        let mut idx = inner.free_head;
+        while idx != FREE_LIST_END {
+            let entry = &mut inner.entries[idx];
+            match entry.idle_since {
+                Some(since) if since + ttl <= now => {
+                    if keep >= min_idle {
+                        break;
+                    }
+                    entry.idle_since = Some(now);
+                    oldest = oldest.or(Some(now));
+                }
+                since => oldest = since.or(oldest),
+            }
+            keep += 1;
+            idx = entry.next_free;
+        }
+        inner.next_expiry = oldest.map(|since| since + ttl);
+        drop(guard);
+        self.trim(keep)
+    }
+
+    /// バッファを借りる。空きがなければ上限に関わらず新しく確保する
+    #[inline]
+    pub fn lease(&self) -> Result<Lease<'_, A>, LeaseError<A::Error>> {
+        let clock = LeaseClock::start();
+        let (index, reused) = match self.take_free()? {
+            Some(index) => (index, true),
+            None => (self.grow().map_err(LeaseError::Allocate)?, false),
+        };
+        Ok(self.lease_at(index, reused, clock))
+    }
+
+    /// バッファを借りる。上限に達して空きがなければ、確保せずにPoolExhaustedを返す
+    #[inline]
+    pub fn try_lease(&self) -> Result<Lease<'_, A>, TryLeaseError<A::Error>> {
+        let clock = LeaseClock::start();
+        let (index, reused) = match self.take_free()? {
+            Some(index) => (index, true),
+            None if self.is_full() => return Err(TryLeaseError::PoolExhausted),
+            None => (self.grow().map_err(TryLeaseError::Allocate)?, false),
+        };
+        Ok(self.lease_at(index, reused, clock))
+    }
+
+    /// バッファを借りる。上限に達して空きがなければ、他のリースが返されるまで待つ
+    pub fn lease_async(&self) -> LeaseFuture<'_, A> {
+        LeaseFuture {
+            pool: self,
+            clock: LeaseClock::start(),
+        }
+    }
+
+    /// バッファを確保するアロケータ
+    pub fn allocator(&self) -> &A {
+        // SAFETY: allocatorは作った後に書き換えない
+        unsafe { &(*self.inner.as_ptr()).allocator }
+    }
+
+    #[inline]
+    #[cfg_attr(not(feature = "lease-timing"), allow(unused_variables))]
+    fn lease_at(&self, index: usize, reused: bool, clock: LeaseClock) -> Lease<'_, A> {
+        let mut guard = self.inner.borrow_mut();
+        let inner = &mut *guard;
+        inner.leases += 1;
+        let live = inner.entries.len() - inner.vacant - inner.idle;
+        inner.peak_live = inner.peak_live.max(live);
+        if inner.hooks {
+            inner.observe(index, |o, bytes| o.on_lease(bytes, reused));
+        }
+        #[cfg(feature = "lease-timing")]
+        {
+            let now = Instant::now();
+            inner.entries[index].leased_at = Some(now);
+            if let Some(observer) = &inner.observer {
+                observer.hook.on_acquire(now - clock.started);
+            }
+        }
+        #[cfg(debug_assertions)]
+        let lease_id = {
+            inner.last_lease_id += 1;
+            inner.entries[index].lease_id = inner.last_lease_id;
+            inner.last_lease_id
+        };
+        Lease {
+            pool: self,
+            index,
+            #[cfg(debug_assertions)]
+            lease_id,
+            _marker: PhantomData,
+        }
+    }
+
+    /// 返されたリースがエントリを今も持っているか確かめ、エントリを貸出中でなくする
+    /// 古いリースや二重に返されたリースは、フリーリストを壊す前にpanicさせる
+    #[cfg(debug_assertions)]
+    fn check_lease(&self, index: usize, lease_id: u64) {
+        let mut guard = self.inner.borrow_mut();
+        let inner = &mut *guard;
+        match inner.entries.get_mut(index) {
+            Some(entry) if entry.lease_id == lease_id => entry.lease_id = 0,
+            _ => panic!(
+                "lease {} of buffer {} was returned after the entry was returned or reused",
+                lease_id, index
+            ),
+        }
+    }
+
+    /// フリーリストから空きスロットを取り出す
+    /// 返却時のresetやmin_idleまでの確保に失敗したエラーが残っていれば先に返す
+    /// 貸し出し時のresetに失敗したらバッファを捨ててエラーを返す
+    #[inline]
+    fn take_free(&self) -> Result<Option<usize>, LeaseError<A::Error>> {
+        let hooks = self.inner.borrow().hooks;
+        if hooks {
+            self.drain_returns();
+            self.expire_if_due();
+        }
+        let mut guard = self.inner.borrow_mut();
+        let inner = &mut *guard;
+        if hooks {
+            if let Some(err) = inner.reset_errors.pop() {
+                return Err(LeaseError::Reset(err));
+            }
+            if let Some(err) = inner.refill_error.take() {
+                return Err(LeaseError::Allocate(err));
+            }
+        }
+        if inner.free_head == FREE_LIST_END {
+            return Ok(None);
+        }
+        let idx = inner.free_head;
+        inner.free_head = inner.entries[idx].next_free;
+        inner.entries[idx].next_free = FREE_LIST_END; // 使用中マーク
+        #[cfg(debug_assertions)]
+        {
+            inner.entries[idx].in_free = false;
+        }
+        inner.idle -= 1;
+        #[cfg(feature = "poison")]
+        if let (Some((_, check)), Some(buffer)) = (inner.poison, inner.entries[idx].buffer.as_ref())
+        {
+            if let Some(offset) = check(buffer) {
+                panic!(
+                    "buffer {} was written at offset {} after it was returned to the pool",
+                    idx, offset
+                );
+            }
+        }
+        if hooks && inner.reset_policy == ResetPolicy::OnLease {
+            if let Some(buffer) = inner.entries[idx].buffer.as_mut() {
+                if let Err(err) = inner.reset.call(buffer) {
+                    drop(guard);
+                    self.discard(idx);
+                    return Err(LeaseError::Reset(err));
+                }
+            }
+        }
+        inner.reused += 1;
+        Ok(Some(idx))
+    }
+
+    /// 使えなくなったバッファを捨て、エントリをバッファのないスロットにする
+    fn discard(&self, index: usize) {
+        let mut guard = self.inner.borrow_mut();
+        let inner = &mut *guard;
+        inner.observe(index, |o, bytes| o.on_free(bytes));
+        inner.deregister(index);
+        inner.entries[index].buffer = None;
+        inner.entries[index].next_free = inner.vacant_head;
+        inner.vacant_head = index;
+        inner.vacant += 1;
+        drop(guard);
+        // 上限に空きができたので、lease_asyncで待っているタスクは新しく確保できる
+        self.wake_waiters();
+    }
+
+    /// 新しくバッファを確保してエントリに加える
+    /// trimでバッファを解放したスロットがあればそこを使う
+    #[inline]
+    fn grow(&self) -> Result<usize, A::Error> {
+        let mut guard = self.inner.borrow_mut();
+        let inner = &mut *guard;
+        let mut buf = inner.allocator.allocate()?;
+        // 登録に失敗したバッファはプールに入れずに解放する
+        inner.register(&mut buf)?;
+        inner.allocated += 1;
+        drop(guard);
+        Ok(self.insert(buf))
+    }
+
+    /// バッファをエントリに加える（バッファのないスロットがあればそこを使う）
+    fn insert(&self, buf: A::Buffer) -> usize {
+        let mut guard = self.inner.borrow_mut();
+        let inner = &mut *guard;
+        if inner.vacant_head != FREE_LIST_END {
+            let idx = inner.vacant_head;
+            inner.vacant_head = inner.entries[idx].next_free;
+            inner.vacant -= 1;
+            inner.entries[idx].buffer = Some(buf);
+            inner.entries[idx].next_free = FREE_LIST_END;
+            inner.observe(idx, |o, bytes| o.on_allocate(bytes));
+            return idx;
+        }
+        let idx = inner.entries.len();
+        inner.entries.push(Entry {
+            buffer: Some(buf),
+            next_free: FREE_LIST_END,
+            #[cfg(debug_assertions)]
+            lease_id: 0,
+            #[cfg(debug_assertions)]
+            in_free: false,
+            idle_since: None,
+            #[cfg(feature = "lease-timing")]
+            leased_at: None,
+            sent: false,
+        });
+        inner.observe(idx, |o, bytes| o.on_allocate(bytes));
+        idx
+    }
+
+    /// 貸出中のエントリからバッファを取り出してinto_sendのリースに渡す（エントリは貸出中のまま）
+    /// 返却キュー（なければ作る）とバッファを返す
+    fn send(&self, index: usize) -> (Sender<Returned<A::Buffer>>, A::Buffer) {
+        let mut guard = self.inner.borrow_mut();
+        let inner = &mut *guard;
+        let (sender, _) = inner
+            .returns
+            .get_or_insert_with(crossbeam_channel::unbounded);
+        let sender = sender.clone();
+        inner.hooks = true;
+        let entry = &mut inner.entries[index];
+        entry.sent = true;
+        let buffer = entry.buffer.take().expect("leased entry has no buffer");
+        (sender, buffer)
+    }
+
+    /// 別スレッドから返されたリースのバッファをエントリに戻し、リースを返す
+    #[inline]
+    fn drain_returns(&self) {
+        let guard = self.inner.borrow();
+        let inner = &*guard;
+        let Some((_, receiver)) = &inner.returns else {
+            return;
+        };
+        if receiver.is_empty() {
+            return;
+        }
+        let receiver = receiver.clone();
+        drop(guard);
+        for returned in receiver.try_iter() {
+            let mut guard = self.inner.borrow_mut();
+            let entry = &mut guard.entries[returned.index];
+            entry.buffer = Some(returned.buffer);
+            entry.sent = false;
+            drop(guard);
+            #[cfg(debug_assertions)]
+            self.check_lease(returned.index, returned.lease_id);
+            self.return_entry(returned.index);
+        }
+    }
+
+    /// 確保済みのバッファ数が上限に達しているか
+    #[inline]
+    fn is_full(&self) -> bool {
+        let inner = self.inner.borrow();
+        inner.entries.len() - inner.vacant >= inner.max_capacity
+    }
+
+    /// 空きバッファがn個になるまで前もって確保しておく（slab_warmと同じく、最初のリースで確保を待たない）
+    /// 確保するバッファ数の上限に達したらそこで止める
+    pub fn reserve(&self, n: usize) -> Result<(), A::Error> {
+        loop {
+            let idle = self.inner.borrow().idle;
+            if idle >= n || self.is_full() {
+                return Ok(());
+            }
+            let index = self.grow()?;
+            self.release(index);
+        }
+    }
+
+    /// 空きバッファをkeep個だけ残して解放し、解放したバッファ数を返す
+    /// 残すのは次に貸し出される順に先頭からkeep個。末尾のバッファのないエントリは切り詰める
+    /// （リースはインデックスを持つので、貸出中のエントリより前は詰められない）
+    pub fn trim(&self, keep: usize) -> usize {
+        let mut position = 0;
+        self.retain_free(
+            |_| {
+                position += 1;
+                position <= keep
+            },
+            drop,
+        )
+    }
+
+    /// trimと同じく空きバッファをkeep個だけ残し、残さなかったバッファを解放せずに取り出す
+    /// （別のプールへ移すとき）
+    pub fn take_idle(&self, keep: usize) -> Vec<A::Buffer> {
+        let mut position = 0;
+        let mut taken = Vec::new();
+        self.retain_free(
+            |_| {
+                position += 1;
+                position <= keep
+            },
+            |buffer| taken.push(buffer),
+        );
+        taken
+    }
+
+    /// 空きバッファのうちfがtrueを返したものだけを残し、解放したバッファ数を返す
+    /// fは次に貸し出される順に呼ばれ、残したバッファはその順のままフリーリストに戻る
+    /// （ページの事前タッチやチェックサムの検査をしながら、選んで縮めるとき）
+    /// &mut selfなので、リースが残っていないときだけ使える
+    pub fn retain_idle(&mut self, f: impl FnMut(&mut A::Buffer) -> bool) -> usize {
+        self.retain_free(f, drop)
+    }
+
+    /// 空きバッファをインデックス順に返す
+    /// &mut selfなので、リースが残っていないときだけ使える
+    pub fn idle_buffers_mut(&mut self) -> impl Iterator<Item = &mut A::Buffer> {
+        let inner = self.inner.get_mut();
+        let mut idle = vec![false; inner.entries.len()];
+        let mut idx = inner.free_head;
+        while idx != FREE_LIST_END {
+            idle[idx] = true;
+            idx = inner.entries[idx].next_free;
+        }
+        inner
+            .entries
+            .iter_mut()
+            .zip(idle)
+            .filter(|(_, idle)| *idle)
+            .filter_map(|(entry, _)| entry.buffer.as_mut())
+    }
+
+    /// trim / retain_idle / take_idleの本体（残さなかったバッファはremovedへ渡す）
+    /// fの中からプールに触れると空きリストを壊すので、&selfで呼ぶのはプールの中からだけにする
+    fn retain_free(
+        &self,
+        mut f: impl FnMut(&mut A::Buffer) -> bool,
+        mut removed: impl FnMut(A::Buffer),
+    ) -> usize {
+        let mut guard = self.inner.borrow_mut();
+        let inner = &mut *guard;
+        let mut idle = Vec::new();
+        let mut idx = inner.free_head;
+        while idx != FREE_LIST_END {
+            idle.push(idx);
+            idx = inner.entries[idx].next_free;
+        }
+        let mut kept = Vec::with_capacity(idle.len());
+        for idx in idle {
+            let buffer = inner.entries[idx]
+                .buffer
+                .as_mut()
+                .expect("idle entry has no buffer");
+            if f(buffer) {
+                kept.push(idx);
+            } else {
+                inner.observe(idx, |o, bytes| o.on_free(bytes));
+                inner.deregister(idx);
+                if let Some(buffer) = inner.entries[idx].buffer.take() {
+                    removed(buffer);
+                }
+                #[cfg(debug_assertions)]
+                {
+                    inner.entries[idx].in_free = false;
+                }
+            }
+        }
+        let dropped = inner.idle - kept.len();
+        inner.idle = kept.len();
+
+        // 残した空きバッファは元の順につなぎ直す
+        inner.free_head = FREE_LIST_END;
+        for &idx in kept.iter().rev() {
+            inner.entries[idx].next_free = inner.free_head;
+            inner.free_head = idx;
+        }
+        drop(guard);
+        self.compact_vacant();
+        dropped
+    }
+
+    /// 末尾のバッファのないエントリを切り詰め、残りのバッファのないエントリをインデックス順につなぎ直す
+    fn compact_vacant(&self) {
+        let mut guard = self.inner.borrow_mut();
+        let inner = &mut *guard;
+        while inner
+            .entries
+            .last()
+            .is_some_and(|entry| entry.buffer.is_none() && !entry.sent)
+        {
+            inner.entries.pop();
+        }
+        inner.vacant_head = FREE_LIST_END;
+        inner.vacant = 0;
+        for idx in (0..inner.entries.len()).rev() {
+            let entry = &inner.entries[idx];
+            if entry.buffer.is_none() && !entry.sent {
+                inner.entries[idx].next_free = inner.vacant_head;
+                inner.vacant_head = idx;
+                inner.vacant += 1;
+            }
+        }
+    }
+
+    /// 貸出中のエントリからバッファを取り出し、プールから外す
+    fn detach(&self, index: usize) -> A::Buffer {
+        let mut guard = self.inner.borrow_mut();
+        let inner = &mut *guard;
+        inner.observe(index, |o, bytes| {
+            o.on_return(bytes);
+            o.on_free(bytes);
+        });
+        #[cfg(feature = "lease-timing")]
+        inner.end_hold(index);
+        inner.deregister(index);
+        let buffer = inner.entries[index]
+            .buffer
+            .take()
+            .expect("leased entry has no buffer");
+        drop(guard);
+        self.compact_vacant();
+        // 上限に空きができたので、lease_asyncで待っているタスクは新しく確保できる
+        self.wake_waiters();
+        buffer
+    }
+
+    /// 空きバッファをすべて解放し、エントリの配列も切り詰めた長さに合わせて縮める
+    pub fn shrink_to_fit(&self) -> usize {
+        let dropped = self.trim(0);
+        let mut guard = self.inner.borrow_mut();
+        let inner = &mut *guard;
+        inner.entries.shrink_to_fit();
+        dropped
+    }
+
+    /// 貸出中のエントリのバッファ
+    #[inline]
+    fn buffer(&self, index: usize) -> &A::Buffer {
+        let buffer: *const A::Buffer = self.inner.borrow().entries[index]
+            .buffer
+            .as_ref()
+            .expect("leased entry has no buffer");
+        // SAFETY: 貸出中のエントリのバッファはリースだけが指し、エントリは動かない
+        unsafe { &*buffer }
+    }
+
+    /// 貸出中のエントリのバッファ（可変）
+    #[inline]
+    #[allow(clippy::mut_from_ref)]
+    fn buffer_mut(&self, index: usize) -> &mut A::Buffer {
+        let buffer: *mut A::Buffer = self.inner.borrow_mut().entries[index]
+            .buffer
+            .as_mut()
+            .expect("leased entry has no buffer");
+        // SAFETY: 貸出中のエントリのバッファはリースだけが指し、エントリは動かない
+        unsafe { &mut *buffer }
+    }
+
+    /// プールへの`Arc`を持つリースを返す
+    /// ライフタイムを持たないので、Futureに保持して`.await`をまたいだり、`spawn_local`したタスクへ移したりできる
+    /// （プール自体がシングルスレッド前提なので、`OwnedLease`はSendではない）
+    #[inline]
+    pub fn lease_owned(self: &Arc<Self>) -> Result<OwnedLease<A>, LeaseError<A::Error>> {
+        let lease = self.lease()?;
+        Ok(self.to_owned_lease(lease))
+    }
+
+    /// lease_asyncのOwnedLease版
+    pub async fn lease_owned_async(
+        self: &Arc<Self>,
+    ) -> Result<OwnedLease<A>, LeaseError<A::Error>> {
+        let lease = self.lease_async().await?;
+        Ok(self.to_owned_lease(lease))
+    }
+
+    fn to_owned_lease(self: &Arc<Self>, lease: Lease<'_, A>) -> OwnedLease<A> {
+        let index = lease.index;
+        #[cfg(debug_assertions)]
+        let lease_id = lease.lease_id;
+        // 返却はOwnedLeaseのDropで行う
+        std::mem::forget(lease);
+        OwnedLease {
+            pool: Arc::clone(self),
+            index,
+            #[cfg(debug_assertions)]
+            lease_id,
+        }
+    }
+
+    /// Lease::into_rawで手放したリースを、スロット番号から戻す（完了キューから返ってきたトークンを解決するとき）
+    ///
+    /// # Safety
+    /// slotはこのプールのLease::into_raw / OwnedLease::into_rawが返した番号で、まだ戻していないこと（戻すのは1度だけ）
+    pub unsafe fn lease_from_raw(&self, slot: usize) -> Lease<'_, A> {
+        Lease {
+            pool: self,
+            index: slot,
+            #[cfg(debug_assertions)]
+            lease_id: self.raw_lease_id(slot),
+            _marker: PhantomData,
+        }
+    }
+
+    /// lease_from_rawのOwnedLease版
+    ///
+    /// # Safety
+    /// lease_from_rawと同じ
+    pub unsafe fn owned_lease_from_raw(self: &Arc<Self>, slot: usize) -> OwnedLease<A> {
+        OwnedLease {
+            pool: Arc::clone(self),
+            index: slot,
+            #[cfg(debug_assertions)]
+            lease_id: self.raw_lease_id(slot),
+        }
+    }
+
+    /// 戻すスロットが貸出中か確かめ、そのリース番号を返す
+    #[cfg(debug_assertions)]
+    fn raw_lease_id(&self, slot: usize) -> u64 {
+        let guard = self.inner.borrow();
+        let inner = &*guard;
+        let lease_id = if slot < inner.entries.len() {
+            let entry = &inner.entries[slot];
+            entry.buffer.as_ref().map_or(0, |_| entry.lease_id)
+        } else {
+            0
+        };
+        assert!(lease_id != 0, "slot {} is not leased from this pool", slot);
+        lease_id
+    }
+
+    /// 貸出中のリースのスロット番号と貸し出してからの時間を、長く借りられている順に返す
+    /// （返し忘れたリースや、プールを枯らしているリースを探すとき）
+    #[cfg(feature = "lease-timing")]
+    pub fn held_leases(&self) -> Vec<(usize, Duration)> {
+        let guard = self.inner.borrow();
+        let inner = &*guard;
+        let now = Instant::now();
+        let mut held: Vec<_> = (0..inner.entries.len())
+            .filter_map(|index| {
+                let since = inner.entries[index].leased_at?;
+                Some((index, now - since))
+            })
+            .collect();
+        held.sort_by_key(|&(_, held)| std::cmp::Reverse(held));
+        held
+    }
+
+    /// スロットのリースを貸し出してからの時間
+    #[cfg(feature = "lease-timing")]
+    fn held(&self, index: usize) -> Duration {
+        self.inner.borrow().entries[index]
+            .leased_at
+            .map_or(Duration::ZERO, |since| since.elapsed())
+    }
+
+    /// 貸し出したエントリが返された
+    #[inline]
+    fn return_lease(&self, index: usize) {
+        if !self.inner.borrow().hooks {
+            self.release(index);
+            return;
+        }
+        self.return_entry(index);
+        self.spill_excess();
+        self.refill();
+    }
+
+    /// 空きバッファが上限を超えていれば、超えた分を渡し先へ移す（新しく返されたものから残す）
+    #[inline]
+    fn spill_excess(&self) {
+        let inner = self.inner.borrow();
+        let max_idle = match &inner.spill {
+            Some(spill) if inner.idle > spill.max_idle => spill.max_idle,
+            _ => return,
+        };
+        drop(inner);
+        let excess = self.take_idle(max_idle);
+        if let Some(spill) = &self.inner.borrow().spill {
+            (spill.to)(self.allocator(), excess);
+        }
+    }
+
+    /// 返されたエントリの貸出を終えてフリーリストに戻す
+    #[inline]
+    fn return_entry(&self, index: usize) {
+        let mut guard = self.inner.borrow_mut();
+        let inner = &mut *guard;
+        inner.observe(index, |o, bytes| o.on_return(bytes));
+        #[cfg(feature = "lease-timing")]
+        inner.end_hold(index);
+        drop(guard);
+        self.release(index);
+    }
+
+    /// エントリをフリーリストに戻す（バッファは保持したまま）
+    #[inline]
+    fn release(&self, index: usize) {
+        let mut guard = self.inner.borrow_mut();
+        let inner = &mut *guard;
+        let hooks = inner.hooks;
+        if hooks && inner.reset_policy == ResetPolicy::OnReturn {
+            if let Some(buffer) = inner.entries[index].buffer.as_mut() {
+                if let Err(err) = inner.reset.call(buffer) {
+                    inner.reset_errors.push(err);
+                    drop(guard);
+                    self.discard(index);
+                    return;
+                }
+            }
+        }
+        #[cfg(feature = "poison")]
+        if let (Some((fill, _)), Some(buffer)) =
+            (inner.poison, inner.entries[index].buffer.as_mut())
+        {
+            fill(buffer);
+        }
+        #[cfg(debug_assertions)]
+        {
+            let entry = &mut inner.entries[index];
+            assert!(
+                !entry.in_free,
+                "buffer {} was pushed to the free list twice",
+                index
+            );
+            entry.in_free = true;
+        }
+        if hooks {
+            if let Some(ttl) = inner.idle_ttl {
+                let now = Instant::now();
+                inner.entries[index].idle_since = Some(now);
+                inner.next_expiry.get_or_insert(now + ttl);
+            }
+        }
+        inner.entries[index].next_free = inner.free_head;
+        inner.free_head = index;
+        inner.idle += 1;
+        drop(guard);
+        if hooks {
+            self.wake_waiters();
+        }
+    }
+
+    /// lease_asyncで待っているタスクをすべて起こし、取れなかったものは再び待たせる
+    /// （待っていたFutureが途中でdropされても空きを取りこぼさない）
+    #[inline]
+    fn wake_waiters(&self) {
+        let mut guard = self.inner.borrow_mut();
+        let inner = &mut *guard;
+        if inner.waiters.is_empty() {
+            return;
+        }
+        let waiters = std::mem::take(&mut inner.waiters);
+        drop(guard);
+        for waker in waiters {
+            waker.wake();
+        }
+    }
+}
+
+impl<A: BufferAllocator> BufferPool<A>
+where
+    A::Buffer: Buffer,
+{
+    /// 返されたバッファをresetするタイミングを指定する（デフォルトはNever）
+    /// 新しく確保したバッファはresetせずにそのまま貸し出す
+    /// Buffer::try_resetに失敗したバッファはプールから捨て、そのエラーをleaseがLeaseError::Resetとして返す
+    pub fn with_reset_policy(mut self, policy: ResetPolicy) -> Self {
+        let inner = self.inner.get_mut();
+        inner.reset_policy = policy;
+        inner.reset = ResetFn::Buffer(<A::Buffer as Buffer>::try_reset);
+        inner.hooks |= policy != ResetPolicy::Never;
+        self
+    }
+
+    /// 貸し出し・返却・確保・解放のたびにobserverへ知らせる（メトリクスの記録用）
+    /// 設定より前に確保したバッファは知らせないので、プールを作った直後に設定する
+    pub fn with_observer(mut self, observer: impl PoolObserver + Send + 'static) -> Self {
+        let inner = self.inner.get_mut();
+        inner.observer = Some(Observer {
+            hook: Box::new(observer),
+            size: |buffer| buffer.capacity(),
+        });
+        inner.hooks = true;
+        self
+    }
+
+    /// 返されたバッファをPOISON_BYTEで埋め、フリーリストから貸し出すときに埋めた内容のままか確かめる
+    /// Buffer::ptrで取り出したポインタを返却後に使って書き込むと、次のリースでpanicする
+    #[cfg(feature = "poison")]
+    pub fn with_poison(mut self) -> Self {
+        self.inner.get_mut().poison = Some((poison_fill::<A::Buffer>, poison_check::<A::Buffer>));
+        self
+    }
+
+    /// 貸出中のエントリのバッファをlenバイト以上にアロケータで伸ばす（スロットはそのまま）
+    fn grow_buffer(&self, index: usize, len: usize) -> Result<(), GrowError<A::Error>> {
+        let mut guard = self.inner.borrow_mut();
+        let inner = &mut *guard;
+        let buffer = inner.entries[index]
+            .buffer
+            .as_mut()
+            .expect("leased entry has no buffer");
+        let old = buffer.capacity();
+        if old >= len {
+            return Ok(());
+        }
+        // 登録したメモリは伸ばすと動いてしまう
+        if inner.registrar.is_some() {
+            return Err(GrowError::Unsupported);
+        }
+        if !inner
+            .allocator
+            .grow(buffer, len)
+            .map_err(GrowError::Allocate)?
+        {
+            return Err(GrowError::Unsupported);
+        }
+        let new = buffer.capacity();
+        debug_assert!(
+            new >= len,
+            "allocator grew the buffer to {} bytes, less than {}",
+            new,
+            len
+        );
+        inner.observe(index, |o, _| o.on_resize(old, new));
+        Ok(())
+    }
+
+    /// 貸出回数や再利用の割合、現在のバッファ数を返す
+    pub fn stats(&self) -> PoolStats {
+        let guard = self.inner.borrow();
+        let inner = &*guard;
+        let buffers = inner.entries.len() - inner.vacant;
+        let bytes = inner
+            .entries
+            .iter()
+            .filter_map(|entry| entry.buffer.as_ref())
+            .map(|buffer| buffer.capacity())
+            .sum();
+        PoolStats {
+            leases: inner.leases,
+            reused: inner.reused,
+            allocated: inner.allocated,
+            live: buffers - inner.idle,
+            peak_live: inner.peak_live,
+            idle: inner.idle,
+            bytes,
+        }
+    }
+
+    /// 同時貸出数の最大を今の貸出数に戻す（一定期間ごとのピークを測るとき）
+    pub fn reset_peak(&self) {
+        let mut guard = self.inner.borrow_mut();
+        let inner = &mut *guard;
+        inner.peak_live = inner.entries.len() - inner.vacant - inner.idle;
+    }
+}
+
+/// ポイズンとして返却したバッファに書き込むバイト
+#[cfg(feature = "poison")]
+pub const POISON_BYTE: u8 = 0xA5;
+
+#[cfg(feature = "poison")]
+fn poison_fill<B: Buffer>(buffer: &mut B) {
+    buffer.as_mut_slice().fill(POISON_BYTE);
+}
+
+#[cfg(feature = "poison")]
+fn poison_check<B: Buffer>(buffer: &B) -> Option<usize> {
+    buffer.as_slice().iter().position(|&b| b != POISON_BYTE)
+}
+
+impl<A: BufferAllocator> Drop for BufferPool<A> {
+    /// 貸出中のリースが残っていれば（forgetやinto_rawで手放したまま戻していない）、警告してバッファを解放する
+    /// デバッグビルドではpanicする（OwnedLeaseはArcでプールを生かすので、残るのはリースを漏らしたときだけ）
+    /// into_sendのリースはプールより長く使ってよく、プールがなければ返したスレッドでバッファを解放する
+    /// （返却キューに届いているものは先にプールへ戻す）
+    fn drop(&mut self) {
+        self.drain_returns();
+        let inner = self.inner.get_mut();
+        let sent = inner.entries.iter().filter(|entry| entry.sent).count();
+        if sent > 0 && inner.registrar.is_some() {
+            log::warn!(
+                "BufferPool dropped with {} leases sent to other threads; their buffers are freed still registered",
+                sent
+            );
+        }
+        let outstanding = inner.entries.len() - inner.vacant - inner.idle - sent;
+        if outstanding > 0 {
+            log::warn!(
+                "BufferPool dropped with {} outstanding leases; their buffers are freed",
+                outstanding
+            );
+            #[cfg(debug_assertions)]
+            if !std::thread::panicking() {
+                let slots: Vec<_> = (0..inner.entries.len())
+                    .filter(|&index| {
+                        let entry = &inner.entries[index];
+                        entry.buffer.is_some() && entry.lease_id != 0
+                    })
+                    .collect();
+                panic!(
+                    "BufferPool dropped with {} outstanding leases (slots {:?})",
+                    outstanding, slots
+                );
+            }
+        }
+        for index in 0..inner.entries.len() {
+            inner.observe(index, |o, bytes| o.on_free(bytes));
+            inner.deregister(index);
+        }
+    }
+}
+
+/// BufferPool::with_registrarで設定する、バッファのメモリを登録・解除するフック
+/// RDMAのibv_reg_mrやSPDKのspdk_mem_registerのように、I/Oに使う前にメモリを登録しておくスタック向け
+/// 登録はバッファがプールに入るとき（確保したとき）、
+/// 解除はプールが手放すとき（trim、resetの失敗、detach、take_idle、プールのdrop）に1度ずつ呼ぶ。貸し出しと返却（into_sendのリースを含む）では呼ばない
+/// 登録の鍵（RDMAのlkeyなど）は、バッファのアドレスをキーにしてフックの側で持つ
+pub trait MemoryRegistrar<A: BufferAllocator> {
+    /// プールに入れるバッファを登録する。失敗したらバッファは解放し、確保の失敗としてleaseやreserveが返す
+    fn register(&self, buffer: &mut A::Buffer) -> Result<(), A::Error>;
+    /// プールが手放すバッファの登録を解除する
+    fn deregister(&self, buffer: &mut A::Buffer);
+}
+
+/// BufferPool::with_observerで設定するフック
+/// メトリクスのカウンタやゲージをここから更新する（bytesはそのバッファの長さ）
+pub trait PoolObserver {
+    /// バッファを貸し出した（reusedはフリーリストのバッファを使ったか。falseなら新しく確保した）
+    fn on_lease(&self, _bytes: usize, _reused: bool) {}
+    /// 貸し出したバッファが返された（detachで外したときも呼ぶ）
+    fn on_return(&self, _bytes: usize) {}
+    /// プールが新しくバッファを確保した（reserveで確保したものを含む）
+    fn on_allocate(&self, _bytes: usize) {}
+    /// プールがバッファを手放した（trim、resetの失敗、detach、プールのdrop）
+    fn on_free(&self, _bytes: usize) {}
+    /// 貸出中のバッファをensure_capacityで伸ばした（oldからnewバイトへ）
+    fn on_resize(&self, _old: usize, _new: usize) {}
+    /// leaseを呼んでから貸し出すまでにかかった（lease_asyncなら待った）時間
+    #[cfg(feature = "lease-timing")]
+    fn on_acquire(&self, _waited: Duration) {}
+    /// 返された（detachで外した）リースを貸し出していた時間
+    #[cfg(feature = "lease-timing")]
+    fn on_hold(&self, _held: Duration) {}
+}
+
+impl<T: PoolObserver + ?Sized> PoolObserver for Arc<T> {
+    fn on_lease(&self, bytes: usize, reused: bool) {
+        (**self).on_lease(bytes, reused);
+    }
+
+    fn on_return(&self, bytes: usize) {
+        (**self).on_return(bytes);
+    }
+
+    fn on_allocate(&self, bytes: usize) {
+        (**self).on_allocate(bytes);
+    }
+
+    fn on_free(&self, bytes: usize) {
+        (**self).on_free(bytes);
+    }
+
+    fn on_resize(&self, old: usize, new: usize) {
+        (**self).on_resize(old, new);
+    }
+
+    #[cfg(feature = "lease-timing")]
+    fn on_acquire(&self, waited: Duration) {
+        (**self).on_acquire(waited);
+    }
+
+    #[cfg(feature = "lease-timing")]
+    fn on_hold(&self, held: Duration) {
+        (**self).on_hold(held);
+    }
+}
+
+/// アトミックなカウンタとゲージを持つPoolObserver
+/// Arcで包んでプールに渡し、別スレッドのエクスポータから読む
+#[derive(Debug, Default)]
+pub struct PoolMetrics {
+    /// 貸し出した回数
+    pub leases: AtomicU64,
+    /// そのうち空きがなく新しく確保した回数
+    pub misses: AtomicU64,
+    /// プールが持っているバッファ数（貸出中と空き）
+    pub buffers: AtomicUsize,
+    /// プールが持っているバッファの合計バイト数
+    pub bytes: AtomicUsize,
+    /// 貸出中のバッファの合計バイト数
+    pub outstanding_bytes: AtomicUsize,
+    /// 貸し出すまでにかかった時間の合計（ナノ秒。leasesで割ると平均）
+    #[cfg(feature = "lease-timing")]
+    pub acquire_nanos: AtomicU64,
+    /// 返されたリースを貸し出していた時間の合計（ナノ秒）
+    #[cfg(feature = "lease-timing")]
+    pub hold_nanos: AtomicU64,
+    /// 返されたリースを貸し出していた時間の最大（ナノ秒）
+    #[cfg(feature = "lease-timing")]
+    pub max_hold_nanos: AtomicU64,
+}
+
+impl PoolObserver for PoolMetrics {
+    fn on_lease(&self, bytes: usize, reused: bool) {
+        self.leases.fetch_add(1, Ordering::Relaxed);
+        if !reused {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+        }
+        self.outstanding_bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    fn on_return(&self, bytes: usize) {
+        self.outstanding_bytes.fetch_sub(bytes, Ordering::Relaxed);
+    }
+
+    fn on_allocate(&self, bytes: usize) {
+        self.buffers.fetch_add(1, Ordering::Relaxed);
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    fn on_free(&self, bytes: usize) {
+        self.buffers.fetch_sub(1, Ordering::Relaxed);
+        self.bytes.fetch_sub(bytes, Ordering::Relaxed);
+    }
+
+    fn on_resize(&self, old: usize, new: usize) {
+        // 伸ばすのは貸出中のバッファだけ
+        let grown = new.saturating_sub(old);
+        self.bytes.fetch_add(grown, Ordering::Relaxed);
+        self.outstanding_bytes.fetch_add(grown, Ordering::Relaxed);
+    }
+
+    #[cfg(feature = "lease-timing")]
+    fn on_acquire(&self, waited: Duration) {
+        self.acquire_nanos
+            .fetch_add(waited.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    #[cfg(feature = "lease-timing")]
+    fn on_hold(&self, held: Duration) {
+        let nanos = held.as_nanos() as u64;
+        self.hold_nanos.fetch_add(nanos, Ordering::Relaxed);
+        self.max_hold_nanos.fetch_max(nanos, Ordering::Relaxed);
+    }
+}
+
+/// BufferPool::statsの結果
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PoolStats {
+    /// これまでに貸し出した回数
+    pub leases: u64,
+    /// そのうちフリーリストのバッファを再利用した回数
+    pub reused: u64,
+    /// 新しく確保したバッファ数（reserveで確保したものを含む）
+    pub allocated: u64,
+    /// 貸出中のバッファ数
+    pub live: usize,
+    /// 同時に貸し出したバッファ数の最大（reserveやwith_max_capacityの目安にする）
+    pub peak_live: usize,
+    /// フリーリストにある空きバッファ数
+    pub idle: usize,
+    /// プールが持っているバッファ（貸出中と空き）の合計バイト数
+    pub bytes: usize,
+}
+
+impl<A: BufferAllocator> Lease<'_, A> {
+    /// 貸し出してからの時間
+    #[cfg(feature = "lease-timing")]
+    pub fn held(&self) -> Duration {
+        self.pool.held(self.index)
+    }
+
+    /// このリースが持っているスロットの番号
+    /// 貸出中の他のリースとは重ならない。返すかdetachすると、次のリースが同じ番号を使う
+    #[inline]
+    pub fn slot(&self) -> usize {
+        self.index
+    }
+
+    /// リースを返さずに手放し、スロット番号にする
+    /// （整数のトークンしか持てないCのライブラリや完了キューにバッファを登録するとき）
+    /// バッファは貸出中のまま残り、BufferPool::lease_from_rawで戻してdropすると返る。戻さないままプールを捨てると、デバッグビルドではpanicする
+    pub fn into_raw(self) -> usize {
+        ManuallyDrop::new(self).index
+    }
+
+    /// バッファをプールから外して所有権ごと取り出す（プールより長く使う、外部のコードに渡すとき）
+    /// 外したスロットは次に確保したバッファで再利用される
+    pub fn detach(self) -> A::Buffer {
+        let this = ManuallyDrop::new(self);
+        #[cfg(debug_assertions)]
+        this.pool.check_lease(this.index, this.lease_id);
+        this.pool.detach(this.index)
+    }
+
+    /// どのスレッドでdropしてもよいリースに変える
+    /// スロットは貸出中のままで、dropされると返却キューを通ってこのプールに返る
+    /// （返るのはプールのスレッドで次にlease / maintainを呼んだとき。バッファがSendならSend）
+    pub fn into_send(self) -> SendLease<A::Buffer> {
+        let this = ManuallyDrop::new(self);
+        let (returns, buffer) = this.pool.send(this.index);
+        SendLease {
+            returns,
+            buffer: ManuallyDrop::new(buffer),
+            index: this.index,
+            #[cfg(debug_assertions)]
+            lease_id: this.lease_id,
+        }
+    }
+}
+
+impl<A: BufferAllocator> Drop for Lease<'_, A> {
+    #[inline]
+    fn drop(&mut self) {
+        #[cfg(debug_assertions)]
+        self.pool.check_lease(self.index, self.lease_id);
+        self.pool.return_lease(self.index);
+    }
+}
+
+impl<A: BufferAllocator> Deref for Lease<'_, A> {
+    type Target = A::Buffer;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        self.pool.buffer(self.index)
+    }
+}
+
+impl<A: BufferAllocator> DerefMut for Lease<'_, A> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.pool.buffer_mut(self.index)
+    }
+}
+
+impl<A: BufferAllocator> Buffer for Lease<'_, A>
+where
+    A::Buffer: Buffer,
+{
+    /// リースを返すかdetachするまで同じアドレスを返す（プールが伸びても変わらない）
+    /// ensure_capacityで伸ばすとアドレスが変わるので、登録中のバッファは伸ばさない
+    unsafe fn ptr(&self) -> *mut u8 {
+        unsafe { (**self).ptr() }
+    }
+
+    /// into_rawした後も、戻したリースを返すまでかプールを捨てるまでは同じアドレスのまま書き込める
+    /// （Cのライブラリにはas_mut_ptr()とcapacity()を渡し、完了時にslot()のトークンでリースを戻す）
+    fn as_mut_ptr(&mut self) -> *mut u8 {
+        (**self).as_mut_ptr()
+    }
+
+    fn reset(&mut self) {
+        (**self).reset();
+    }
+
+    fn try_reset(&mut self) -> std::io::Result<()> {
+        (**self).try_reset()
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        (**self).as_slice()
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        (**self).as_mut_slice()
+    }
+
+    unsafe fn as_uninit_slice(&mut self) -> &mut [MaybeUninit<u8>] {
+        unsafe { (**self).as_uninit_slice() }
+    }
+
+    unsafe fn assume_init_to(&mut self, len: usize) {
+        unsafe { (**self).assume_init_to(len) }
+    }
+
+    fn len(&self) -> usize {
+        (**self).len()
+    }
+
+    fn capacity(&self) -> usize {
+        (**self).capacity()
+    }
+}
+
+impl<'a, A: BufferAllocator> Lease<'a, A>
+where
+    A::Buffer: Buffer,
+{
+    /// バッファがlenバイトに満たなければ、同じスロットのままアロケータで伸ばす
+    /// （VecAllocatorのように伸ばせるアロケータ向け。長さの決まらない応答をdetachしてコピーせずに書ける）
+    /// 伸ばせないアロケータではGrowError::Unsupportedを返し、バッファはそのまま
+    pub fn ensure_capacity(&mut self, len: usize) -> Result<(), GrowError<A::Error>> {
+        self.pool.grow_buffer(self.index, len)
+    }
+
+    /// バッファを先頭から順にlensバイトずつの重ならない区間に分ける
+    /// 区間はまとめて1つのリースを共有し、すべてdropされたときにバッファがプールへ返る
+    /// lensの合計がバッファの長さを超えるとpanicする
+    pub fn split(mut self, lens: impl IntoIterator<Item = usize>) -> Vec<LeaseSlice<'a, A>> {
+        // 未初期化のまま貸し出すバッファでは、初期化済みの範囲だけを分ける
+        let size = self.len();
+        let base = self.as_mut_ptr();
+        let lease = Rc::new(self);
+        let mut offset = 0;
+        lens.into_iter()
+            .map(|len| {
+                assert!(
+                    offset + len <= size,
+                    "split lengths exceed the buffer size {}",
+                    size
+                );
+                let slice = LeaseSlice {
+                    _lease: Rc::clone(&lease),
+                    // SAFETY: offset + len <= sizeなので、バッファの内側を指す
+                    ptr: unsafe { base.add(offset) },
+                    len,
+                };
+                offset += len;
+                slice
+            })
+            .collect()
+    }
+}
+
+impl<A: BufferAllocator> AsRef<[u8]> for Lease<'_, A>
+where
+    A::Buffer: Buffer,
+{
+    #[inline]
+    fn as_ref(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
+
+impl<A: BufferAllocator> AsMut<[u8]> for Lease<'_, A>
+where
+    A::Buffer: Buffer,
+{
+    #[inline]
+    fn as_mut(&mut self) -> &mut [u8] {
+        self.as_mut_slice()
+    }
+}
+
+/// Lease::splitで分けたバッファの一部
+/// 他の区間と重ならないので、それぞれ独立に読み書きできる
+pub struct LeaseSlice<'a, A: BufferAllocator> {
+    /// 区間がすべてdropされるまでリースを返さずに持っておく
+    _lease: Rc<Lease<'a, A>>,
+    /// 区間の先頭（splitのときにas_mut_ptrで取っておき、区間ごとに書き込む）
+    ptr: *mut u8,
+    len: usize,
+}
+
+impl<A: BufferAllocator> Deref for LeaseSlice<'_, A>
+where
+    A::Buffer: Buffer,
+{
+    type Target = [u8];
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        // SAFETY: offsetからlenバイトはバッファの内側で、他の区間と重ならない
+        unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+impl<A: BufferAllocator> DerefMut for LeaseSlice<'_, A>
+where
+    A::Buffer: Buffer,
+{
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        // SAFETY: offsetからlenバイトはバッファの内側で、他の区間と重ならない
+        unsafe { std::slice::from_raw_parts_mut(self.ptr, self.len) }
+    }
+}
+
+impl<A: BufferAllocator> Buffer for LeaseSlice<'_, A>
+where
+    A::Buffer: Buffer,
+{
+    unsafe fn ptr(&self) -> *mut u8 {
+        self.ptr
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+/// lease / lease_async / lease_ownedのエラー
+#[derive(Debug)]
+pub enum LeaseError<E> {
+    /// バッファの確保に失敗した
+    Allocate(E),
+    /// 空きバッファのresetに失敗した（そのバッファはプールから捨てた）
+    Reset(std::io::Error),
+}
+
+impl<E: std::fmt::Display> std::fmt::Display for LeaseError<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LeaseError::Allocate(e) => write!(f, "failed to allocate a buffer: {}", e),
+            LeaseError::Reset(e) => write!(f, "failed to reset a pooled buffer: {}", e),
+        }
+    }
+}
+
+impl<E: std::fmt::Debug + std::fmt::Display> std::error::Error for LeaseError<E> {}
+
+impl From<LeaseError<std::io::Error>> for std::io::Error {
+    fn from(err: LeaseError<std::io::Error>) -> Self {
+        match err {
+            LeaseError::Allocate(e) | LeaseError::Reset(e) => e,
+        }
+    }
+}
+
+/// ensure_capacityのエラー
+#[derive(Debug)]
+pub enum GrowError<E> {
+    /// アロケータがバッファを伸ばせない
+    Unsupported,
+    /// バッファを伸ばすための確保に失敗した
+    Allocate(E),
+}
+
+impl<E: std::fmt::Display> std::fmt::Display for GrowError<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GrowError::Unsupported => write!(f, "allocator cannot grow a buffer in place"),
+            GrowError::Allocate(e) => write!(f, "failed to grow a buffer: {}", e),
+        }
+    }
+}
+
+impl<E: std::fmt::Debug + std::fmt::Display> std::error::Error for GrowError<E> {}
+
+/// try_leaseのエラー
+#[derive(Debug)]
+pub enum TryLeaseError<E> {
+    /// 確保済みのバッファ数が上限に達していて、空きもない
+    PoolExhausted,
+    /// バッファの確保に失敗した
+    Allocate(E),
+    /// 空きバッファのresetに失敗した（そのバッファはプールから捨てた）
+    Reset(std::io::Error),
+}
+
+impl<E: std::fmt::Display> std::fmt::Display for TryLeaseError<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TryLeaseError::PoolExhausted => write!(f, "buffer pool is exhausted"),
+            TryLeaseError::Allocate(e) => write!(f, "failed to allocate a buffer: {}", e),
+            TryLeaseError::Reset(e) => write!(f, "failed to reset a pooled buffer: {}", e),
+        }
+    }
+}
+
+impl<E: std::fmt::Debug + std::fmt::Display> std::error::Error for TryLeaseError<E> {}
+
+impl<E> From<LeaseError<E>> for TryLeaseError<E> {
+    fn from(err: LeaseError<E>) -> Self {
+        match err {
+            LeaseError::Allocate(e) => TryLeaseError::Allocate(e),
+            LeaseError::Reset(e) => TryLeaseError::Reset(e),
+        }
+    }
+}
+
+/// lease_asyncが返すFuture
+pub struct LeaseFuture<'a, A: BufferAllocator> {
+    pool: &'a BufferPool<A>,
+    /// lease_asyncを呼んだ時刻（待った時間もon_acquireに含める）
+    clock: LeaseClock,
+}
+
+impl<'a, A: BufferAllocator> Future for LeaseFuture<'a, A> {
+    type Output = Result<Lease<'a, A>, LeaseError<A::Error>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let pool = self.pool;
+        let clock = self.clock;
+        match pool.take_free() {
+            Ok(Some(index)) => return Poll::Ready(Ok(pool.lease_at(index, true, clock))),
+            Ok(None) => {}
+            Err(err) => return Poll::Ready(Err(err)),
+        }
+        if !pool.is_full() {
+            return Poll::Ready(
+                pool.grow()
+                    .map(|index| pool.lease_at(index, false, clock))
+                    .map_err(LeaseError::Allocate),
+            );
+        }
+        let mut guard = pool.inner.borrow_mut();
+        let inner = &mut *guard;
+        if !inner.waiters.iter().any(|w| w.will_wake(cx.waker())) {
+            inner.waiters.push(cx.waker().clone());
+            inner.hooks = true;
+        }
+        Poll::Pending
+    }
+}
+
+/// プールへの`Arc`を持つ、ライフタイムのないリース
+pub struct OwnedLease<A: BufferAllocator> {
+    pool: Arc<BufferPool<A>>,
+    index: usize,
+    #[cfg(debug_assertions)]
+    lease_id: u64,
+}
+
+impl<A: BufferAllocator> OwnedLease<A>
+where
+    A::Buffer: Buffer,
+{
+    /// Lease::ensure_capacityのOwnedLease版
+    pub fn ensure_capacity(&mut self, len: usize) -> Result<(), GrowError<A::Error>> {
+        self.pool.grow_buffer(self.index, len)
+    }
+}
+
+impl<A: BufferAllocator> OwnedLease<A> {
+    /// Lease::heldのOwnedLease版
+    #[cfg(feature = "lease-timing")]
+    pub fn held(&self) -> Duration {
+        self.pool.held(self.index)
+    }
+
+    /// Lease::slotのOwnedLease版
+    #[inline]
+    pub fn slot(&self) -> usize {
+        self.index
+    }
+
+    /// Lease::into_rawのOwnedLease版
+    /// プールへのArcは手放すので、戻すまで別のArcでプールを生かしておく
+    pub fn into_raw(self) -> usize {
+        let mut this = ManuallyDrop::new(self);
+        // SAFETY: thisはこれ以降使わない
+        unsafe { std::ptr::drop_in_place(&mut this.pool) };
+        this.index
+    }
+
+    /// Lease::detachのOwnedLease版
+    pub fn detach(self) -> A::Buffer {
+        let mut this = ManuallyDrop::new(self);
+        #[cfg(debug_assertions)]
+        this.pool.check_lease(this.index, this.lease_id);
+        let buffer = this.pool.detach(this.index);
+        // SAFETY: thisはこれ以降使わない
+        unsafe { std::ptr::drop_in_place(&mut this.pool) };
+        buffer
+    }
+
+    /// Lease::into_sendのOwnedLease版
+    pub fn into_send(self) -> SendLease<A::Buffer> {
+        let mut this = ManuallyDrop::new(self);
+        let (returns, buffer) = this.pool.send(this.index);
+        // SAFETY: thisはこれ以降poolを使わない
+        unsafe { std::ptr::drop_in_place(&mut this.pool) };
+        SendLease {
+            returns,
+            buffer: ManuallyDrop::new(buffer),
+            index: this.index,
+            #[cfg(debug_assertions)]
+            lease_id: this.lease_id,
+        }
+    }
+}
+
+impl<A: BufferAllocator> Drop for OwnedLease<A> {
+    #[inline]
+    fn drop(&mut self) {
+        #[cfg(debug_assertions)]
+        self.pool.check_lease(self.index, self.lease_id);
+        self.pool.return_lease(self.index);
+    }
+}
+
+impl<A: BufferAllocator> Deref for OwnedLease<A> {
+    type Target = A::Buffer;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        self.pool.buffer(self.index)
+    }
+}
+
+impl<A: BufferAllocator> DerefMut for OwnedLease<A> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.pool.buffer_mut(self.index)
+    }
+}
+
+impl<A: BufferAllocator> Buffer for OwnedLease<A>
+where
+    A::Buffer: Buffer,
+{
+    unsafe fn ptr(&self) -> *mut u8 {
+        unsafe { (**self).ptr() }
+    }
+
+    fn as_mut_ptr(&mut self) -> *mut u8 {
+        (**self).as_mut_ptr()
+    }
+
+    fn reset(&mut self) {
+        (**self).reset();
+    }
+
+    fn try_reset(&mut self) -> std::io::Result<()> {
+        (**self).try_reset()
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        (**self).as_slice()
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        (**self).as_mut_slice()
+    }
+
+    unsafe fn as_uninit_slice(&mut self) -> &mut [MaybeUninit<u8>] {
+        unsafe { (**self).as_uninit_slice() }
+    }
+
+    unsafe fn assume_init_to(&mut self, len: usize) {
+        unsafe { (**self).assume_init_to(len) }
+    }
+
+    fn len(&self) -> usize {
+        (**self).len()
+    }
+
+    fn capacity(&self) -> usize {
+        (**self).capacity()
+    }
+}
+
+impl<A: BufferAllocator> AsRef<[u8]> for OwnedLease<A>
+where
+    A::Buffer: Buffer,
+{
+    #[inline]
+    fn as_ref(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
+
+impl<A: BufferAllocator> AsMut<[u8]> for OwnedLease<A>
+where
+    A::Buffer: Buffer,
+{
+    #[inline]
+    fn as_mut(&mut self) -> &mut [u8] {
+        self.as_mut_slice()
+    }
+}
+
+/// BufferPoolへの`Arc`を持つ、安く複製できるハンドル
+/// ルータ、コーデック、コネクションのように複数の構造体にプールを持たせるとき、`&BufferPool`のライフタイムを引き回さずに済む
+/// leaseはOwnedLeaseを返し、それ以外のメソッドはDerefでBufferPoolのものを使う
+/// （プール自体がシングルスレッド前提なので、`PoolHandle`はSendではない）
+pub struct PoolHandle<A: BufferAllocator> {
+    pool: Arc<BufferPool<A>>,
+}
+
+impl<A: BufferAllocator> PoolHandle<A> {
+    pub fn new(pool: BufferPool<A>) -> Self {
+        PoolHandle {
+            pool: Arc::new(pool),
+        }
+    }
+
+    /// バッファを借りる（BufferPool::lease_owned）
+    #[inline]
+    pub fn lease(&self) -> Result<OwnedLease<A>, LeaseError<A::Error>> {
+        self.pool.lease_owned()
+    }
+
+    /// BufferPool::try_leaseのOwnedLease版
+    #[inline]
+    pub fn try_lease(&self) -> Result<OwnedLease<A>, TryLeaseError<A::Error>> {
+        let lease = self.pool.try_lease()?;
+        Ok(self.pool.to_owned_lease(lease))
+    }
+
+    /// バッファを借りる（BufferPool::lease_owned_async）
+    pub async fn lease_async(&self) -> Result<OwnedLease<A>, LeaseError<A::Error>> {
+        self.pool.lease_owned_async().await
+    }
+
+    /// プールへの`Arc`（OwnedLeaseを返す他のメソッドに渡すとき）
+    pub fn as_arc(&self) -> &Arc<BufferPool<A>> {
+        &self.pool
+    }
+}
+
+impl<A: BufferAllocator> Clone for PoolHandle<A> {
+    fn clone(&self) -> Self {
+        PoolHandle {
+            pool: Arc::clone(&self.pool),
+        }
+    }
+}
+
+impl<A: BufferAllocator> From<BufferPool<A>> for PoolHandle<A> {
+    fn from(pool: BufferPool<A>) -> Self {
+        Self::new(pool)
+    }
+}
+
+impl<A: BufferAllocator> From<Arc<BufferPool<A>>> for PoolHandle<A> {
+    fn from(pool: Arc<BufferPool<A>>) -> Self {
+        PoolHandle { pool }
+    }
+}
+
+impl<A: BufferAllocator> Deref for PoolHandle<A> {
+    type Target = BufferPool<A>;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.pool
+    }
+}
+
+/// バッファ（LeaseやOwnedLeaseなど）をio::Read / Write / Seekとして使うカーソル
+/// 書き込んだ範囲の末尾をfilledとして覚え、Readはfilledまで、SeekFrom::Endはfilledを基準にする
+/// Vecへコピーせずに、プールのバッファを既存のI/Oコードへそのまま渡せる
+pub struct BufferCursor<B> {
+    buf: B,
+    pos: usize,
+    filled: usize,
+}
+
+impl<B: Buffer> BufferCursor<B> {
+    /// 空のカーソル（filledは0）
+    pub fn new(buf: B) -> Self {
+        Self::with_filled(buf, 0)
+    }
+
+    /// 先頭からfilledバイトが読めるデータとして入っているカーソル（バッファの長さで切り詰める）
+    pub fn with_filled(buf: B, filled: usize) -> Self {
+        let filled = filled.min(buf.len());
+        BufferCursor {
+            buf,
+            pos: 0,
+            filled,
+        }
+    }
+
+    /// 現在の読み書きの位置
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    pub fn set_position(&mut self, pos: usize) {
+        self.pos = pos;
+    }
+
+    /// 書き込まれたデータのバイト数
+    pub fn filled(&self) -> usize {
+        self.filled
+    }
+
+    /// 書き込まれたデータ
+    pub fn filled_slice(&self) -> &[u8] {
+        &self.buf.as_slice()[..self.filled]
+    }
+
+    /// filledと位置を0に戻す（バッファの中身はそのまま）
+    pub fn clear(&mut self) {
+        self.pos = 0;
+        self.filled = 0;
+    }
+
+    pub fn get_ref(&self) -> &B {
+        &self.buf
+    }
+
+    pub fn get_mut(&mut self) -> &mut B {
+        &mut self.buf
+    }
+
+    /// バッファを取り出す（リースならそのまま返却できる）
+    pub fn into_inner(self) -> B {
+        self.buf
+    }
+
+    // 初期化済みの範囲より先にシークしていれば、その間を0で埋めて初期化済みにする（io::Cursor<Vec<u8>>と同じ）
+    // 書き込みは必ず位置から始める（位置がバッファの末尾より先なら末尾まで埋める）
+    fn fill_gap(&mut self) {
+        let init = self.buf.len();
+        let end = self.pos.min(self.buf.capacity());
+        if end <= init {
+            return;
+        }
+        // SAFETY: 初期化済みの範囲の続きからendまでに0を書き込み、その範囲までを初期化済みにする
+        unsafe {
+            let bytes = self.buf.as_uninit_slice();
+            std::ptr::write_bytes(bytes[init..end].as_mut_ptr(), 0, end - init);
+            self.buf.assume_init_to(end);
+        }
+    }
+}
+
+impl<B: Buffer> std::io::Read for BufferCursor<B> {
+    fn read(&mut self, out: &mut [u8]) -> std::io::Result<usize> {
+        let start = self.pos.min(self.filled);
+        let n = out.len().min(self.filled - start);
+        out[..n].copy_from_slice(&self.buf.as_slice()[start..start + n]);
+        self.pos = start + n;
+        Ok(n)
+    }
+}
+
+impl<B: Buffer> std::io::Write for BufferCursor<B> {
+    /// バッファの末尾に達したら0を返す（write_allはWriteZeroになる）
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        let start = self.pos;
+        if start >= self.buf.capacity() || data.is_empty() {
+            return Ok(0);
+        }
+        // 未初期化のバッファで初期化済みの範囲より先に書くときは、間を0で埋めてから位置に書く
+        self.fill_gap();
+        let init = self.buf.len();
+        // SAFETY: 書き込むのは初期化済みのu8だけで、startより前は初期化済み、書いた範囲までを初期化済みにする
+        let n = unsafe {
+            let bytes = self.buf.as_uninit_slice();
+            let n = data.len().min(bytes.len() - start);
+            std::ptr::copy_nonoverlapping(
+                data.as_ptr(),
+                bytes[start..].as_mut_ptr().cast::<u8>(),
+                n,
+            );
+            if start + n > init {
+                self.buf.assume_init_to(start + n);
+            }
+            n
+        };
+        self.pos = start + n;
+        self.filled = self.filled.max(self.pos);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<B: Buffer> std::io::Seek for BufferCursor<B> {
+    fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+        let pos = match pos {
+            std::io::SeekFrom::Start(n) => Some(n),
+            std::io::SeekFrom::End(n) => (self.filled as u64).checked_add_signed(n),
+            std::io::SeekFrom::Current(n) => (self.pos as u64).checked_add_signed(n),
+        };
+        match pos {
+            Some(n) => {
+                self.pos = n as usize;
+                Ok(n)
+            }
+            None => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )),
+        }
+    }
+}
+
+/// サイズクラスごとに空きリストを分けたプール
+/// 可変長のメッセージ向けに、要求した長さ以上で最も小さいクラスのバッファを貸し出す
+pub struct TieredBufferPool<A: BufferAllocator> {
+    /// (クラスのバイト数, そのクラスのプール) をバイト数の昇順に並べたもの
+    tiers: Vec<(usize, BufferPool<A>)>,
+}
+
+impl<A: BufferAllocator> TieredBufferPool<A> {
+    /// classes: (クラスのバイト数, そのバイト数以上のバッファを確保するアロケータ) の組
+    pub fn new(classes: impl IntoIterator<Item = (usize, A)>) -> Self {
+        let mut tiers: Vec<_> = classes
+            .into_iter()
+            .map(|(len, allocator)| (len, BufferPool::new(allocator)))
+            .collect();
+        tiers.sort_by_key(|(len, _)| *len);
+        TieredBufferPool { tiers }
+    }
+
+    /// len バイト以上のバッファを借りる。最も大きいクラスより長ければTooLargeを返す
+    #[inline]
+    pub fn lease_at_least(&self, len: usize) -> Result<Lease<'_, A>, TieredLeaseError<A::Error>> {
+        let (_, pool) = self
+            .tiers
+            .iter()
+            .find(|(class, _)| *class >= len)
+            .ok_or(TieredLeaseError::TooLarge(len))?;
+        pool.lease().map_err(|err| match err {
+            LeaseError::Allocate(e) => TieredLeaseError::Allocate(e),
+            LeaseError::Reset(e) => TieredLeaseError::Reset(e),
+        })
+    }
+
+    /// 全クラスのプールのresetのタイミングを指定する
+    pub fn with_reset_policy(self, policy: ResetPolicy) -> Self
+    where
+        A::Buffer: Buffer,
+    {
+        TieredBufferPool {
+            tiers: self
+                .tiers
+                .into_iter()
+                .map(|(len, pool)| (len, pool.with_reset_policy(policy)))
+                .collect(),
+        }
+    }
+
+    /// サイズクラスのバイト数（昇順）
+    pub fn classes(&self) -> impl Iterator<Item = usize> + '_ {
+        self.tiers.iter().map(|(len, _)| *len)
+    }
+
+    /// len バイトのクラスのプール（reserveやstatsをクラスごとに使う）
+    pub fn tier(&self, len: usize) -> Option<&BufferPool<A>> {
+        self.tiers
+            .iter()
+            .find(|(class, _)| *class == len)
+            .map(|(_, pool)| pool)
+    }
+}
+
+/// lease_at_leastのエラー
+#[derive(Debug)]
+pub enum TieredLeaseError<E> {
+    /// 要求した長さを満たすサイズクラスがない
+    TooLarge(usize),
+    /// バッファの確保に失敗した
+    Allocate(E),
+    /// 空きバッファのresetに失敗した（そのバッファはプールから捨てた）
+    Reset(std::io::Error),
+}
+
+impl<E: std::fmt::Display> std::fmt::Display for TieredLeaseError<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TieredLeaseError::TooLarge(len) => {
+                write!(f, "no size class can hold {} bytes", len)
+            }
+            TieredLeaseError::Allocate(e) => write!(f, "failed to allocate a buffer: {}", e),
+            TieredLeaseError::Reset(e) => write!(f, "failed to reset a pooled buffer: {}", e),
+        }
+    }
+}
+
+impl<E: std::fmt::Debug + std::fmt::Display> std::error::Error for TieredLeaseError<E> {}
+
+/// スレッドごとにBufferPoolを持たせるための共有ハンドル（コアごとのバッファキャッシュと同じ構成）
+/// localは呼び出したスレッドのプールを返し（初めて呼んだときに作る）、各スレッドはロックなしで借りて返す
+/// overflowを有効にすると、ローカルプールから溢れた空きバッファを共有のリストに置き、他のスレッドのプールが確保の代わりに使う
+/// 終わったスレッドのプールは後で作られたスレッドが引き継ぎ、ハンドルをdropするとすべてのスレッドのプールを解放する
+pub struct ThreadLocalBufferPool<A>
+where
+    A: BufferAllocator + Send + Sync,
+    A::Buffer: Send,
+    A::Error: Send,
+{
+    shared: Arc<SharedAllocator<A>>,
+    locals: ThreadLocal<LocalBufferPool<A>>,
+}
+
+/// ローカルプールが共有するアロケータと空きバッファ
+struct SharedAllocator<A: BufferAllocator> {
+    allocator: A,
+    /// 共有の空きバッファ（overflowなしならNone）
+    overflow: Option<Mutex<Vec<A::Buffer>>>,
+    /// ローカルプールに残す空きバッファの最大数（リースの返却とspillでこれを超えた分をoverflowへ移す）
+    max_local_idle: usize,
+}
+
+impl<A> ThreadLocalBufferPool<A>
+where
+    A: BufferAllocator + Send + Sync,
+    A::Buffer: Send,
+    A::Error: Send,
+{
+    /// overflowなし: ローカルプールの空きバッファはそのスレッドだけが使い、ハンドルのdropで解放する
+    pub fn new(allocator: A) -> Self {
+        Self::with_shared(SharedAllocator {
+            allocator,
+            overflow: None,
+            max_local_idle: usize::MAX,
+        })
+    }
+
+    /// overflowあり: ローカルプールの空きバッファはmax_local_idle個まで、残りは返却時に共有のリストへ移す
+    pub fn with_overflow(allocator: A, max_local_idle: usize) -> Self {
+        Self::with_shared(SharedAllocator {
+            allocator,
+            overflow: Some(Mutex::new(Vec::new())),
+            max_local_idle,
+        })
+    }
+
+    fn with_shared(shared: SharedAllocator<A>) -> Self {
+        ThreadLocalBufferPool {
+            shared: Arc::new(shared),
+            locals: ThreadLocal::new(),
+        }
+    }
+
+    /// 呼び出したスレッドのプール（同じスレッドからは同じプールを返す）
+    pub fn local(&self) -> &LocalBufferPool<A> {
+        self.locals.get_or(|| {
+            let mut pool = BufferPool::new(LocalAllocator {
+                shared: Arc::clone(&self.shared),
+            });
+            if self.shared.overflow.is_some() {
+                let inner = pool.inner.get_mut();
+                inner.spill = Some(Spill {
+                    max_idle: self.shared.max_local_idle,
+                    to: |allocator, idle| allocator.shared.push_overflow(idle),
+                });
+                inner.hooks = true;
+            }
+            LocalBufferPool { pool }
+        })
+    }
+
+    /// 共有のリストにある空きバッファの数
+    pub fn overflow_len(&self) -> usize {
+        self.shared
+            .overflow
+            .as_ref()
+            .map_or(0, |overflow| overflow.lock().unwrap().len())
+    }
+}
+
+impl<A: BufferAllocator> SharedAllocator<A> {
+    fn push_overflow(&self, idle: Vec<A::Buffer>) {
+        if let (Some(overflow), false) = (&self.overflow, idle.is_empty()) {
+            overflow.lock().unwrap().extend(idle);
+        }
+    }
+}
+
+/// ローカルプールのアロケータ: overflowに空きバッファがあればそれを使い、なければ共有ハンドルのアロケータで確保する
+pub struct LocalAllocator<A: BufferAllocator> {
+    shared: Arc<SharedAllocator<A>>,
+}
+
+impl<A: BufferAllocator> BufferAllocator for LocalAllocator<A> {
+    type Buffer = A::Buffer;
+    type Error = A::Error;
+
+    fn allocate(&self) -> Result<Self::Buffer, Self::Error> {
+        let reused = self
+            .shared
+            .overflow
+            .as_ref()
+            .and_then(|overflow| overflow.lock().unwrap().pop());
+        match reused {
+            Some(buffer) => Ok(buffer),
+            None => self.shared.allocator.allocate(),
+        }
+    }
+
+    fn grow(&self, buffer: &mut Self::Buffer, len: usize) -> Result<bool, Self::Error> {
+        self.shared.allocator.grow(buffer, len)
+    }
+}
+
+/// ThreadLocalBufferPool::localが返す、1スレッド用のプール（BufferPoolとして使う）
+pub struct LocalBufferPool<A: BufferAllocator> {
+    pool: BufferPool<LocalAllocator<A>>,
+}
+
+impl<A: BufferAllocator> LocalBufferPool<A> {
+    /// max_local_idleを超えた空きバッファをoverflowへ移し、移した数を返す（overflowなしなら何もしない）
+    /// リースの返却でも移すので、reserveやmaintainで空きバッファを増やしたあとに呼ぶ
+    pub fn spill(&self) -> usize {
+        let shared = &self.pool.allocator().shared;
+        if shared.overflow.is_none() {
+            return 0;
+        }
+        let spilled = self.pool.take_idle(shared.max_local_idle);
+        let n = spilled.len();
+        shared.push_overflow(spilled);
+        n
+    }
+}
+
+impl<A: BufferAllocator> Deref for LocalBufferPool<A> {
+    type Target = BufferPool<LocalAllocator<A>>;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.pool
+    }
+}
+
+/// ヒープを使わない固定容量のプール（エントリの表もバッファもインラインの配列に持つ）
+/// 作るときにSLOTS個のバッファをすべて用意し、空きがなければleaseはPoolExhaustedを返す
+/// アロケータを呼べないno-allocやリアルタイムの文脈向け（スタックやスレッドローカルに置く）
+pub struct FixedBufferPool<B, const SLOTS: usize> {
+    buffers: [UnsafeCell<B>; SLOTS],
+    /// 空きの場合、次の空きスロットのインデックス（FREE_LIST_ENDで終端）
+    next_free: [Cell<usize>; SLOTS],
+    free_head: Cell<usize>,
+    idle: Cell<usize>,
+}
+
+impl<B, const SLOTS: usize> FixedBufferPool<B, SLOTS> {
+    /// makeでSLOTS個のバッファを作る
+    pub fn from_fn(mut make: impl FnMut() -> B) -> Self {
+        FixedBufferPool {
+            buffers: std::array::from_fn(|_| UnsafeCell::new(make())),
+            next_free: std::array::from_fn(|i| {
+                Cell::new(if i + 1 < SLOTS { i + 1 } else { FREE_LIST_END })
+            }),
+            free_head: Cell::new(if SLOTS > 0 { 0 } else { FREE_LIST_END }),
+            idle: Cell::new(SLOTS),
+        }
+    }
+
+    /// バッファを借りる。空きがなければPoolExhaustedを返す（確保はしない）
+    #[inline]
+    pub fn lease(&self) -> Result<FixedLease<'_, B, SLOTS>, PoolExhausted> {
+        let index = self.free_head.get();
+        if index == FREE_LIST_END {
+            return Err(PoolExhausted);
+        }
+        self.free_head.set(self.next_free[index].get());
+        self.idle.set(self.idle.get() - 1);
+        Ok(FixedLease { pool: self, index })
+    }
+
+    /// 空きバッファの数
+    pub fn idle(&self) -> usize {
+        self.idle.get()
+    }
+
+    #[inline]
+    fn release(&self, index: usize) {
+        self.next_free[index].set(self.free_head.get());
+        self.free_head.set(index);
+        self.idle.set(self.idle.get() + 1);
+    }
+}
+
+impl<B: Default, const SLOTS: usize> Default for FixedBufferPool<B, SLOTS> {
+    fn default() -> Self {
+        Self::from_fn(B::default)
+    }
+}
+
+/// FixedBufferPool::leaseのエラー: 空きスロットがない
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolExhausted;
+
+impl std::fmt::Display for PoolExhausted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "fixed buffer pool is exhausted")
+    }
+}
+
+impl std::error::Error for PoolExhausted {}
+
+/// FixedBufferPoolのリース: Dropでスロットを空きに戻す
+pub struct FixedLease<'a, B, const SLOTS: usize> {
+    pool: &'a FixedBufferPool<B, SLOTS>,
+    index: usize,
+}
+
+impl<B, const SLOTS: usize> Drop for FixedLease<'_, B, SLOTS> {
+    #[inline]
+    fn drop(&mut self) {
+        self.pool.release(self.index);
+    }
+}
+
+impl<B, const SLOTS: usize> Deref for FixedLease<'_, B, SLOTS> {
+    type Target = B;
+
+    #[inline]
+    fn deref(&self) -> &B {
+        // SAFETY: 貸出中のスロットはこのリースだけが指す
+        unsafe { &*self.pool.buffers[self.index].get() }
+    }
+}
+
+impl<B, const SLOTS: usize> DerefMut for FixedLease<'_, B, SLOTS> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut B {
+        // SAFETY: 貸出中のスロットはこのリースだけが指し、&mut selfで排他
+        unsafe { &mut *self.pool.buffers[self.index].get() }
+    }
+}
+
+impl<B: Buffer, const SLOTS: usize> Buffer for FixedLease<'_, B, SLOTS> {
+    unsafe fn ptr(&self) -> *mut u8 {
+        unsafe { (**self).ptr() }
+    }
+
+    fn as_mut_ptr(&mut self) -> *mut u8 {
+        (**self).as_mut_ptr()
+    }
+
+    fn reset(&mut self) {
+        (**self).reset();
+    }
+
+    fn try_reset(&mut self) -> std::io::Result<()> {
+        (**self).try_reset()
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        (**self).as_slice()
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        (**self).as_mut_slice()
+    }
+
+    unsafe fn as_uninit_slice(&mut self) -> &mut [MaybeUninit<u8>] {
+        unsafe { (**self).as_uninit_slice() }
+    }
+
+    unsafe fn assume_init_to(&mut self, len: usize) {
+        unsafe { (**self).assume_init_to(len) }
+    }
+
+    fn len(&self) -> usize {
+        (**self).len()
+    }
+
+    fn capacity(&self) -> usize {
+        (**self).capacity()
+    }
+}
+
+/// 複数スレッドから共有できるプール: リースの返却先
+pub trait Recycle {
+    type Buffer;
+
+    fn recycle(&self, buffer: Self::Buffer);
+}
+
+/// into_sendのリースが返却キューで運ぶもの（スロットとバッファ）
+struct Returned<B> {
+    index: usize,
+    #[cfg(debug_assertions)]
+    lease_id: u64,
+    buffer: B,
+}
+
+/// BufferPoolのリースをどのスレッドでもdropできるようにしたもの（Lease::into_sendが返す）
+/// スロットはプールで貸出中のままなので、同時貸出数や上限、貸出時間の数え方は元のリースと変わらない
+/// dropすると返却キューを通ってプールのスレッドで返る。プールがdropされていれば、返さずにその場で解放する
+pub struct SendLease<B> {
+    returns: Sender<Returned<B>>,
+    buffer: ManuallyDrop<B>,
+    index: usize,
+    #[cfg(debug_assertions)]
+    lease_id: u64,
+}
+
+impl<B> SendLease<B> {
+    /// 元のリースのスロットの番号
+    #[inline]
+    pub fn slot(&self) -> usize {
+        self.index
+    }
+}
+
+impl<B> Drop for SendLease<B> {
+    #[inline]
+    fn drop(&mut self) {
+        // SAFETY: Drop以外でbufferを取り出すことはない
+        let buffer = unsafe { ManuallyDrop::take(&mut self.buffer) };
+        // 受け口がなければ（プールがdropされていれば）SendErrorごとバッファを解放する
+        drop(self.returns.send(Returned {
+            index: self.index,
+            #[cfg(debug_assertions)]
+            lease_id: self.lease_id,
+            buffer,
+        }));
+    }
+}
+
+impl<B> Deref for SendLease<B> {
+    type Target = B;
+
+    #[inline]
+    fn deref(&self) -> &B {
+        &self.buffer
+    }
+}
+
+impl<B> DerefMut for SendLease<B> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut B {
+        &mut self.buffer
+    }
+}
+
+impl<B: Buffer> Buffer for SendLease<B> {
+    unsafe fn ptr(&self) -> *mut u8 {
+        unsafe { (**self).ptr() }
+    }
+
+    fn as_mut_ptr(&mut self) -> *mut u8 {
+        (**self).as_mut_ptr()
+    }
+
+    fn reset(&mut self) {
+        (**self).reset();
+    }
+
+    fn try_reset(&mut self) -> std::io::Result<()> {
+        (**self).try_reset()
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        (**self).as_slice()
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        (**self).as_mut_slice()
+    }
+
+    unsafe fn as_uninit_slice(&mut self) -> &mut [MaybeUninit<u8>] {
+        unsafe { (**self).as_uninit_slice() }
+    }
+
+    unsafe fn assume_init_to(&mut self, len: usize) {
+        unsafe { (**self).assume_init_to(len) }
+    }
+
+    fn len(&self) -> usize {
+        (**self).len()
+    }
+
+    fn capacity(&self) -> usize {
+        (**self).capacity()
+    }
+}
+
+impl<B: Buffer> AsRef<[u8]> for SendLease<B> {
+    #[inline]
+    fn as_ref(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
+
+impl<B: Buffer> AsMut<[u8]> for SendLease<B> {
+    #[inline]
+    fn as_mut(&mut self) -> &mut [u8] {
+        self.as_mut_slice()
+    }
+}
+
+/// 共有プールのリース: バッファを所有し、Dropでプールへ返す
+/// スレッドをまたいで返却できる（バッファがSendならSend）
+pub struct SyncLease<'a, P: Recycle> {
+    pool: &'a P,
+    buffer: ManuallyDrop<P::Buffer>,
+}
+
+impl<P: Recycle> Drop for SyncLease<'_, P> {
+    #[inline]
+    fn drop(&mut self) {
+        // SAFETY: Drop以外でbufferを取り出すことはない
+        let buffer = unsafe { ManuallyDrop::take(&mut self.buffer) };
+        self.pool.recycle(buffer);
+    }
+}
+
+impl<P: Recycle> Deref for SyncLease<'_, P> {
+    type Target = P::Buffer;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.buffer
+    }
+}
+
+impl<P: Recycle> DerefMut for SyncLease<'_, P> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.buffer
+    }
+}
+
+impl<P: Recycle> Buffer for SyncLease<'_, P>
+where
+    P::Buffer: Buffer,
+{
+    unsafe fn ptr(&self) -> *mut u8 {
+        unsafe { (**self).ptr() }
+    }
+
+    fn as_mut_ptr(&mut self) -> *mut u8 {
+        (**self).as_mut_ptr()
+    }
+
+    fn reset(&mut self) {
+        (**self).reset();
+    }
+
+    fn try_reset(&mut self) -> std::io::Result<()> {
+        (**self).try_reset()
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        (**self).as_slice()
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        (**self).as_mut_slice()
+    }
+
+    unsafe fn as_uninit_slice(&mut self) -> &mut [MaybeUninit<u8>] {
+        unsafe { (**self).as_uninit_slice() }
+    }
+
+    unsafe fn assume_init_to(&mut self, len: usize) {
+        unsafe { (**self).assume_init_to(len) }
+    }
+
+    fn len(&self) -> usize {
+        (**self).len()
+    }
+
+    fn capacity(&self) -> usize {
+        (**self).capacity()
+    }
+}
+
+impl<P: Recycle> AsRef<[u8]> for SyncLease<'_, P>
+where
+    P::Buffer: Buffer,
+{
+    #[inline]
+    fn as_ref(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
+
+impl<P: Recycle> AsMut<[u8]> for SyncLease<'_, P>
+where
+    P::Buffer: Buffer,
+{
+    #[inline]
+    fn as_mut(&mut self) -> &mut [u8] {
+        self.as_mut_slice()
+    }
+}
+
+impl<P: Recycle> SyncLease<'_, P> {
+    /// 同じプールへの`Arc`を持つリースに変える（返却はOwnedSyncLeaseのDropで行う）
+    fn into_owned(self, pool: &Arc<P>) -> OwnedSyncLease<P> {
+        debug_assert!(std::ptr::eq(self.pool, Arc::as_ptr(pool)));
+        let mut this = ManuallyDrop::new(self);
+        // SAFETY: thisはdropしないので、bufferを取り出すのはここだけ
+        let buffer = unsafe { ManuallyDrop::take(&mut this.buffer) };
+        OwnedSyncLease {
+            pool: Arc::clone(pool),
+            buffer: ManuallyDrop::new(buffer),
+        }
+    }
+}
+
+/// 共有プールへの`Arc`を持つ、ライフタイムのないリース
+/// プールとバッファがSend / SyncならSendなので、別スレッドのタスクへ移したりBytesの持ち主にしたりできる
+pub struct OwnedSyncLease<P: Recycle> {
+    pool: Arc<P>,
+    buffer: ManuallyDrop<P::Buffer>,
+}
+
+impl<P: Recycle> Drop for OwnedSyncLease<P> {
+    #[inline]
+    fn drop(&mut self) {
+        // SAFETY: Drop以外でbufferを取り出すことはない
+        let buffer = unsafe { ManuallyDrop::take(&mut self.buffer) };
+        self.pool.recycle(buffer);
+    }
+}
+
+impl<P: Recycle> Deref for OwnedSyncLease<P> {
+    type Target = P::Buffer;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.buffer
+    }
+}
+
+impl<P: Recycle> DerefMut for OwnedSyncLease<P> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.buffer
+    }
+}
+
+impl<P: Recycle> Buffer for OwnedSyncLease<P>
+where
+    P::Buffer: Buffer,
+{
+    unsafe fn ptr(&self) -> *mut u8 {
+        unsafe { (**self).ptr() }
+    }
+
+    fn as_mut_ptr(&mut self) -> *mut u8 {
+        (**self).as_mut_ptr()
+    }
+
+    fn reset(&mut self) {
+        (**self).reset();
+    }
+
+    fn try_reset(&mut self) -> std::io::Result<()> {
+        (**self).try_reset()
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        (**self).as_slice()
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        (**self).as_mut_slice()
+    }
+
+    unsafe fn as_uninit_slice(&mut self) -> &mut [MaybeUninit<u8>] {
+        unsafe { (**self).as_uninit_slice() }
+    }
+
+    unsafe fn assume_init_to(&mut self, len: usize) {
+        unsafe { (**self).assume_init_to(len) }
+    }
+
+    fn len(&self) -> usize {
+        (**self).len()
+    }
+
+    fn capacity(&self) -> usize {
+        (**self).capacity()
+    }
+}
+
+impl<P: Recycle> AsRef<[u8]> for OwnedSyncLease<P>
+where
+    P::Buffer: Buffer,
+{
+    #[inline]
+    fn as_ref(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
+
+impl<P: Recycle> AsMut<[u8]> for OwnedSyncLease<P>
+where
+    P::Buffer: Buffer,
+{
+    #[inline]
+    fn as_mut(&mut self) -> &mut [u8] {
+        self.as_mut_slice()
+    }
+}
+
+/// 空きバッファをMutexで守ったVecに積む共有プール
+pub struct MutexBufferPool<A: BufferAllocator> {
+    allocator: A,
+    free: Mutex<Vec<A::Buffer>>,
+}
+
+impl<A: BufferAllocator> MutexBufferPool<A> {
+    pub fn new(allocator: A) -> Self {
+        MutexBufferPool {
+            allocator,
+            free: Mutex::new(Vec::new()),
+        }
+    }
+
+    #[inline]
+    pub fn lease(&self) -> Result<SyncLease<'_, Self>, A::Error> {
+        let reused = self.free.lock().unwrap().pop();
+        // 新規アロケーションはロックの外で行う
+        let buffer = match reused {
+            Some(buffer) => buffer,
+            None => self.allocator.allocate()?,
+        };
+        Ok(SyncLease {
+            pool: self,
+            buffer: ManuallyDrop::new(buffer),
+        })
+    }
+
+    /// プールへの`Arc`を持つリースを返す
+    #[inline]
+    pub fn lease_owned(self: &Arc<Self>) -> Result<OwnedSyncLease<Self>, A::Error> {
+        Ok(self.lease()?.into_owned(self))
+    }
+}
+
+impl<A: BufferAllocator> Recycle for MutexBufferPool<A> {
+    type Buffer = A::Buffer;
+
+    #[inline]
+    fn recycle(&self, buffer: A::Buffer) {
+        self.free.lock().unwrap().push(buffer);
+    }
+}
+
+/// 空きバッファをロックフリーのMPMCキュー (crossbeam ArrayQueue) に置く共有プール
+/// キューに入りきらない返却バッファは解放する
+pub struct LockFreeBufferPool<A: BufferAllocator> {
+    allocator: A,
+    free: ArrayQueue<A::Buffer>,
+}
+
+impl<A: BufferAllocator> LockFreeBufferPool<A> {
+    /// max_idle: プールに保持しておく空きバッファの最大数
+    pub fn new(allocator: A, max_idle: usize) -> Self {
+        LockFreeBufferPool {
+            allocator,
+            free: ArrayQueue::new(max_idle),
+        }
+    }
+
+    #[inline]
+    pub fn lease(&self) -> Result<SyncLease<'_, Self>, A::Error> {
+        let buffer = match self.free.pop() {
+            Some(buffer) => buffer,
+            None => self.allocator.allocate()?,
+        };
+        Ok(SyncLease {
+            pool: self,
+            buffer: ManuallyDrop::new(buffer),
+        })
+    }
+
+    /// プールへの`Arc`を持つリースを返す
+    #[inline]
+    pub fn lease_owned(self: &Arc<Self>) -> Result<OwnedSyncLease<Self>, A::Error> {
+        Ok(self.lease()?.into_owned(self))
+    }
+}
+
+impl<A: BufferAllocator> Recycle for LockFreeBufferPool<A> {
+    type Buffer = A::Buffer;
+
+    #[inline]
+    fn recycle(&self, buffer: A::Buffer) {
+        // いっぱいならそのまま解放する
+        drop(self.free.push(buffer));
+    }
+}
+
+/// スレッドに振った番号（DeferredBufferPoolのシャードを選ぶ）
+fn thread_slot() -> usize {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    thread_local! {
+        static SLOT: usize = NEXT.fetch_add(1, Ordering::Relaxed);
+    }
+    SLOT.with(|slot| *slot)
+}
+
+/// DeferredBufferPoolのスレッドごとの空きバッファ（隣のシャードとキャッシュラインを共有しない）
+#[repr(align(128))]
+struct Shard<B> {
+    /// 持ち主のスレッドが貸し出し・返却する空きバッファ
+    /// （スレッド数がシャード数を超えたときだけ競合するので、ふだんのロックは空振りで済む）
+    local: Mutex<Vec<B>>,
+    /// 他のスレッドから返されたバッファ。持ち主は空きを切らしたときにまとめて取り込む
+    remote: SegQueue<B>,
+}
+
+/// snmallocのメッセージパッシングのように、バッファを借りたスレッドのシャードへ返す共有プール
+/// 同じスレッドで返せばロックの競合なしに再利用し、別スレッドで返したバッファは持ち主のキューに積んで後からまとめて取り込む
+pub struct DeferredBufferPool<A: BufferAllocator> {
+    allocator: A,
+    shards: Box<[Shard<A::Buffer>]>,
+}
+
+impl<A: BufferAllocator> DeferredBufferPool<A> {
+    /// shards: シャードの数（使うスレッド数にする）
+    pub fn new(allocator: A, shards: usize) -> Self {
+        assert!(shards > 0, "DeferredBufferPool needs at least one shard");
+        DeferredBufferPool {
+            allocator,
+            shards: (0..shards)
+                .map(|_| Shard {
+                    local: Mutex::new(Vec::new()),
+                    remote: SegQueue::new(),
+                })
+                .collect(),
+        }
+    }
+
+    #[inline]
+    fn current_shard(&self) -> usize {
+        thread_slot() % self.shards.len()
+    }
+
+    #[inline]
+    pub fn lease(&self) -> Result<DeferredLease<'_, A>, A::Error> {
+        let owner = self.current_shard();
+        let shard = &self.shards[owner];
+        let reused = {
+            let mut local = shard.local.lock().unwrap();
+            local.pop().or_else(|| {
+                // 空きを切らしたら、他のスレッドから返されたバッファを今ある分だけまとめて取り込む
+                let pending = shard.remote.len();
+                local.extend(std::iter::from_fn(|| shard.remote.pop()).take(pending));
+                local.pop()
+            })
+        };
+        // 新規アロケーションはロックの外で行う
+        let buffer = match reused {
+            Some(buffer) => buffer,
+            None => self.allocator.allocate()?,
+        };
+        Ok(DeferredLease {
+            pool: self,
+            owner,
+            buffer: ManuallyDrop::new(buffer),
+        })
+    }
+
+    /// バッファを持ち主のシャードへ返す
+    #[inline]
+    fn recycle(&self, owner: usize, buffer: A::Buffer) {
+        if self.current_shard() == owner {
+            self.shards[owner].local.lock().unwrap().push(buffer);
+        } else {
+            self.shards[owner].remote.push(buffer);
+        }
+    }
+}
+
+/// DeferredBufferPoolのリース: 借りたスレッドのシャードを覚えておき、Dropでそこへ返す
+/// スレッドをまたいで返却できる（バッファがSendならSend）
+pub struct DeferredLease<'a, A: BufferAllocator> {
+    pool: &'a DeferredBufferPool<A>,
+    owner: usize,
+    buffer: ManuallyDrop<A::Buffer>,
+}
+
+impl<A: BufferAllocator> Drop for DeferredLease<'_, A> {
+    #[inline]
+    fn drop(&mut self) {
+        // SAFETY: Drop以外でbufferを取り出すことはない
+        let buffer = unsafe { ManuallyDrop::take(&mut self.buffer) };
+        self.pool.recycle(self.owner, buffer);
+    }
+}
+
+impl<A: BufferAllocator> Deref for DeferredLease<'_, A> {
+    type Target = A::Buffer;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.buffer
+    }
+}
+
+impl<A: BufferAllocator> DerefMut for DeferredLease<'_, A> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.buffer
+    }
+}
+
+/// プールの統計を一定間隔で記録し、ハーネスの結果と同じ形式（parquet / csv / jsonl）の時系列として書き出す
+/// 長く動かすプールの使われ方を、ベンチマークの結果と同じ道具（DuckDB、polars）で分析できる
+#[cfg(feature = "arrow")]
+pub mod stats_log {
+    use super::{Buffer, BufferAllocator, BufferPool, PoolStats};
+    use crate::output::{create_parent_dir, FileWriter, Format, ParquetOptions};
+    use arrow::array::{ArrayRef, StringArray, UInt64Array};
+    use arrow::datatypes::{DataType, Field, Schema};
+    use arrow::record_batch::RecordBatch;
+    use log::warn;
+    use std::collections::BTreeMap;
+    use std::error::Error;
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+    use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+    // これだけたまったらまとめてライターへ渡す
+    const FLUSH_ROWS: usize = 1024;
+
+    // 1回分の記録
+    struct Snapshot {
+        timestamp_ms: u64,
+        stats: PoolStats,
+    }
+
+    fn schema() -> Schema {
+        Schema::new(vec![
+            Field::new("pool", DataType::Utf8, false),
+            Field::new("timestamp_ms", DataType::UInt64, false),
+            Field::new("leases", DataType::UInt64, false),
+            Field::new("reused", DataType::UInt64, false),
+            Field::new("allocated", DataType::UInt64, false),
+            Field::new("live", DataType::UInt64, false),
+            Field::new("peak_live", DataType::UInt64, false),
+            Field::new("idle", DataType::UInt64, false),
+            Field::new("bytes", DataType::UInt64, false),
+        ])
+    }
+
+    fn to_record_batch(pool: &str, snapshots: &[Snapshot]) -> Result<RecordBatch, Box<dyn Error>> {
+        let column = |f: fn(&Snapshot) -> u64| -> ArrayRef {
+            Arc::new(UInt64Array::from_iter_values(snapshots.iter().map(f)))
+        };
+        let batch = RecordBatch::try_new(
+            Arc::new(schema()),
+            vec![
+                Arc::new(StringArray::from(vec![pool; snapshots.len()])) as ArrayRef,
+                column(|s| s.timestamp_ms),
+                column(|s| s.stats.leases),
+                column(|s| s.stats.reused),
+                column(|s| s.stats.allocated),
+                column(|s| s.stats.live as u64),
+                column(|s| s.stats.peak_live as u64),
+                column(|s| s.stats.idle as u64),
+                column(|s| s.stats.bytes as u64),
+            ],
+        )?;
+        Ok(batch)
+    }
+
+    /// BufferPool::statsを時刻付きで記録して書き出す
+    /// ファイルはfinishかdropで完成する（それまでは一時ファイルに書く）ので、Maintenance::on_statsに渡したままでもよい
+    pub struct StatsRecorder {
+        pool: String,
+        interval: Duration,
+        last: Option<Instant>,
+        pending: Vec<Snapshot>,
+        writer: Option<FileWriter>,
+        written: usize,
+    }
+
+    impl StatsRecorder {
+        /// pathに書き出すレコーダーを作る（形式は拡張子から決め、parquetの設定はハーネスのデフォルトと同じ）
+        /// poolは記録に付けるプールの名前（複数のプールの記録を1つのデータセットにまとめたときに区別する）
+        /// sampleはintervalごとに1回だけ記録する
+        pub fn create(
+            path: impl AsRef<Path>,
+            pool: &str,
+            interval: Duration,
+        ) -> Result<Self, Box<dyn Error>> {
+            Self::with_options(path, pool, interval, &ParquetOptions::default())
+        }
+
+        /// parquetの圧縮やrow groupの大きさを指定して作る
+        pub fn with_options(
+            path: impl AsRef<Path>,
+            pool: &str,
+            interval: Duration,
+            options: &ParquetOptions,
+        ) -> Result<Self, Box<dyn Error>> {
+            let path = path.as_ref();
+            create_parent_dir(path)?;
+            let writer = FileWriter::create(
+                path.to_path_buf(),
+                Format::from_path(path),
+                Arc::new(schema()),
+                &options.writer_properties(&BTreeMap::new()),
+            )?;
+            Ok(StatsRecorder {
+                pool: pool.to_string(),
+                interval,
+                last: None,
+                pending: Vec::new(),
+                writer: Some(writer),
+                written: 0,
+            })
+        }
+
+        /// 前回の記録からinterval以上たっていればpoolの統計を記録し、trueを返す
+        /// イベントループやリクエストの処理から毎回呼んでよい
+        pub fn sample<A>(&mut self, pool: &BufferPool<A>) -> Result<bool, Box<dyn Error>>
+        where
+            A: BufferAllocator,
+            A::Buffer: Buffer,
+        {
+            let now = Instant::now();
+            if self
+                .last
+                .is_some_and(|last| now.duration_since(last) < self.interval)
+            {
+                return Ok(false);
+            }
+            self.last = Some(now);
+            self.record(pool.stats())?;
+            Ok(true)
+        }
+
+        /// statsを今の時刻で記録する（intervalは見ない）
+        pub fn record(&mut self, stats: PoolStats) -> Result<(), Box<dyn Error>> {
+            let timestamp_ms = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_millis() as u64);
+            self.pending.push(Snapshot {
+                timestamp_ms,
+                stats,
+            });
+            if self.pending.len() >= FLUSH_ROWS {
+                self.flush()?;
+            }
+            Ok(())
+        }
+
+        /// たまっている記録をライターへ渡す
+        pub fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+            let Some(writer) = self.writer.as_mut() else {
+                return Ok(());
+            };
+            if self.pending.is_empty() {
+                return Ok(());
+            }
+            writer.write(&to_record_batch(&self.pool, &self.pending)?)?;
+            self.written += self.pending.len();
+            self.pending.clear();
+            Ok(())
+        }
+
+        /// ライターへ渡した記録の数
+        pub fn written(&self) -> usize {
+            self.written
+        }
+
+        /// 残りを書き出してファイルを閉じ、そのパスを返す
+        pub fn finish(mut self) -> Result<PathBuf, Box<dyn Error>> {
+            self.flush()?;
+            let writer = self.writer.take().expect("stats recorder already finished");
+            writer.finish()
+        }
+
+        /// Maintenance::on_statsに渡す関数にする（書き出しに失敗したら警告を出して続ける）
+        pub fn into_on_stats(mut self) -> impl FnMut(PoolStats) + 'static {
+            move |stats| {
+                if let Err(err) = self.record(stats) {
+                    warn!("Failed to record stats of pool {}: {}", self.pool, err);
+                }
+            }
+        }
+    }
+
+    impl Drop for StatsRecorder {
+        fn drop(&mut self) {
+            if self.writer.is_none() {
+                return;
+            }
+            let result = self
+                .flush()
+                .and_then(|()| self.writer.take().expect("writer checked above").finish());
+            if let Err(err) = result {
+                warn!("Failed to write stats of pool {}: {}", self.pool, err);
+            }
+        }
+    }
+}
+
+/// bytesクレートとの相互運用（bytes feature）
+/// 書き込みはBufferCursorをBufMutとして使い、書き終えたらfreezeでBytesに変える
+#[cfg(feature = "bytes")]
+mod bytes_interop {
+    use super::{Buffer, BufferCursor, OwnedSyncLease, Recycle, SendLease};
+    use bytes::{buf::UninitSlice, BufMut, Bytes};
+
+    // SAFETY: chunk_mutは位置から末尾までの領域を返し、advance_mutはその範囲内でしか進めない
+    // io::Writeと同じく、初期化済みの範囲より先の位置に書くときは間を0で埋めてから位置に書く
+    // 未初期化のバッファでは、advance_mutで書き込まれた範囲までを初期化済みにする
+    unsafe impl<B: Buffer> BufMut for BufferCursor<B> {
+        #[inline]
+        fn remaining_mut(&self) -> usize {
+            self.buf.capacity().saturating_sub(self.pos)
+        }
+
+        #[inline]
+        unsafe fn advance_mut(&mut self, cnt: usize) {
+            assert!(
+                cnt <= self.remaining_mut(),
+                "advance_mut past the end of the buffer"
+            );
+            // chunk_mutで埋めていれば何もしない
+            self.fill_gap();
+            let end = self.pos + cnt;
+            if end > self.buf.len() {
+                // SAFETY: 呼び出し側がchunk_mutのcntバイトに書き込んでいて、posより前は初期化済み
+                unsafe { self.buf.assume_init_to(end) };
+            }
+            self.pos = end;
+            self.filled = self.filled.max(self.pos);
+        }
+
+        #[inline]
+        fn chunk_mut(&mut self) -> &mut UninitSlice {
+            self.fill_gap();
+            let start = self.pos.min(self.buf.capacity());
+            // SAFETY: UninitSliceは未初期化の値を書き込めない
+            let bytes = unsafe { self.buf.as_uninit_slice() };
+            UninitSlice::uninit(&mut bytes[start..])
+        }
+    }
+
+    impl<P> BufferCursor<OwnedSyncLease<P>>
+    where
+        P: Recycle + Send + Sync + 'static,
+        P::Buffer: Buffer + Send + 'static,
+    {
+        /// 書き込んだ範囲をコピーせずにBytesにする
+        /// リースはBytes（とそのクローン）がすべてdropされたときにプールへ返る
+        pub fn freeze(self) -> Bytes {
+            let filled = self.filled;
+            Bytes::from_owner(self.buf).slice(..filled)
+        }
+    }
+
+    impl<B: Buffer + Send + 'static> BufferCursor<SendLease<B>> {
+        /// OwnedSyncLease版と同じ（リースはBytesがすべてdropされたときにBufferPoolへ返る）
+        pub fn freeze(self) -> Bytes {
+            let filled = self.filled;
+            Bytes::from_owner(self.buf).slice(..filled)
+        }
+    }
+}
+
+/// tokioのAsyncReadとの相互運用（tokio feature）
+/// BufferCursorの位置から先をReadBufとして渡し、未初期化のバッファもゼロクリアせずにpoll_readで埋める
+#[cfg(feature = "tokio")]
+mod read_buf_interop {
+    use super::{Buffer, BufferCursor};
+    use std::{
+        pin::Pin,
+        task::{Context, Poll},
+    };
+    use tokio::io::{AsyncRead, ReadBuf};
+
+    impl<B: Buffer> BufferCursor<B> {
+        /// バッファ全体をReadBufとしてfに渡す（filledは位置、initializedはバッファの初期化済みの長さ）
+        /// fが埋めた範囲の末尾に位置を進め、ReadBufが初期化した範囲までをバッファの初期化済みにする
+        /// 初期化済みの範囲より先の位置からは、io::Writeと同じく間を0で埋めてから位置に読み込む
+        pub fn with_read_buf<T>(&mut self, f: impl FnOnce(&mut ReadBuf<'_>) -> T) -> T {
+            self.fill_gap();
+            let init = self.buf.len();
+            let start = self.pos.min(self.buf.capacity());
+            // SAFETY: ReadBufは初期化済みの範囲を未初期化に戻さない
+            let bytes = unsafe { self.buf.as_uninit_slice() };
+            let mut read_buf = ReadBuf::uninit(bytes);
+            // SAFETY: 先頭initバイトはバッファが初期化済みとしている範囲
+            unsafe { read_buf.assume_init(init) };
+            read_buf.set_filled(start);
+            let out = f(&mut read_buf);
+            let pos = read_buf.filled().len();
+            let initialized = read_buf.initialized().len();
+            if initialized > init {
+                // SAFETY: ReadBufが先頭initializedバイトを初期化済みとしている
+                unsafe { self.buf.assume_init_to(initialized) };
+            }
+            self.pos = pos;
+            self.filled = self.filled.max(pos);
+            out
+        }
+
+        /// readerのpoll_readで位置から先を埋め、読んだバイト数を返す（0ならEOFかバッファの末尾）
+        pub fn poll_read_from<R: AsyncRead + ?Sized>(
+            &mut self,
+            reader: Pin<&mut R>,
+            cx: &mut Context<'_>,
+        ) -> Poll<std::io::Result<usize>> {
+            self.with_read_buf(|buf| {
+                let before = buf.filled().len();
+                reader
+                    .poll_read(cx, buf)
+                    .map_ok(|()| buf.filled().len() - before)
+            })
+        }
+
+        /// poll_read_fromのasync版
+        pub async fn read_from<R: AsyncRead + Unpin + ?Sized>(
+            &mut self,
+            reader: &mut R,
+        ) -> std::io::Result<usize> {
+            std::future::poll_fn(|cx| self.poll_read_from(Pin::new(&mut *reader), cx)).await
+        }
+    }
+}
+
+/// BufferPoolを定期的に手入れするtokioのタスク（tokio feature）
+/// リクエストの処理中にmaintainやtrimを呼ばなくても、期限切れの空きバッファを解放して統計を出せる
+#[cfg(feature = "tokio")]
+pub mod maintenance {
+    use super::{Buffer, BufferAllocator, BufferPool, PoolStats};
+    use std::{
+        sync::{Arc, Weak},
+        time::Duration,
+    };
+
+    /// メンテナンスタスクの設定
+    /// intervalごとにmaintainで期限切れの空きバッファを解放し、keep_idleを超える空きバッファをtrimし、statsをon_statsに渡す
+    pub struct Maintenance<F> {
+        interval: Duration,
+        keep_idle: Option<usize>,
+        on_stats: F,
+    }
+
+    impl Maintenance<fn(PoolStats)> {
+        pub fn new(interval: Duration) -> Self {
+            Maintenance {
+                interval,
+                keep_idle: None,
+                on_stats: |_| {},
+            }
+        }
+    }
+
+    impl<F: FnMut(PoolStats) + 'static> Maintenance<F> {
+        /// 毎回trimで空きバッファをkeep個まで減らす
+        pub fn keep_idle(mut self, keep: usize) -> Self {
+            self.keep_idle = Some(keep);
+            self
+        }
+
+        /// 毎回のstatsを受け取る（メトリクスのエクスポータに渡すなど）
+        pub fn on_stats<G: FnMut(PoolStats) + 'static>(self, on_stats: G) -> Maintenance<G> {
+            Maintenance {
+                interval: self.interval,
+                keep_idle: self.keep_idle,
+                on_stats,
+            }
+        }
+
+        /// プールが生きている間メンテナンスを繰り返す（プールがdropされたら終わる）
+        /// BufferPoolはSendではないので、LocalSetの中でspawn_localするか、spawn_maintenanceを使う
+        pub async fn run<A>(mut self, pool: Weak<BufferPool<A>>)
+        where
+            A: BufferAllocator,
+            A::Buffer: Buffer,
+        {
+            let mut ticker = tokio::time::interval(self.interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            // 最初のtickはすぐに終わるので、1周期待ってから始める
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                let Some(pool) = pool.upgrade() else {
+                    return;
+                };
+                pool.maintain();
+                if let Some(keep) = self.keep_idle {
+                    pool.trim(keep);
+                }
+                (self.on_stats)(pool.stats());
+            }
+        }
+    }
+
+    impl<A> BufferPool<A>
+    where
+        A: BufferAllocator + 'static,
+        A::Buffer: Buffer + 'static,
+    {
+        /// メンテナンスタスクを現在のLocalSetにspawn_localする（プールへの参照は弱参照で持つ）
+        pub fn spawn_maintenance<F: FnMut(PoolStats) + 'static>(
+            self: &Arc<Self>,
+            maintenance: Maintenance<F>,
+        ) -> tokio::task::JoinHandle<()> {
+            tokio::task::spawn_local(maintenance.run(Arc::downgrade(self)))
+        }
+    }
+}
+
+// LockFreeBufferPoolのArrayQueueはloomで差し替えられないので、ロックを使うMutexBufferPoolだけを検査する
+#[cfg(all(test, feature = "loom"))]
+mod loom_tests {
+    use super::{BoxedSliceAllocator, Buffer, MutexBufferPool};
+    use loom::sync::Arc;
+    use loom::thread;
+
+    // 2スレッドが同時に借りて返しても、同じバッファを同時に貸し出さず、返したバッファは再利用される
+    #[test]
+    fn concurrent_lease_and_drop() {
+        loom::model(|| {
+            let pool = Arc::new(MutexBufferPool::new(BoxedSliceAllocator::new(1)));
+            // 1つは返却済みにしておき、再利用と新規確保の両方の経路を通す
+            drop(pool.lease().unwrap());
+            let handles: Vec<_> = (1..=2)
+                .map(|value| {
+                    let pool = Arc::clone(&pool);
+                    thread::spawn(move || {
+                        let mut lease = pool.lease().unwrap();
+                        lease.as_mut_slice()[0] = value;
+                        thread::yield_now();
+                        assert_eq!(lease.as_slice()[0], value);
+                    })
+                })
+                .collect();
+            for handle in handles {
+                handle.join().unwrap();
+            }
+            let idle = pool.free.lock().unwrap().len();
+            assert!((1..=2).contains(&idle));
+        });
+    }
+}
+
+// リースの読み書きがポインタの規則を破らないかをMiriで検査する（cargo +nightly miri test miri_tests）
+// バッファをエントリに直接置く[u8; N]や未初期化のバッファで、借りている間にプールの他のエントリが動く経路を通す
+#[cfg(test)]
+mod miri_tests {
+    use super::{
+        ArrayAllocator, Buffer, BufferCursor, BufferPool, PoolHandle, UninitAllocator, VecAllocator,
+    };
+    use std::io::{Read, Seek, SeekFrom, Write};
+
+    // 書き込み中のスライスを持ったまま、同じプールで他のリースを借りて返す
+    #[test]
+    fn array_write_while_other_leases_change() {
+        let pool = BufferPool::new(ArrayAllocator::<16>);
+        let mut a = pool.lease().unwrap();
+        let bytes = a.as_mut_slice();
+        bytes[0] = 1;
+        let mut b = pool.lease().unwrap();
+        b.as_mut_slice()[0] = 2;
+        drop(b);
+        let _c = pool.lease().unwrap();
+        bytes[1] = 3;
+        assert_eq!(&a.as_slice()[..2], &[1, 3]);
+    }
+
+    // 返したバッファは中身を残したまま次のリースに渡る
+    #[test]
+    fn vec_reuse_keeps_contents() {
+        let pool = BufferPool::new(VecAllocator::new(8));
+        let mut a = pool.lease().unwrap();
+        a.as_mut_slice().copy_from_slice(b"abcdefgh");
+        drop(a);
+        let b = pool.lease().unwrap();
+        assert_eq!(b.as_slice(), b"abcdefgh");
+    }
+
+    // ptrのアドレスにinto_rawの間だけ書き込み、lease_from_rawで戻して読む（完了キューのトークンと同じ流れ）
+    #[test]
+    fn raw_token_roundtrip() {
+        let pool = BufferPool::new(ArrayAllocator::<8>);
+        let mut lease = pool.lease().unwrap();
+        let ptr = lease.as_mut_ptr();
+        let slot = lease.into_raw();
+        let _other = pool.lease().unwrap();
+        // SAFETY: 貸出中のバッファの内側で、into_rawしている間は他に誰も触らない
+        unsafe { ptr.add(3).write(7) };
+        // SAFETY: into_rawが返したスロットを1度だけ戻す
+        let lease = unsafe { pool.lease_from_raw(slot) };
+        assert_eq!(lease.as_slice()[3], 7);
+    }
+
+    // splitした区間をそれぞれ書き換える
+    #[test]
+    fn split_slices_write_independently() {
+        let pool = BufferPool::new(ArrayAllocator::<8>);
+        let lease = pool.lease().unwrap();
+        let mut slices = lease.split([3, 5]);
+        let (head, tail) = slices.split_at_mut(1);
+        head[0].fill(1);
+        tail[0].fill(2);
+        head[0][0] = 3;
+        assert_eq!(&*head[0], &[3, 1, 1]);
+        assert_eq!(&*tail[0], &[2; 5]);
+    }
+
+    // 未初期化のバッファにカーソルで書き、初期化した範囲だけを読む
+    #[test]
+    fn uninit_cursor_write_read() {
+        let pool = BufferPool::new(UninitAllocator::<16>);
+        let mut cursor = BufferCursor::new(pool.lease().unwrap());
+        cursor.write_all(b"hello").unwrap();
+        assert_eq!(cursor.get_ref().len(), 5);
+        cursor.seek(SeekFrom::Start(0)).unwrap();
+        let mut out = Vec::new();
+        cursor.read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"hello");
+        let mut lease = cursor.into_inner();
+        assert_eq!(lease.init_from_slice(b"bye"), 3);
+        assert_eq!(lease.as_slice(), b"bye");
+    }
+
+    // 初期化済みの範囲より先にシークしてから書くと、間を0で埋めてシークした位置に書く
+    #[test]
+    fn uninit_cursor_write_after_seek_past_init() {
+        let pool = BufferPool::new(UninitAllocator::<16>);
+        let mut cursor = BufferCursor::new(pool.lease().unwrap());
+        cursor.write_all(b"ab").unwrap();
+        cursor.seek(SeekFrom::Start(8)).unwrap();
+        cursor.write_all(b"cd").unwrap();
+        assert_eq!(cursor.position(), 10);
+        assert_eq!(cursor.filled_slice(), b"ab\0\0\0\0\0\0cd");
+        assert_eq!(cursor.get_ref().len(), 10);
+        cursor.seek(SeekFrom::Start(16)).unwrap();
+        assert_eq!(cursor.write(b"x").unwrap(), 0);
+    }
+
+    // 初期化済みの範囲より先にシークしてからput_sliceすると、io::Writeと同じく間を0で埋めてシークした位置に書く
+    #[cfg(feature = "bytes")]
+    #[test]
+    fn uninit_cursor_put_after_seek_past_init() {
+        use super::MutexBufferPool;
+        use bytes::BufMut;
+        use std::sync::Arc;
+
+        let pool = Arc::new(MutexBufferPool::new(UninitAllocator::<16>));
+        let mut cursor = BufferCursor::new(pool.lease_owned().unwrap());
+        cursor.put_slice(b"ab");
+        cursor.seek(SeekFrom::Start(8)).unwrap();
+        assert_eq!(cursor.remaining_mut(), 8);
+        cursor.put_slice(b"cd");
+        assert_eq!(cursor.position(), 10);
+        assert_eq!(cursor.filled_slice(), b"ab\0\0\0\0\0\0cd");
+        assert_eq!(&cursor.freeze()[..], b"ab\0\0\0\0\0\0cd");
+    }
+
+    // ensure_capacityで伸ばしたリースも、そのままプールへ返せる（デバッグビルドではリースの照合を通る）
+    #[test]
+    fn grown_lease_returns_to_pool() {
+        let pool = BufferPool::new(VecAllocator::new(4));
+        let mut lease = pool.lease().unwrap();
+        lease.ensure_capacity(64).unwrap();
+        assert!(lease.len() >= 64);
+        lease.as_mut_slice()[63] = 1;
+        drop(lease);
+        let lease = pool.lease().unwrap();
+        assert!(lease.len() >= 64);
+        drop(lease);
+
+        let handle = PoolHandle::new(BufferPool::new(VecAllocator::new(4)));
+        let mut owned = handle.lease().unwrap();
+        owned.ensure_capacity(64).unwrap();
+        drop(owned);
+    }
+
+    // OwnedLeaseはハンドルをすべて捨てた後もプールを生かし、返すとプールも解放される
+    #[test]
+    fn owned_lease_outlives_handle() {
+        let handle = PoolHandle::new(BufferPool::new(ArrayAllocator::<4>));
+        let mut lease = handle.lease().unwrap();
+        drop(handle);
+        lease.as_mut_slice().fill(9);
+        assert_eq!(lease.as_slice(), &[9; 4]);
+    }
+}
+
+// プールの数え方（上限、同時貸出数、登録）と返却の経路を検査する
+#[cfg(test)]
+mod tests {
+    use super::{
+        ArrayAllocator, Buffer, BufferAllocator, BufferPool, DeferredBufferPool, LeaseError,
+        LockFreeBufferPool, MemoryRegistrar, ThreadLocalBufferPool, TryLeaseError,
+    };
+    use std::cell::Cell;
+    use std::convert::Infallible;
+    use std::ops::DerefMut;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+    use std::time::Duration;
+
+    // register / deregisterを呼んだ回数を数える
+    #[derive(Clone, Default)]
+    struct CountingRegistrar {
+        registered: Arc<AtomicUsize>,
+        deregistered: Arc<AtomicUsize>,
+    }
+
+    impl<A: BufferAllocator> MemoryRegistrar<A> for CountingRegistrar {
+        fn register(&self, _buffer: &mut A::Buffer) -> Result<(), A::Error> {
+            self.registered.fetch_add(1, Ordering::Relaxed);
+            Ok(())
+        }
+
+        fn deregister(&self, _buffer: &mut A::Buffer) {
+            self.deregistered.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    // into_sendのリースを別スレッドでdropしても、スロットは貸出中のまま数えられ、登録し直さずにプールへ返る
+    #[test]
+    fn send_lease_returns_from_other_thread() {
+        let registrar = CountingRegistrar::default();
+        let pool =
+            BufferPool::with_max_capacity(ArrayAllocator::<8>, 1).with_registrar(registrar.clone());
+        let mut lease = pool.lease().unwrap().into_send();
+        lease.as_mut_slice()[0] = 7;
+        assert_eq!(pool.stats().live, 1);
+        assert!(matches!(
+            pool.try_lease(),
+            Err(TryLeaseError::PoolExhausted)
+        ));
+
+        std::thread::spawn(move || drop(lease)).join().unwrap();
+        let lease = pool.try_lease().unwrap();
+        assert_eq!(lease.as_slice()[0], 7);
+        let stats = pool.stats();
+        assert_eq!((stats.allocated, stats.reused, stats.peak_live), (1, 1, 1));
+        drop(lease);
+
+        assert_eq!(registrar.registered.load(Ordering::Relaxed), 1);
+        assert_eq!(registrar.deregistered.load(Ordering::Relaxed), 0);
+        drop(pool);
+        assert_eq!(registrar.deregistered.load(Ordering::Relaxed), 1);
+    }
+
+    // into_sendのリースはプールより長く使え、返すときにその場で解放される
+    #[test]
+    fn send_lease_outlives_pool() {
+        let pool = BufferPool::new(ArrayAllocator::<8>);
+        let mut lease = pool.lease().unwrap().into_send();
+        drop(pool);
+        lease.as_mut_slice().fill(1);
+        std::thread::spawn(move || drop(lease)).join().unwrap();
+    }
+
+    // localは同じスレッドには同じプールを、別のスレッドには別のプールを返す
+    #[test]
+    fn thread_local_pool_is_per_thread() {
+        let pool = ThreadLocalBufferPool::new(ArrayAllocator::<8>);
+        drop(pool.local().lease().unwrap());
+        assert!(std::ptr::eq(pool.local(), pool.local()));
+        assert_eq!(pool.local().stats().idle, 1);
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                assert_eq!(pool.local().stats().idle, 0);
+            });
+        });
+    }
+
+    // 返却で空きバッファがmax_local_idleを超えた分はoverflowへ移り、他のスレッドが確保の代わりに使う
+    #[test]
+    fn thread_local_pool_spills_on_return() {
+        let pool = ThreadLocalBufferPool::with_overflow(ArrayAllocator::<8>, 1);
+        let local = pool.local();
+        let leases: Vec<_> = (0..3).map(|_| local.lease().unwrap()).collect();
+        drop(leases);
+        assert_eq!(local.stats().idle, 1);
+        assert_eq!(pool.overflow_len(), 2);
+        std::thread::scope(|s| {
+            s.spawn(|| drop(pool.local().lease().unwrap()));
+        });
+        assert_eq!(pool.overflow_len(), 1);
+    }
+
+    // left回だけ確保に成功するアロケータ
+    struct LimitedAllocator {
+        left: Cell<usize>,
+    }
+
+    impl BufferAllocator for LimitedAllocator {
+        type Buffer = [u8; 8];
+        type Error = &'static str;
+
+        fn allocate(&self) -> Result<[u8; 8], &'static str> {
+            match self.left.get() {
+                0 => Err("out of buffers"),
+                n => {
+                    self.left.set(n - 1);
+                    Ok([0; 8])
+                }
+            }
+        }
+    }
+
+    // 返却時にmin_idle個まで確保し直すのに失敗したら、次のleaseがそのエラーを返す
+    #[test]
+    fn refill_error_reaches_next_lease() {
+        let pool = BufferPool::new(LimitedAllocator { left: Cell::new(1) }).with_min_idle(2);
+        drop(pool.lease().unwrap());
+        assert!(matches!(
+            pool.lease(),
+            Err(LeaseError::Allocate("out of buffers"))
+        ));
+        assert!(pool.lease().is_ok());
+    }
+
+    // ttlを過ぎた空きバッファはmaintainと次のleaseで解放され、min_idle個は期限切れでも残る
+    #[test]
+    fn idle_ttl_expires_down_to_min_idle() {
+        let ttl = Duration::from_millis(1);
+        let pool = BufferPool::new(ArrayAllocator::<8>)
+            .with_idle_ttl(ttl)
+            .with_min_idle(1);
+        let leases: Vec<_> = (0..3).map(|_| pool.lease().unwrap()).collect();
+        drop(leases);
+        assert_eq!(pool.stats().idle, 3);
+        std::thread::sleep(ttl * 5);
+        assert_eq!(pool.maintain(), 2);
+        assert_eq!(pool.stats().idle, 1);
+        std::thread::sleep(ttl * 5);
+        assert_eq!(pool.maintain(), 0);
+        assert_eq!(pool.stats().idle, 1);
+
+        let pool = BufferPool::new(ArrayAllocator::<8>).with_idle_ttl(ttl);
+        let leases: Vec<_> = (0..2).map(|_| pool.lease().unwrap()).collect();
+        drop(leases);
+        std::thread::sleep(ttl * 5);
+        let lease = pool.lease().unwrap();
+        assert_eq!(pool.stats().idle, 0);
+        assert_eq!(pool.stats().allocated, 3);
+        drop(lease);
+    }
+
+    // 上限に達して空きがなければtry_leaseはPoolExhaustedを返す。返されたリースは再利用し、detachした分は確保し直せる
+    #[test]
+    fn try_lease_respects_max_capacity() {
+        let pool = BufferPool::with_max_capacity(ArrayAllocator::<8>, 2);
+        let a = pool.try_lease().unwrap();
+        let b = pool.try_lease().unwrap();
+        assert!(matches!(
+            pool.try_lease(),
+            Err(TryLeaseError::PoolExhausted)
+        ));
+        drop(a);
+        let a = pool.try_lease().unwrap();
+        let stats = pool.stats();
+        assert_eq!((stats.allocated, stats.reused, stats.live), (2, 1, 2));
+
+        b.detach();
+        let b = pool.try_lease().unwrap();
+        assert_eq!(pool.stats().allocated, 3);
+        assert!(matches!(
+            pool.try_lease(),
+            Err(TryLeaseError::PoolExhausted)
+        ));
+        // leaseは上限を超えても確保する
+        let c = pool.lease().unwrap();
+        assert_eq!(pool.stats().live, 3);
+        drop((a, b, c));
+    }
+
+    // trimは空きバッファをkeep個だけ残し、shrink_to_fitはすべて解放する
+    #[test]
+    fn trim_and_shrink_to_fit() {
+        let pool = BufferPool::new(ArrayAllocator::<8>);
+        let leases: Vec<_> = (0..4).map(|_| pool.lease().unwrap()).collect();
+        drop(leases);
+        assert_eq!(pool.trim(1), 3);
+        assert_eq!(pool.stats().idle, 1);
+        assert_eq!(pool.trim(1), 0);
+
+        let lease = pool.lease().unwrap();
+        assert_eq!(pool.stats().reused, 1);
+        assert_eq!(pool.shrink_to_fit(), 0);
+        drop(lease);
+        assert_eq!(pool.shrink_to_fit(), 1);
+        let stats = pool.stats();
+        assert_eq!((stats.idle, stats.live, stats.bytes), (0, 0, 0));
+        drop(pool.lease().unwrap());
+        assert_eq!(pool.stats().allocated, 5);
+    }
+
+    // reserveは空きバッファがn個になるまで確保し、上限に達したらそこで止める
+    #[test]
+    fn reserve_stops_at_max_capacity() {
+        let pool = BufferPool::with_max_capacity(ArrayAllocator::<8>, 3);
+        pool.reserve(2).unwrap();
+        assert_eq!((pool.stats().idle, pool.stats().allocated), (2, 2));
+        pool.reserve(1).unwrap();
+        assert_eq!(pool.stats().allocated, 2);
+
+        let lease = pool.lease().unwrap();
+        assert_eq!(pool.stats().reused, 1);
+        pool.reserve(5).unwrap();
+        let stats = pool.stats();
+        assert_eq!((stats.idle, stats.live, stats.allocated), (2, 1, 3));
+        drop(lease);
+    }
+
+    // 同じスロットを2度返すと、フリーリストを壊す前にpanicする
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "was returned after the entry was returned")]
+    fn double_return_panics() {
+        let pool = BufferPool::new(ArrayAllocator::<8>);
+        let slot = pool.lease().unwrap().into_raw();
+        // SAFETY: 2つ目のリースは返却の検査だけに使い、バッファには触らない
+        let (first, second) = unsafe { (pool.lease_from_raw(slot), pool.lease_from_raw(slot)) };
+        drop(first);
+        drop(second);
+    }
+
+    // peak_liveは同時に貸し出した最大数を覚えていて、reset_peakで今の貸出数に戻る
+    #[test]
+    fn peak_live_tracks_concurrent_leases() {
+        let pool = BufferPool::new(ArrayAllocator::<8>);
+        let mut leases: Vec<_> = (0..3).map(|_| pool.lease().unwrap()).collect();
+        leases.truncate(1);
+        let stats = pool.stats();
+        assert_eq!((stats.live, stats.peak_live), (1, 3));
+
+        pool.reset_peak();
+        assert_eq!(pool.stats().peak_live, 1);
+        leases.push(pool.lease().unwrap());
+        assert_eq!(pool.stats().peak_live, 2);
+        drop(leases);
+        assert_eq!(pool.stats().peak_live, 2);
+    }
+
+    // 確保した回数を数えるアロケータ
+    #[derive(Clone, Default)]
+    struct CountingAllocator {
+        allocated: Arc<AtomicUsize>,
+    }
+
+    impl BufferAllocator for CountingAllocator {
+        type Buffer = [u8; 8];
+        type Error = Infallible;
+
+        fn allocate(&self) -> Result<[u8; 8], Infallible> {
+            self.allocated.fetch_add(1, Ordering::Relaxed);
+            Ok([0; 8])
+        }
+    }
+
+    const ROUNDS: usize = 3;
+    const LEASES: u8 = 16;
+
+    // 借りたリースを別スレッドでdropし、借りたスレッドで借り直すと同じバッファが全部戻っているか確かめる
+    fn check_cross_thread_returns<L>(allocated: &AtomicUsize, lease: impl Fn() -> L)
+    where
+        L: DerefMut<Target = [u8; 8]> + Send,
+    {
+        for round in 0..ROUNDS {
+            let mut leases: Vec<L> = (0..LEASES).map(|_| lease()).collect();
+            if round == 0 {
+                for (mark, lease) in (0..LEASES).zip(&mut leases) {
+                    lease[0] = mark;
+                }
+            } else {
+                let mut marks: Vec<u8> = leases.iter().map(|lease| lease[0]).collect();
+                marks.sort_unstable();
+                assert_eq!(marks, (0..LEASES).collect::<Vec<_>>());
+            }
+            std::thread::scope(|s| {
+                s.spawn(move || drop(leases));
+            });
+        }
+        assert_eq!(allocated.load(Ordering::Relaxed), usize::from(LEASES));
+    }
+
+    #[test]
+    fn lock_free_pool_returns_from_other_thread() {
+        let allocator = CountingAllocator::default();
+        let pool = LockFreeBufferPool::new(allocator.clone(), usize::from(LEASES));
+        check_cross_thread_returns(&allocator.allocated, || pool.lease().unwrap());
     }
 
-    unsafe fn size(&self) -> usize {
-        unsafe { (**self).size() }
+    #[test]
+    fn deferred_pool_returns_from_other_thread() {
+        let allocator = CountingAllocator::default();
+        let pool = DeferredBufferPool::new(allocator.clone(), 2);
+        check_cross_thread_returns(&allocator.allocated, || pool.lease().unwrap());
     }
 }
@@ -0,0 +1,241 @@
+use crate::shared::SharedSlab;
+use crate::touch::{touch, touched, Touch};
+use crate::{
+    bufpool, core_for, dispatch_size, expand_thread_counts, parse_thread_counts, pin_thread,
+    resolve_sizes, Data, DataAllocator, ThreadCounts,
+};
+use log::{info, warn};
+use memalloc_results::stats::quantile;
+use quanta::Clock;
+use slab::Slab;
+use std::cell::RefCell;
+use std::hint::black_box;
+use std::sync::{Barrier, Mutex};
+
+#[derive(clap::Args, Debug)]
+pub struct BurstArgs {
+    /// Comma-separated data sizes in bytes (the sizes the main benchmark supports)
+    #[arg(long, value_delimiter = ',', num_args = 1.., default_value = "64,1024,4096")]
+    sizes: Vec<usize>,
+
+    /// Comma-separated thread counts ("max", "1..8" or "sweep" as in the main benchmark)
+    #[arg(long, value_delimiter = ',', num_args = 1.., default_value = "2,4", value_parser = parse_thread_counts)]
+    threads: Vec<ThreadCounts>,
+
+    /// Objects each thread allocates per burst
+    #[arg(long, default_value_t = 1000)]
+    burst: usize,
+
+    /// Bursts per cell
+    #[arg(long, default_value_t = 1000)]
+    bursts: usize,
+}
+
+// バーストで使うアロケータ
+#[derive(Clone, Copy, Debug)]
+enum BurstAllocator {
+    Box,
+    SlabWarm,    // スレッドごとのSlab（バーストをまたいで再利用）
+    BufpoolWarm, // スレッドごとのBufferPool（バーストをまたいで再利用）
+    SlabMutex,   // 全スレッドで共有するMutex<Slab>
+    ShardedSlab, // 全スレッドで共有するsharded_slab::Slab
+}
+
+impl BurstAllocator {
+    fn as_str(&self) -> &'static str {
+        match self {
+            BurstAllocator::Box => "box",
+            BurstAllocator::SlabWarm => "slab_warm",
+            BurstAllocator::BufpoolWarm => "bufpool_warm",
+            BurstAllocator::SlabMutex => "slab_mutex",
+            BurstAllocator::ShardedSlab => "sharded_slab",
+        }
+    }
+
+    fn all() -> &'static [BurstAllocator] {
+        &[
+            BurstAllocator::Box,
+            BurstAllocator::SlabWarm,
+            BurstAllocator::BufpoolWarm,
+            BurstAllocator::SlabMutex,
+            BurstAllocator::ShardedSlab,
+        ]
+    }
+}
+
+// バリアで全スレッドを揃えてからburst個を一斉に確保し、確保にかかった時間をバーストごとに返す
+// 解放は計測の外で行う。最初の1回はウォームアップとして捨てる
+fn bursts<H>(
+    clock: &Clock,
+    barrier: &Barrier,
+    args: &BurstArgs,
+    mut alloc: impl FnMut() -> H,
+    mut free: impl FnMut(H),
+) -> Vec<u64> {
+    let mut handles = Vec::with_capacity(args.burst);
+    let mut samples = Vec::with_capacity(args.bursts);
+    for i in 0..=args.bursts {
+        barrier.wait();
+        let start = clock.raw();
+        for _ in 0..args.burst {
+            handles.push(alloc());
+        }
+        let end = clock.raw();
+        if i > 0 {
+            samples.push(clock.delta(start, end).as_nanos() as u64);
+        }
+        for h in handles.drain(..) {
+            free(black_box(h));
+        }
+    }
+    samples
+}
+
+fn burst_thread<T: Default + Send + Sync + Touch>(
+    allocator: BurstAllocator,
+    clock: &Clock,
+    barrier: &Barrier,
+    args: &BurstArgs,
+    slab_mutex: &Mutex<Slab<T>>,
+    sharded: &sharded_slab::Slab<T>,
+) -> Vec<u64> {
+    match allocator {
+        BurstAllocator::Box => bursts(
+            clock,
+            barrier,
+            args,
+            || touched(Box::new(T::default())),
+            drop,
+        ),
+        BurstAllocator::SlabWarm => {
+            // 確保と解放の両方から触るのでRefCellに入れる
+            let slab = RefCell::new(Slab::<T>::with_capacity(args.burst));
+            bursts(
+                clock,
+                barrier,
+                args,
+                || {
+                    let mut slab = slab.borrow_mut();
+                    let key = slab.insert(T::default());
+                    touch(&mut slab[key]);
+                    key
+                },
+                |key| drop(slab.borrow_mut().remove(key)),
+            )
+        }
+        BurstAllocator::BufpoolWarm => {
+            let pool = bufpool::BufferPool::new(DataAllocator::<T>::new());
+            bursts(
+                clock,
+                barrier,
+                args,
+                || {
+                    let mut lease = pool.lease().unwrap();
+                    touch(&mut *lease);
+                    lease
+                },
+                drop,
+            )
+        }
+        BurstAllocator::SlabMutex => bursts(
+            clock,
+            barrier,
+            args,
+            || SharedSlab::insert_touched(slab_mutex, T::default()),
+            |key| drop(slab_mutex.remove(key)),
+        ),
+        BurstAllocator::ShardedSlab => bursts(
+            clock,
+            barrier,
+            args,
+            || SharedSlab::insert_touched(sharded, T::default()),
+            |key| drop(SharedSlab::remove(sharded, key)),
+        ),
+    }
+}
+
+// スレッドごとのバースト所要時間を返す（[スレッド][バースト]）
+fn run_size<const N: usize>(
+    allocator: BurstAllocator,
+    clock: &Clock,
+    threads: usize,
+    core_ids: &[core_affinity::CoreId],
+    args: &BurstArgs,
+) -> Vec<Vec<u64>> {
+    let barrier = Barrier::new(threads);
+    let slab_mutex = Mutex::new(Slab::<Data<N>>::new());
+    let sharded = sharded_slab::Slab::<Data<N>>::new();
+    std::thread::scope(|s| {
+        let handles: Vec<_> = (0..threads)
+            .map(|t| {
+                let (barrier, slab_mutex, sharded) = (&barrier, &slab_mutex, &sharded);
+                let core = core_for(core_ids, t);
+                s.spawn(move || {
+                    pin_thread(t, core, None);
+                    burst_thread(allocator, clock, barrier, args, slab_mutex, sharded)
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|h| h.join().expect("benchmark thread panicked"))
+            .collect()
+    })
+}
+
+pub fn run(args: &BurstArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let sizes = resolve_sizes(&args.sizes)?;
+    if args.burst == 0 || args.bursts == 0 {
+        return Err("--burst and --bursts must be greater than 0".into());
+    }
+    let clock = Clock::new();
+    let core_ids = core_affinity::get_core_ids().unwrap_or_default();
+    let thread_counts = expand_thread_counts(&args.threads);
+    let max_threads = thread_counts.iter().copied().max().unwrap_or(1);
+    if core_ids.len() < max_threads {
+        warn!(
+            "Only {} cores available for up to {} threads; threads will share cores",
+            core_ids.len(),
+            max_threads
+        );
+    }
+
+    info!(
+        "All threads allocate {} objects at once after a barrier ({} bursts)",
+        args.burst, args.bursts
+    );
+    println!(
+        "{:>6} {:>7} {:<14} {:>16} {:>16} {:>8}",
+        "size", "threads", "allocator", "worst median(ns)", "worst p99(ns)", "skew"
+    );
+
+    for &size in &sizes {
+        for &threads in &thread_counts {
+            for &allocator in BurstAllocator::all() {
+                let per_thread =
+                    dispatch_size!(size, run_size(allocator, &clock, threads, &core_ids, args));
+                // バーストごとに最も遅いスレッドと最も速いスレッドを取る
+                let (mut worst, mut best): (Vec<u64>, Vec<u64>) = (0..args.bursts)
+                    .map(|i| {
+                        let times = per_thread.iter().map(|t| t[i]);
+                        (times.clone().max().unwrap_or(0), times.min().unwrap_or(0))
+                    })
+                    .unzip();
+                worst.sort_unstable();
+                best.sort_unstable();
+                let worst_median = quantile(&worst, 0.5);
+                println!(
+                    "{:>6} {:>7} {:<14} {:>16.0} {:>16.0} {:>7.2}x",
+                    size,
+                    threads,
+                    allocator.as_str(),
+                    worst_median,
+                    quantile(&worst, 0.99),
+                    worst_median / quantile(&best, 0.5)
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,111 @@
+use crate::topology;
+use std::collections::BTreeMap;
+use std::process::Command;
+
+// 結果ファイルのメタデータのキーの接頭辞
+const KEY_PREFIX: &str = "memalloc_bench.";
+
+// 計測環境の情報（結果ファイルのメタデータとして記録する）
+#[derive(Debug)]
+pub struct Environment {
+    pub hostname: String,
+    pub os: String,
+    pub arch: String,
+    pub kernel: String,
+    pub cpu_model: String,
+    pub cores: usize,
+    pub physical_cores: usize,
+    pub threads_per_core: usize,
+    pub performance_cores: usize, // ハイブリッドCPUでなければ0
+    pub efficiency_cores: usize,
+    pub rustc: String,
+    pub crate_version: String,
+    pub profile: String,
+}
+
+impl Environment {
+    pub fn collect() -> Self {
+        let physical = topology::physical_cores();
+        let core_types = topology::core_types();
+        let count = |ty| core_types.values().filter(|&&t| t == ty).count();
+        Environment {
+            hostname: hostname::get()
+                .map(|h| h.to_string_lossy().into_owned())
+                .unwrap_or_else(|_| "unknown".to_string()),
+            os: std::env::consts::OS.to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+            kernel: kernel_version().unwrap_or_else(|| "unknown".to_string()),
+            cpu_model: cpu_model().unwrap_or_else(|| "unknown".to_string()),
+            cores: std::thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1),
+            // トポロジが取れない環境では0
+            physical_cores: physical.len(),
+            threads_per_core: topology::threads_per_core(&physical),
+            performance_cores: count(topology::CoreType::Performance),
+            efficiency_cores: count(topology::CoreType::Efficiency),
+            rustc: env!("MEMALLOC_BENCH_RUSTC_VERSION").to_string(),
+            crate_version: env!("CARGO_PKG_VERSION").to_string(),
+            profile: if cfg!(debug_assertions) {
+                "debug"
+            } else {
+                "release"
+            }
+            .to_string(),
+        }
+    }
+
+    pub fn to_metadata(&self) -> BTreeMap<String, String> {
+        [
+            ("hostname", self.hostname.clone()),
+            ("os", self.os.clone()),
+            ("arch", self.arch.clone()),
+            ("kernel", self.kernel.clone()),
+            ("cpu_model", self.cpu_model.clone()),
+            ("cores", self.cores.to_string()),
+            ("physical_cores", self.physical_cores.to_string()),
+            ("threads_per_core", self.threads_per_core.to_string()),
+            ("performance_cores", self.performance_cores.to_string()),
+            ("efficiency_cores", self.efficiency_cores.to_string()),
+            ("rustc", self.rustc.clone()),
+            ("crate_version", self.crate_version.clone()),
+            ("profile", self.profile.clone()),
+        ]
+        .into_iter()
+        .map(|(key, value)| (format!("{}{}", KEY_PREFIX, key), value))
+        .collect()
+    }
+}
+
+// コマンドを実行して標準出力の1行目を返す
+fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let line = stdout.lines().next()?.trim();
+    (!line.is_empty()).then(|| line.to_string())
+}
+
+fn kernel_version() -> Option<String> {
+    std::fs::read_to_string("/proc/sys/kernel/osrelease")
+        .ok()
+        .map(|s| s.trim().to_string())
+        .or_else(|| command_output("uname", &["-r"]))
+}
+
+fn cpu_model() -> Option<String> {
+    // Linux (x86_64): "model name", Linux (aarch64): 取れない場合がある
+    if let Ok(cpuinfo) = std::fs::read_to_string("/proc/cpuinfo") {
+        let model = cpuinfo.lines().find_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            matches!(key.trim(), "model name" | "Model" | "cpu model").then(|| value.trim())
+        });
+        if let Some(model) = model {
+            return Some(model.to_string());
+        }
+    }
+    // macOS
+    command_output("sysctl", &["-n", "machdep.cpu.brand_string"])
+}
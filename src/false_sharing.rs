@@ -0,0 +1,217 @@
+use log::{info, warn};
+use memalloc_results::stats::quantile;
+use quanta::Clock;
+use slab::Slab;
+use std::sync::Barrier;
+
+// 測定パラメータ
+const ITERATIONS: u32 = 20;
+const CACHE_LINE: usize = 64;
+
+#[derive(clap::Args, Debug)]
+pub struct FalseSharingArgs {
+    /// Comma-separated object sizes in bytes (8, 16, 32 or 64)
+    #[arg(long, value_delimiter = ',', num_args = 1.., default_value = "8,16,32,64")]
+    sizes: Vec<usize>,
+
+    /// Writes per thread per iteration
+    #[arg(long, default_value_t = 1_000_000)]
+    writes: u64,
+}
+
+// 書き込み対象のオブジェクト。先頭8バイトをカウンタとして使う
+#[repr(C, align(8))]
+struct Object<const N: usize>([u8; N]);
+
+impl<const N: usize> Object<N> {
+    fn new() -> Self {
+        Object([0; N])
+    }
+}
+
+// 隣接ラインのプリフェッチ (spatial prefetcher) も避けるため128バイト境界に置く
+#[repr(C, align(128))]
+struct Padded<T>(T);
+
+// 2つのオブジェクトの置き方
+#[derive(Clone, Copy, Debug)]
+enum Layout {
+    Box,    // Box::newを2回続けて確保
+    Slab,   // 同じslabの隣接エントリ
+    Padded, // それぞれ別のキャッシュラインに置いた基準
+}
+
+impl Layout {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Layout::Box => "box",
+            Layout::Slab => "slab",
+            Layout::Padded => "padded",
+        }
+    }
+
+    fn all() -> &'static [Layout] {
+        &[Layout::Box, Layout::Slab, Layout::Padded]
+    }
+}
+
+// 2スレッドがそれぞれ自分のオブジェクトに書き込み続け、遅い方の所要時間をイテレーションごとに返す
+// ptrsは呼び出し側が測定中ずっと生かしておく
+fn measure(
+    clock: &Clock,
+    ptrs: [*mut u64; 2],
+    core_ids: &[core_affinity::CoreId],
+    writes: u64,
+) -> Vec<u64> {
+    let barrier = Barrier::new(ptrs.len());
+    // 生ポインタはSendでないのでアドレスとして渡す
+    let addrs = ptrs.map(|p| p as usize);
+    let per_thread: Vec<Vec<u64>> = std::thread::scope(|s| {
+        let handles: Vec<_> = addrs
+            .iter()
+            .enumerate()
+            .map(|(t, &addr)| {
+                let barrier = &barrier;
+                let clock = clock.clone();
+                let core = (!core_ids.is_empty()).then(|| core_ids[t % core_ids.len()]);
+                s.spawn(move || {
+                    if let Some(core) = core {
+                        if !core_affinity::set_for_current(core) {
+                            warn!("Failed to pin thread {} to core {}", t, core.id);
+                        }
+                    }
+                    let counter = addr as *mut u64;
+                    (0..ITERATIONS)
+                        .map(|_| {
+                            barrier.wait();
+                            let start = clock.raw();
+                            for i in 0..writes {
+                                // SAFETY: 各スレッドは自分のオブジェクトにだけ書き込む
+                                unsafe {
+                                    counter.write_volatile(counter.read_volatile().wrapping_add(i))
+                                };
+                            }
+                            let end = clock.raw();
+                            clock.delta(start, end).as_nanos() as u64
+                        })
+                        .collect()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|h| h.join().expect("false-sharing thread panicked"))
+            .collect()
+    });
+
+    let mut samples: Vec<u64> = (0..ITERATIONS as usize)
+        .map(|i| per_thread.iter().map(|t| t[i]).max().unwrap_or(0))
+        .collect();
+    samples.sort_unstable();
+    samples
+}
+
+// 指定したレイアウトで2つのオブジェクトを確保して測定する
+// 戻り値は (2つのオブジェクトのアドレス, ソート済みの所要時間)
+fn measure_layout<const N: usize>(
+    clock: &Clock,
+    layout: Layout,
+    core_ids: &[core_affinity::CoreId],
+    writes: u64,
+) -> ([usize; 2], Vec<u64>) {
+    match layout {
+        Layout::Box => {
+            let a = Box::into_raw(Box::new(Object::<N>::new()));
+            let b = Box::into_raw(Box::new(Object::<N>::new()));
+            let ptrs = [a.cast(), b.cast()];
+            let samples = measure(clock, ptrs, core_ids, writes);
+            // SAFETY: 上でinto_rawしたものを1度だけ戻す
+            unsafe {
+                drop(Box::from_raw(a));
+                drop(Box::from_raw(b));
+            }
+            (addresses(ptrs), samples)
+        }
+        Layout::Slab => {
+            let mut slab = Slab::with_capacity(2);
+            slab.insert(Object::<N>::new());
+            slab.insert(Object::<N>::new());
+            let mut entries = slab.iter_mut().map(|(_, o)| (o as *mut Object<N>).cast());
+            let ptrs = [entries.next().unwrap(), entries.next().unwrap()];
+            (addresses(ptrs), measure(clock, ptrs, core_ids, writes))
+        }
+        Layout::Padded => {
+            let mut objects = [Padded(Object::<N>::new()), Padded(Object::<N>::new())];
+            let [a, b] = &mut objects;
+            let ptrs = [
+                (&mut a.0 as *mut Object<N>).cast(),
+                (&mut b.0 as *mut Object<N>).cast(),
+            ];
+            (addresses(ptrs), measure(clock, ptrs, core_ids, writes))
+        }
+    }
+}
+
+fn addresses(ptrs: [*mut u64; 2]) -> [usize; 2] {
+    ptrs.map(|p| p as usize)
+}
+
+pub fn run(args: &FalseSharingArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let clock = Clock::new();
+    let core_ids = core_affinity::get_core_ids().unwrap_or_default();
+    if core_ids.len() < 2 {
+        warn!("Fewer than 2 cores available; threads may share a core and hide false sharing");
+    }
+
+    info!(
+        "Two threads write {} times per iteration to adjacent objects ({} iterations)",
+        args.writes, ITERATIONS
+    );
+    println!(
+        "{:>6} {:<8} {:>10} {:>10} {:>12} {:>9}",
+        "size", "layout", "distance", "same line", "ns/write", "slowdown"
+    );
+
+    for &size in &args.sizes {
+        let mut rows = Vec::new();
+        for &layout in Layout::all() {
+            let (addrs, samples) = match size {
+                8 => measure_layout::<8>(&clock, layout, &core_ids, args.writes),
+                16 => measure_layout::<16>(&clock, layout, &core_ids, args.writes),
+                32 => measure_layout::<32>(&clock, layout, &core_ids, args.writes),
+                64 => measure_layout::<64>(&clock, layout, &core_ids, args.writes),
+                _ => {
+                    return Err(
+                        format!("unsupported size {} (expected 8, 16, 32 or 64)", size).into(),
+                    )
+                }
+            };
+            let ns_per_write = quantile(&samples, 0.5) / args.writes as f64;
+            rows.push((layout, addrs, ns_per_write));
+        }
+
+        // 別々のキャッシュラインに置いた場合を基準にした遅さ
+        let padded = rows
+            .iter()
+            .find(|(layout, _, _)| matches!(layout, Layout::Padded))
+            .map(|&(_, _, ns)| ns)
+            .unwrap_or(f64::NAN);
+        for (layout, [a, b], ns_per_write) in rows {
+            println!(
+                "{:>6} {:<8} {:>10} {:>10} {:>12.3} {:>8.2}x",
+                size,
+                layout.as_str(),
+                a.abs_diff(b),
+                if a / CACHE_LINE == b / CACHE_LINE {
+                    "yes"
+                } else {
+                    "no"
+                },
+                ns_per_write,
+                ns_per_write / padded
+            );
+        }
+    }
+
+    Ok(())
+}
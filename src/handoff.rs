@@ -0,0 +1,175 @@
+use crate::shared::SharedSlab;
+use crate::touch::touched;
+use crate::{core_for, dispatch_size, pin_thread, resolve_sizes, Data};
+use log::{info, warn};
+use memalloc_results::stats::quantile;
+use quanta::Clock;
+use slab::Slab;
+use std::hint::black_box;
+use std::sync::{Arc, Barrier, Mutex};
+
+#[derive(clap::Args, Debug)]
+pub struct HandoffArgs {
+    /// Comma-separated data sizes in bytes (the sizes the main benchmark supports)
+    #[arg(long, value_delimiter = ',', num_args = 1.., default_value = "64,1024,4096")]
+    sizes: Vec<usize>,
+
+    /// Capacity of the bounded channel between the producer and the consumer
+    #[arg(long, default_value_t = 128)]
+    capacity: usize,
+
+    /// Objects handed off per allocator and size
+    #[arg(long, default_value_t = 1_000_000)]
+    messages: usize,
+}
+
+// チャネルで渡すオブジェクトの持ち方
+#[derive(Clone, Copy, Debug)]
+enum Handoff {
+    Box,         // Box<DataN>をそのまま渡す
+    SlabMutex,   // Arc<Mutex<Slab>>のキーを渡し、受信側が同じスラブから削除する
+    ShardedSlab, // Arc<sharded_slab::Slab>のキーを渡す
+}
+
+impl Handoff {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Handoff::Box => "box",
+            Handoff::SlabMutex => "slab_mutex",
+            Handoff::ShardedSlab => "sharded_slab",
+        }
+    }
+
+    fn all() -> &'static [Handoff] {
+        &[Handoff::Box, Handoff::SlabMutex, Handoff::ShardedSlab]
+    }
+}
+
+// 送信側スレッドが確保してチャネルに送り、受信側スレッドが受け取って解放する
+// 1オブジェクトごとに確保の直前から解放の直後までを計り、(ソート済みのレイテンシ, 全体の所要時間 (ns)) を返す
+// 送受信で別のコアのclock.raw()を比べるので、コア間で同期したTSCを前提とする
+fn transfer<P: Send>(
+    clock: &Clock,
+    core_ids: &[core_affinity::CoreId],
+    args: &HandoffArgs,
+    mut alloc: impl FnMut() -> P + Send,
+    mut free: impl FnMut(P) + Send,
+) -> (Vec<u64>, u64) {
+    let (tx, rx) = crossbeam_channel::bounded::<(P, u64)>(args.capacity);
+    let barrier = Barrier::new(2);
+    std::thread::scope(|s| {
+        let producer = s.spawn(|| {
+            pin_thread(0, core_for(core_ids, 0), None);
+            barrier.wait();
+            let start = clock.raw();
+            for _ in 0..args.messages {
+                let sent = clock.raw();
+                tx.send((alloc(), sent)).unwrap();
+            }
+            // 送信側を閉じて受信側のループを終わらせる
+            drop(tx);
+            start
+        });
+        let consumer = s.spawn(|| {
+            pin_thread(1, core_for(core_ids, 1), None);
+            let mut latencies = Vec::with_capacity(args.messages);
+            barrier.wait();
+            for (obj, sent) in rx {
+                free(black_box(obj));
+                let freed = clock.raw();
+                latencies.push(clock.delta(sent, freed).as_nanos() as u64);
+            }
+            (latencies, clock.raw())
+        });
+
+        let start = producer.join().expect("producer thread panicked");
+        let (mut latencies, end) = consumer.join().expect("consumer thread panicked");
+        latencies.sort_unstable();
+        (latencies, clock.delta(start, end).as_nanos() as u64)
+    })
+}
+
+fn run_size<const N: usize>(
+    handoff: Handoff,
+    clock: &Clock,
+    core_ids: &[core_affinity::CoreId],
+    args: &HandoffArgs,
+) -> (Vec<u64>, u64) {
+    match handoff {
+        Handoff::Box => transfer(
+            clock,
+            core_ids,
+            args,
+            || touched(Box::<Data<N>>::default()),
+            drop,
+        ),
+        Handoff::SlabMutex => {
+            let slab = Arc::new(Mutex::new(Slab::<Data<N>>::with_capacity(
+                args.capacity + 1,
+            )));
+            let (producer, consumer) = (slab.clone(), slab);
+            transfer(
+                clock,
+                core_ids,
+                args,
+                move || SharedSlab::insert_touched(&*producer, Data::default()),
+                move |key| {
+                    let _ = consumer.lock().unwrap().remove(key);
+                },
+            )
+        }
+        Handoff::ShardedSlab => {
+            let slab = Arc::new(sharded_slab::Slab::<Data<N>>::new());
+            let (producer, consumer) = (slab.clone(), slab);
+            transfer(
+                clock,
+                core_ids,
+                args,
+                move || SharedSlab::insert_touched(&*producer, Data::default()),
+                move |key| {
+                    let _ = consumer
+                        .take(key)
+                        .expect("sharded slab entry was already removed");
+                },
+            )
+        }
+    }
+}
+
+pub fn run(args: &HandoffArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let sizes = resolve_sizes(&args.sizes)?;
+    if args.capacity == 0 || args.messages == 0 {
+        return Err("--capacity and --messages must be greater than 0".into());
+    }
+    let clock = Clock::new();
+    let core_ids = core_affinity::get_core_ids().unwrap_or_default();
+    if core_ids.len() < 2 {
+        warn!("Fewer than 2 cores available; producer and consumer may share a core");
+    }
+
+    info!(
+        "Hand off {} objects through a bounded crossbeam channel (capacity {})",
+        args.messages, args.capacity
+    );
+    println!(
+        "{:>6} {:<14} {:>14} {:>14} {:>12}",
+        "size", "allocator", "median(ns)", "p99(ns)", "Mobjs/s"
+    );
+
+    for &size in &sizes {
+        for &handoff in Handoff::all() {
+            let (latencies, wall_ns) =
+                dispatch_size!(size, run_size(handoff, &clock, &core_ids, args));
+            println!(
+                "{:>6} {:<14} {:>14.1} {:>14.1} {:>12.2}",
+                size,
+                handoff.as_str(),
+                quantile(&latencies, 0.5),
+                quantile(&latencies, 0.99),
+                args.messages as f64 / wall_ns as f64 * 1e3
+            );
+        }
+    }
+
+    Ok(())
+}
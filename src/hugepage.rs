@@ -0,0 +1,265 @@
+use crate::bufpool::{BufferPool, HugePageAllocator, PageMode};
+use log::{info, warn};
+use memalloc_results::stats::quantile;
+use quanta::Clock;
+use std::hint::black_box;
+
+// 測定パラメータ
+const ITERATIONS: u32 = 10;
+
+#[derive(clap::Args, Debug)]
+pub struct HugePageArgs {
+    /// Comma-separated buffer sizes in bytes
+    #[arg(long, value_delimiter = ',', num_args = 1.., default_value = "65536,2097152")]
+    sizes: Vec<usize>,
+
+    /// Buffers leased from the pool at once; the working set is sizes x buffers
+    #[arg(long, default_value_t = 64)]
+    buffers: usize,
+
+    /// Random one-byte reads per iteration
+    #[arg(long, default_value_t = 1_000_000)]
+    accesses: u64,
+
+    /// Seed for the access pattern
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+}
+
+fn mode_str(mode: PageMode) -> &'static str {
+    match mode {
+        PageMode::Base => "4k",
+        PageMode::Transparent => "thp",
+        PageMode::HugeTlb => "hugetlb",
+    }
+}
+
+// データTLBのロードミス数を数えるperfカウンタ（perf_event_open）
+// libcにperf_event_attrがないので、PERF_ATTR_SIZE_VER0 (64バイト) までのフィールドだけ定義する
+#[cfg(all(target_os = "linux", feature = "perf"))]
+struct TlbCounter {
+    fd: libc::c_int,
+}
+
+#[cfg(all(target_os = "linux", feature = "perf"))]
+#[repr(C)]
+#[derive(Default)]
+struct PerfEventAttr {
+    type_: u32,
+    size: u32,
+    config: u64,
+    sample_period: u64,
+    sample_type: u64,
+    read_format: u64,
+    flags: u64,
+    wakeup_events: u32,
+    bp_type: u32,
+    config1: u64,
+}
+
+#[cfg(all(target_os = "linux", feature = "perf"))]
+impl TlbCounter {
+    const PERF_TYPE_HW_CACHE: u32 = 3;
+    // PERF_COUNT_HW_CACHE_DTLB | OP_READ << 8 | RESULT_MISS << 16
+    const DTLB_READ_MISS: u64 = 3 | (1 << 16);
+    // disabled | exclude_kernel | exclude_hv
+    const FLAGS: u64 = 1 | (1 << 5) | (1 << 6);
+    const IOC_ENABLE: libc::c_ulong = 0x2400;
+    const IOC_DISABLE: libc::c_ulong = 0x2401;
+    const IOC_RESET: libc::c_ulong = 0x2403;
+
+    fn open() -> std::io::Result<Self> {
+        let attr = PerfEventAttr {
+            type_: Self::PERF_TYPE_HW_CACHE,
+            size: std::mem::size_of::<PerfEventAttr>() as u32,
+            config: Self::DTLB_READ_MISS,
+            flags: Self::FLAGS,
+            ..Default::default()
+        };
+        // 呼び出したスレッドを、どのCPUで動いていても数える
+        // SAFETY: attrはPERF_ATTR_SIZE_VER0の大きさの有効な構造体
+        let fd = unsafe {
+            libc::syscall(
+                libc::SYS_perf_event_open,
+                &attr as *const PerfEventAttr,
+                0,
+                -1,
+                -1,
+                0,
+            )
+        };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(TlbCounter {
+            fd: fd as libc::c_int,
+        })
+    }
+
+    fn start(&self) {
+        // SAFETY: fdはperf_event_openで開いたもの
+        unsafe {
+            libc::ioctl(self.fd, Self::IOC_RESET as _, 0);
+            libc::ioctl(self.fd, Self::IOC_ENABLE as _, 0);
+        }
+    }
+
+    fn stop(&self) -> u64 {
+        let mut count = 0u64;
+        // SAFETY: fdはperf_event_openで開いたもの、読み出しは8バイト
+        unsafe {
+            libc::ioctl(self.fd, Self::IOC_DISABLE as _, 0);
+            libc::read(self.fd, (&mut count as *mut u64).cast(), 8);
+        }
+        count
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "perf"))]
+impl Drop for TlbCounter {
+    fn drop(&mut self) {
+        // SAFETY: fdはperf_event_openで開いたもの
+        unsafe { libc::close(self.fd) };
+    }
+}
+
+#[cfg(not(all(target_os = "linux", feature = "perf")))]
+struct TlbCounter;
+
+#[cfg(not(all(target_os = "linux", feature = "perf")))]
+impl TlbCounter {
+    fn open() -> std::io::Result<Self> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "perf counters need Linux and the perf feature",
+        ))
+    }
+
+    fn start(&self) {}
+
+    fn stop(&self) -> u64 {
+        0
+    }
+}
+
+// 1セルの結果
+struct HugePageResult {
+    iterations_ns: Vec<u64>,        // イテレーションごとの所要時間（ソート済み）
+    misses_per_access: Option<f64>, // 全イテレーションの平均（perfカウンタが使えなければNone）
+}
+
+// プールから全バッファを借り、ランダムなバッファのランダムな位置を1バイトずつ読む
+fn measure(
+    clock: &Clock,
+    mode: PageMode,
+    size: usize,
+    args: &HugePageArgs,
+    counter: Option<&TlbCounter>,
+) -> std::io::Result<HugePageResult> {
+    let pool = BufferPool::new(HugePageAllocator::new(size, mode));
+    pool.reserve(args.buffers)?;
+    let leases = (0..args.buffers)
+        .map(|_| pool.lease())
+        .collect::<Result<Vec<_>, _>>()?;
+    let bases: Vec<*const u8> = leases.iter().map(|l| l.as_ptr()).collect();
+
+    // xorshiftで乱数を作る（StdRngだと乱数生成の方が重くなる）
+    let mut state = args.seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1;
+    let mut next = move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    };
+
+    let mut samples = Vec::with_capacity(ITERATIONS as usize);
+    let mut misses = 0;
+    for i in 0..=ITERATIONS {
+        if let Some(counter) = counter {
+            counter.start();
+        }
+        let start = clock.raw();
+        let mut sum = 0u8;
+        for _ in 0..args.accesses {
+            let r = next();
+            let base = bases[(r >> 32) as usize % bases.len()];
+            let offset = (r as u32 as usize) % size;
+            // SAFETY: offsetはバッファの長さ未満で、リースは測定中ずっと生きている
+            sum = sum.wrapping_add(unsafe { base.add(offset).read_volatile() });
+        }
+        black_box(sum);
+        let end = clock.raw();
+        let count = counter.map_or(0, |c| c.stop());
+        // 最初の1回はウォームアップとして捨てる
+        if i > 0 {
+            samples.push(clock.delta(start, end).as_nanos() as u64);
+            misses += count;
+        }
+    }
+    samples.sort_unstable();
+    Ok(HugePageResult {
+        iterations_ns: samples,
+        misses_per_access: counter
+            .map(|_| misses as f64 / (args.accesses * ITERATIONS as u64) as f64),
+    })
+}
+
+pub fn run(args: &HugePageArgs) -> Result<(), Box<dyn std::error::Error>> {
+    if args.sizes.contains(&0) || args.buffers == 0 || args.accesses == 0 {
+        return Err("--sizes, --buffers and --accesses must be greater than 0".into());
+    }
+    let clock = Clock::new();
+    let counter = match TlbCounter::open() {
+        Ok(counter) => Some(counter),
+        Err(e) => {
+            warn!(
+                "dTLB miss counter is not available ({}); reporting timings only",
+                e
+            );
+            None
+        }
+    };
+
+    info!(
+        "Read {} random bytes per iteration from {} pooled buffers ({} iterations)",
+        args.accesses, args.buffers, ITERATIONS
+    );
+    println!(
+        "{:>9} {:<8} {:>14} {:>14} {:>16} {:>10}",
+        "size", "pages", "median(ns)", "p99(ns)", "dTLB miss/access", "vs 4k"
+    );
+
+    for &size in &args.sizes {
+        let mut base_median = None;
+        for mode in [PageMode::Base, PageMode::Transparent, PageMode::HugeTlb] {
+            let result = match measure(&clock, mode, size, args, counter.as_ref()) {
+                Ok(result) => result,
+                Err(e) => {
+                    warn!(
+                        "Skipping {} pages for {} bytes: {}",
+                        mode_str(mode),
+                        size,
+                        e
+                    );
+                    continue;
+                }
+            };
+            let accesses = args.accesses as f64;
+            let median = quantile(&result.iterations_ns, 0.5) / accesses;
+            let base = *base_median.get_or_insert(median);
+            println!(
+                "{:>9} {:<8} {:>14.2} {:>14.2} {:>16} {:>9.2}x",
+                size,
+                mode_str(mode),
+                median,
+                quantile(&result.iterations_ns, 0.99) / accesses,
+                result
+                    .misses_per_access
+                    .map_or("n/a".to_string(), |m| format!("{:.4}", m)),
+                base / median
+            );
+        }
+    }
+
+    Ok(())
+}
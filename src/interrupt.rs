@@ -0,0 +1,26 @@
+// SIGINT / SIGTERMを受けたら計測を止め、それまでに計測した結果を書き出して終わるためのフラグ
+// クラスタのバッチジョブが途中で止められても、何時間分もの計測を失わないようにする
+// 1回目のシグナルでは計測中のセルを終えてから止まり、2回目ではすぐに終了する（結果は書き出さない）
+
+use log::warn;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// シグナルハンドラを登録する（ハンドラはctrlcが作る専用のスレッドで動く）
+pub fn install() -> Result<(), ctrlc::Error> {
+    ctrlc::set_handler(|| {
+        if REQUESTED.swap(true, Ordering::SeqCst) {
+            warn!("Interrupted again, exiting without writing results");
+            std::process::exit(130);
+        }
+        warn!(
+            "Interrupted, finishing the current cell and writing the results so far (send the signal again to exit immediately)"
+        );
+    })
+}
+
+/// シグナルを受けたか
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::SeqCst)
+}
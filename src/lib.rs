@@ -0,0 +1,1361 @@
+//! Box::new、slab、bufpoolの確保・解放を計測するベンチマークのエンジン
+//! アクセスパターンとアロケータごとの計測（run_benchmark / run_parallel）、結果の行（BenchResult）と書き出し（output）、
+//! バッファプール（bufpool）を公開し、他のプロジェクトのベンチマークやテストから使えるようにする
+//! コマンドラインはmain.rsがこの上に載せている（結果ファイルの比較やグラフはmemalloc-reportの側にある）
+
+use clap::ValueEnum;
+use log::{debug, info, warn};
+use quanta::Clock;
+use rand::{Rng, SeedableRng};
+use std::collections::{BTreeMap, BTreeSet, HashSet, VecDeque};
+use std::hint::black_box;
+use std::mem::MaybeUninit;
+use std::path::PathBuf;
+use std::sync::{mpsc, Barrier, Mutex};
+use std::time::Duration;
+
+pub mod backend;
+pub mod budget;
+pub mod bufpool;
+pub mod burst;
+pub mod environment;
+pub mod false_sharing;
+pub mod handoff;
+pub mod hugepage;
+pub mod interrupt;
+pub mod numa;
+pub mod pattern;
+pub mod pool_alloc;
+pub mod select;
+pub mod shared;
+pub mod stable;
+pub mod steal;
+#[cfg(feature = "tokio")]
+pub mod tokio_bench;
+pub mod topology;
+pub mod touch;
+pub mod treiber;
+#[cfg(feature = "upload")]
+pub mod upload;
+pub mod zeroize;
+
+// 結果の行と書き出しはmemalloc-reportと共有するmemalloc-resultsにある
+pub use memalloc_results::{output, stats, BenchResult};
+
+// 測定パラメータ（BATCH_SIZEとINNER_LOOPは結果の値の単位になるのでmemalloc-resultsで決めている）
+pub const ITERATIONS: u32 = 100;
+pub use memalloc_results::{BATCH_SIZE, INNER_LOOP, OPS_PER_ITERATION};
+
+// --dry-runでの所要時間見積もりに使う1操作 (alloc or dealloc) あたりの目安時間
+const ESTIMATED_NS_PER_OP: f64 = 20.0;
+
+// データサイズ (bytes)
+// 静的型を用意しているサイズの一覧。--sizesで指定できるのはこの中のサイズだけ
+// SIZESとdispatch_size!を同じ一覧から作るので、サイズを足すときはdefine_sizes!に1つ足すだけでよい
+macro_rules! define_sizes {
+    ($d:tt $($size:literal),* $(,)?) => {
+        pub const SIZES: &[usize] = &[$($size),*];
+
+        // サイズをconst引数にして総称関数を呼び出す (例: dispatch_size!(size, f(a, b)) → f::<64>(a, b))
+        macro_rules! dispatch_size {
+            ($d size_expr:expr, $d f:ident ( $d ($d arg:expr),* $d (,)? )) => {
+                match $d size_expr {
+                    $($size => $d f::<$size>($d ($d arg),*),)*
+                    size => panic!("Unsupported size: {}", size),
+                }
+            };
+        }
+        pub(crate) use dispatch_size;
+    };
+}
+
+define_sizes!($
+    8, 12, 16, 24, 32, 48, 64, 96, 128, 192, 256, 384, 512, 768, 1024, 1536, 2048, 3072, 4096,
+);
+
+// アクセスパターン
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pattern {
+    Immediate, // alloc→deallocをBATCH_SIZE回繰り返す
+    Lifo,      // BATCH_SIZE個alloc → 逆順dealloc
+    Fifo,      // BATCH_SIZE個alloc → 順番dealloc
+    Random,    // ランダムにalloc/deallocを混ぜる
+    Remote,    // 確保したものを別スレッドへ渡して解放する
+    Hot,       // 計測の外で温めたインスタンスから取り出し、触って返すのをBATCH_SIZE回繰り返す
+}
+
+impl Pattern {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Pattern::Immediate => "immediate",
+            Pattern::Lifo => "lifo",
+            Pattern::Fifo => "fifo",
+            Pattern::Random => "random",
+            Pattern::Remote => "remote",
+            Pattern::Hot => "hot",
+        }
+    }
+
+    pub fn all() -> &'static [Pattern] {
+        &[
+            Pattern::Immediate,
+            Pattern::Lifo,
+            Pattern::Fifo,
+            Pattern::Random,
+            Pattern::Remote,
+            Pattern::Hot,
+        ]
+    }
+}
+
+// アロケータ種別
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Allocator {
+    Box,
+    SlabCold,
+    SlabWarm,
+    BufpoolCold,
+    BufpoolWarm,
+    BufpoolFixed,    // ヒープを使わない固定容量のbufpool::FixedBufferPool
+    VecReuse,        // Vec<Vec<u8>>に空きバッファを積んで使い回す（hotパターンの基準）
+    SlabMutex,       // 全スレッドで共有するMutex<Slab>
+    ShardedSlab,     // 全スレッドで共有するsharded_slab::Slab
+    TreiberPool,     // 全スレッドで共有するロックフリーのTreiberスタックのプール
+    BufpoolMutex,    // 全スレッドで共有するbufpool::MutexBufferPool
+    BufpoolLockfree, // 全スレッドで共有するbufpool::LockFreeBufferPool
+    BufpoolDeferred, // 全スレッドで共有し、借りたスレッドのシャードへ返すbufpool::DeferredBufferPool
+}
+
+impl Allocator {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Allocator::Box => "box",
+            Allocator::SlabCold => "slab_cold",
+            Allocator::SlabWarm => "slab_warm",
+            Allocator::BufpoolCold => "bufpool_cold",
+            Allocator::BufpoolWarm => "bufpool_warm",
+            Allocator::BufpoolFixed => "bufpool_fixed",
+            Allocator::VecReuse => "vec_reuse",
+            Allocator::SlabMutex => "slab_mutex",
+            Allocator::ShardedSlab => "sharded_slab",
+            Allocator::TreiberPool => "treiber_pool",
+            Allocator::BufpoolMutex => "bufpool_mutex",
+            Allocator::BufpoolLockfree => "bufpool_lockfree",
+            Allocator::BufpoolDeferred => "bufpool_deferred",
+        }
+    }
+
+    // スレッド間で1つのインスタンスを共有するか（shared::run_sharedで実行する）
+    pub fn is_shared(&self) -> bool {
+        matches!(
+            self,
+            Allocator::SlabMutex
+                | Allocator::ShardedSlab
+                | Allocator::TreiberPool
+                | Allocator::BufpoolMutex
+                | Allocator::BufpoolLockfree
+                | Allocator::BufpoolDeferred
+        )
+    }
+
+    // このアロケータでパターンを計測するか
+    // hotは使い回す仕組みを持つスレッドごとのアロケータとBoxだけ、vec_reuseはhotだけで計測する
+    pub fn supports(&self, pattern: Pattern) -> bool {
+        match (self, pattern) {
+            (Allocator::VecReuse, pattern) => pattern == Pattern::Hot,
+            (allocator, Pattern::Hot) => matches!(
+                allocator,
+                Allocator::Box
+                    | Allocator::SlabWarm
+                    | Allocator::BufpoolWarm
+                    | Allocator::BufpoolFixed
+            ),
+            _ => true,
+        }
+    }
+
+    pub fn all() -> &'static [Allocator] {
+        &[
+            Allocator::Box,
+            Allocator::SlabCold,
+            Allocator::SlabWarm,
+            Allocator::BufpoolCold,
+            Allocator::BufpoolWarm,
+            Allocator::BufpoolFixed,
+            Allocator::VecReuse,
+            Allocator::SlabMutex,
+            Allocator::ShardedSlab,
+            Allocator::TreiberPool,
+            Allocator::BufpoolMutex,
+            Allocator::BufpoolLockfree,
+            Allocator::BufpoolDeferred,
+        ]
+    }
+}
+
+// 計測する1セル
+#[derive(Clone, Copy, Debug)]
+pub struct Cell {
+    pub allocator: Allocator,
+    pub pattern: Pattern,
+    pub size: usize,
+    pub threads: usize,
+    pub numa: numa::Placement,
+    pub smt: topology::Smt,
+}
+
+impl Cell {
+    // 1スレッドかつNUMA配置の指定がなく、1つのコアで計測が完結するか（--jobsで並行に実行できる）
+    pub fn is_single_core(&self) -> bool {
+        self.threads == 1 && self.numa == numa::Placement::None
+    }
+
+    // 結果のcell_id列と同じID（--only id=...で指定できる）
+    pub fn id(&self) -> String {
+        memalloc_results::cell_id(
+            self.allocator.as_str(),
+            self.pattern.as_str(),
+            self.size as u32,
+            self.threads as u32,
+            self.numa.as_str(),
+            self.smt.as_str(),
+        )
+    }
+}
+
+impl std::fmt::Display for Cell {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} / {} / {} bytes / {} threads",
+            self.allocator.as_str(),
+            self.pattern.as_str(),
+            self.size,
+            self.threads
+        )?;
+        if self.numa != numa::Placement::None {
+            write!(f, " / numa {}", self.numa.as_str())?;
+        }
+        if self.smt != topology::Smt::Any {
+            write!(f, " / smt {}", self.smt.as_str())?;
+        }
+        Ok(())
+    }
+}
+
+// 静的サイズのデータ構造（サイズNごとにconstジェネリクスで単相化される）
+// MaybeUninitを使ってゼロクリアのコストを排除
+#[repr(align(8))]
+struct Data<const N: usize> {
+    _data: [MaybeUninit<u8>; N],
+}
+
+impl<const N: usize> Data<N> {
+    #[inline(always)]
+    fn new() -> Self {
+        Self {
+            _data: [const { MaybeUninit::uninit() }; N],
+        }
+    }
+}
+
+impl<const N: usize> Default for Data<N> {
+    #[inline(always)]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// ベンチマーク結果 (total_ns, latency_ns)
+pub struct BenchTiming {
+    pub total_ns: u64,
+    pub latency_ns: u64,
+}
+
+// bufpool用のアロケータ
+struct DataAllocator<T> {
+    _phantom: std::marker::PhantomData<T>,
+}
+
+impl<T> DataAllocator<T> {
+    fn new() -> Self {
+        Self {
+            _phantom: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<T: Default> bufpool::BufferAllocator for DataAllocator<T> {
+    type Buffer = T;
+    type Error = std::convert::Infallible;
+
+    fn allocate(&self) -> Result<Self::Buffer, Self::Error> {
+        Ok(T::default())
+    }
+}
+
+// データサイズNで1セルを実行する（アロケータごとのバックエンドはbackendにある）
+fn run_cell<const N: usize>(
+    clock: &Clock,
+    allocator: Allocator,
+    pattern: Pattern,
+    rng: &mut rand::rngs::StdRng,
+) -> BenchTiming {
+    use backend::{
+        bench, BoxBackend, BufpoolBackend, FixedBufpoolBackend, SlabBackend, VecReuseBackend,
+    };
+    match allocator {
+        Allocator::Box => bench(clock, &BoxBackend::<Data<N>>::new(), pattern, rng),
+        Allocator::SlabCold => bench(clock, &SlabBackend::<Data<N>, false>::new(), pattern, rng),
+        Allocator::SlabWarm => bench(clock, &SlabBackend::<Data<N>, true>::new(), pattern, rng),
+        Allocator::BufpoolCold => bench(
+            clock,
+            &BufpoolBackend::<Data<N>, false>::new(),
+            pattern,
+            rng,
+        ),
+        Allocator::BufpoolWarm => {
+            bench(clock, &BufpoolBackend::<Data<N>, true>::new(), pattern, rng)
+        }
+        Allocator::BufpoolFixed => {
+            bench(clock, &FixedBufpoolBackend::<Data<N>>::new(), pattern, rng)
+        }
+        Allocator::VecReuse => bench(clock, &VecReuseBackend::<Data<N>>::new(), pattern, rng),
+        Allocator::SlabMutex
+        | Allocator::ShardedSlab
+        | Allocator::TreiberPool
+        | Allocator::BufpoolMutex
+        | Allocator::BufpoolLockfree
+        | Allocator::BufpoolDeferred => {
+            unreachable!("shared allocators are run by shared::run_shared")
+        }
+    }
+}
+
+pub fn run_benchmark(
+    clock: &Clock,
+    allocator: Allocator,
+    pattern: Pattern,
+    size: usize,
+    rng: &mut rand::rngs::StdRng,
+) -> BenchTiming {
+    dispatch_size!(size, run_cell(clock, allocator, pattern, rng))
+}
+
+// threads個のスレッドで同じセルを独立に実行する
+// 各スレッドは別々のコアに固定し、イテレーションごとにバリアで開始を揃える
+// slab/bufpoolはスレッドごとに作られ、Boxはグローバルアロケータを共有する
+// mem_nodeを指定した場合、各スレッドが確保するメモリをそのNUMAノードに割り当てる
+pub fn run_parallel(
+    clock: &Clock,
+    cell: Cell,
+    core_ids: &[core_affinity::CoreId],
+    mem_node: Option<usize>,
+    seed: u64,
+    iterations: u32,
+) -> Vec<Vec<BenchTiming>> {
+    let barrier = Barrier::new(cell.threads);
+    std::thread::scope(|s| {
+        let handles: Vec<_> = (0..cell.threads)
+            .map(|t| {
+                let barrier = &barrier;
+                let clock = clock.clone();
+                let core = core_for(core_ids, t);
+                s.spawn(move || {
+                    pin_thread(t, core, mem_node);
+                    let mut rng = rand::rngs::StdRng::seed_from_u64(seed.wrapping_add(t as u64));
+                    (0..iterations)
+                        .map(|_| {
+                            barrier.wait();
+                            run_benchmark(&clock, cell.allocator, cell.pattern, cell.size, &mut rng)
+                        })
+                        .collect()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|h| h.join().expect("benchmark thread panicked"))
+            .collect()
+    })
+}
+
+// t番目のスレッドを固定するコア（固定先がなければNone）
+fn core_for(core_ids: &[core_affinity::CoreId], t: usize) -> Option<core_affinity::CoreId> {
+    (!core_ids.is_empty()).then(|| core_ids[t % core_ids.len()])
+}
+
+// 1セル分の計測結果を出力する行に変換する
+// core_idsはスレッドを固定したコア（メインスレッドを固定せずに計測した場合は空）
+pub fn to_results(
+    platform: &str,
+    cell: Cell,
+    timings: Vec<Vec<BenchTiming>>,
+    core_ids: &[core_affinity::CoreId],
+    core_types: &BTreeMap<usize, topology::CoreType>,
+) -> Vec<BenchResult> {
+    let mut results = Vec::with_capacity(timings.iter().map(Vec::len).sum());
+    for (thread, thread_timings) in timings.into_iter().enumerate() {
+        let core = core_for(core_ids, thread);
+        let core_type = core.and_then(|c| core_types.get(&c.id)).map(|t| t.as_str());
+        let core = core.map(|c| c.id as u32);
+        for (iteration, timing) in thread_timings.into_iter().enumerate() {
+            debug!(
+                "  thread {} iteration {}: total {} ns, latency {} ns",
+                thread, iteration, timing.total_ns, timing.latency_ns
+            );
+            results.push(BenchResult {
+                platform: platform.to_string(),
+                allocator: cell.allocator.as_str().to_string(),
+                pattern: cell.pattern.as_str().to_string(),
+                size_bytes: cell.size as u32,
+                threads: cell.threads as u32,
+                thread: thread as u32,
+                numa: cell.numa.as_str().to_string(),
+                smt: cell.smt.as_str().to_string(),
+                core,
+                core_type: core_type.map(str::to_string),
+                iteration: iteration as u32,
+                total_ns: timing.total_ns,
+                latency_ns: timing.latency_ns,
+            });
+        }
+    }
+    results
+}
+
+// 現在のスレッドをコアに固定し、mem_nodeを指定した場合はメモリをそのNUMAノードに割り当てる
+fn pin_thread(t: usize, core: Option<core_affinity::CoreId>, mem_node: Option<usize>) {
+    if let Some(core) = core {
+        if !core_affinity::set_for_current(core) {
+            warn!("Failed to pin thread {} to core {}", t, core.id);
+        }
+    }
+    if let Some(node) = mem_node {
+        if let Err(e) = numa::bind_memory(node) {
+            warn!(
+                "Failed to bind memory of thread {} to node {}: {}",
+                t, node, e
+            );
+        }
+    }
+}
+
+fn warmup(clock: &Clock) {
+    // CPU/タイマーのウォームアップ
+    for _ in 0..10000 {
+        let _ = black_box(clock.raw());
+        drop(touch::touched(Box::new(Data::<64>::new())));
+    }
+}
+
+// ベンチマーク実行時の引数（サブコマンドなしの場合）
+#[derive(clap::Args, Debug)]
+pub struct RunArgs {
+    /// Platform name (e.g., 'local', 'hpc-cluster', 'aws-c5')
+    // サブコマンド指定時は省略されるのでOption（サブコマンドなしでは必須）
+    #[arg(required = true)]
+    platform: Option<String>,
+
+    /// Comma-separated data sizes in bytes (8, 12, 16, 24, 32, 48, 64, 96, ...,
+    /// 3072 or 4096; other sizes are rejected so every cell measures exactly
+    /// the requested size)
+    #[arg(long, value_delimiter = ',', num_args = 1..)]
+    sizes: Option<Vec<usize>>,
+
+    /// Comma-separated thread counts to run each cell with ("max" for all cores,
+    /// "1..8" for an inclusive range, "sweep" for powers of two up to all cores).
+    /// Each thread runs the pattern independently on its own pinned core
+    #[arg(long, value_delimiter = ',', num_args = 1.., default_value = "1", value_parser = parse_thread_counts)]
+    threads: Vec<ThreadCounts>,
+
+    /// Comma-separated NUMA placements: none, local (memory on --numa-node)
+    /// or remote (memory on another node). Threads are pinned to --numa-node
+    #[arg(long, value_enum, value_delimiter = ',', num_args = 1.., default_value = "none")]
+    numa: Vec<numa::Placement>,
+
+    /// NUMA node to run the benchmark threads on (with --numa local/remote)
+    #[arg(long, default_value_t = 0)]
+    numa_node: usize,
+
+    /// Comma-separated SMT placements for multi-threaded cells: any,
+    /// siblings (hyperthreads of one physical core) or separate (one thread
+    /// per physical core)
+    #[arg(long, value_enum, value_delimiter = ',', num_args = 1.., default_value = "any")]
+    smt: Vec<topology::Smt>,
+
+    /// Only pin threads to performance or efficiency cores of a hybrid CPU
+    /// (big.LITTLE, P-cores/E-cores). Single-threaded cells are pinned too
+    #[arg(long, value_enum)]
+    core_type: Option<topology::CoreType>,
+
+    /// Only run cells matching this selector, e.g.
+    /// 'allocator=slab_warm|box,pattern=random,size=64..512' (repeatable, OR-ed)
+    #[arg(long, value_name = "SELECTOR")]
+    only: Vec<select::Selector>,
+
+    /// Skip cells matching this selector, e.g. 'pattern=immediate' (repeatable)
+    #[arg(long, value_name = "SELECTOR")]
+    exclude: Vec<select::Selector>,
+
+    /// Output file path, or the dataset root directory with --layout hive
+    /// [default: results/benchmark_<platform>.<format> or results/dataset]
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Output file format
+    #[arg(long, value_enum, default_value_t)]
+    format: output::Format,
+
+    /// Output layout. `hive` writes platform=<name>/allocator=<name>/part-<n>
+    /// files under the output directory, which DuckDB and other engines can
+    /// query in place
+    #[arg(long, value_enum, default_value_t = output::Layout::File)]
+    layout: output::Layout,
+
+    /// Comma-separated partition columns for --layout hive, outermost first
+    #[arg(
+        long,
+        value_enum,
+        value_delimiter = ',',
+        default_value = "platform,allocator"
+    )]
+    partition_by: Vec<output::PartitionColumn>,
+
+    #[command(flatten)]
+    parquet: output::ParquetOptions,
+
+    /// Measure up to N single-threaded cells concurrently, each on its own
+    /// pinned core ("max" for all cores). Cells with --threads > 1 or
+    /// --numa local/remote still run one at a time
+    #[arg(long, default_value = "1", value_parser = parse_threads)]
+    jobs: usize,
+
+    /// Validate the configuration, print the planned matrix and estimated
+    /// duration, then exit without measuring
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Keep the rows already in the output file and only measure the cells
+    /// (cell_id allocator/pattern/size/threads/numa/smt for this platform)
+    /// that are missing
+    #[arg(long)]
+    resume: bool,
+
+    /// Keep the rows already in the output file and add this run's rows
+    /// instead of overwriting it (hive and sqlite outputs always append)
+    #[arg(long)]
+    append: bool,
+
+    /// Make the results readable on disk every N measured cells, so an OOM
+    /// kill or node failure loses at most N cells (0 to only write at the end)
+    #[arg(long, value_name = "N", default_value_t = 10)]
+    checkpoint_every: usize,
+
+    /// Time budget for the whole run, e.g. '30m' or '1h30m'. Iterations per
+    /// cell are scaled down from the default to fit, but every cell still
+    /// gets at least the minimum sample
+    #[arg(long, value_name = "DURATION", value_parser = budget::parse_duration)]
+    max_duration: Option<Duration>,
+
+    /// Re-measure each cell until the 95% confidence interval of the median
+    /// is within this fraction of the median (e.g. 0.02 or 2%), instead of a
+    /// fixed number of iterations
+    #[arg(long, value_name = "WIDTH", value_parser = stable::parse_width, conflicts_with = "max_duration")]
+    until_stable: Option<f64>,
+
+    /// Iteration cap per cell for --until-stable
+    #[arg(long, value_name = "N", default_value_t = 1000, value_parser = clap::value_parser!(u32).range(1..))]
+    max_iterations: u32,
+
+    /// POST the written result file(s) to this URL after the run
+    #[cfg(feature = "upload")]
+    #[arg(long, value_name = "URL")]
+    upload: Option<String>,
+}
+
+// --sizesの指定を確かめる（重複は除去、順序は保持）
+// 実装のないサイズを近いサイズに読み替えると、サイズクラスの境界の前後を測ったつもりで同じサイズを測ってしまうのでエラーにする
+fn resolve_sizes(requested: &[usize]) -> Result<Vec<usize>, String> {
+    let mut sizes = Vec::with_capacity(requested.len());
+    for &size in requested {
+        if !SIZES.contains(&size) {
+            let supported: Vec<String> = SIZES.iter().map(|s| s.to_string()).collect();
+            return Err(format!(
+                "unsupported size: {} bytes (supported: {})",
+                size,
+                supported.join(", ")
+            ));
+        }
+        if !sizes.contains(&size) {
+            sizes.push(size);
+        }
+    }
+    Ok(sizes)
+}
+
+// --threadsの1要素で指定したスレッド数の並び
+#[derive(Clone, Debug)]
+struct ThreadCounts(Vec<usize>);
+
+// "4" / "max" / "1..8" / "sweep" -> スレッド数の並び
+// 範囲は両端を含み、sweepは論理コア数までの2のべき乗（最後に論理コア数そのものを加える）
+fn parse_thread_counts(s: &str) -> Result<ThreadCounts, String> {
+    if s == "sweep" {
+        let max = parse_threads("max")?;
+        let mut counts: Vec<usize> = std::iter::successors(Some(1usize), |&n| n.checked_mul(2))
+            .take_while(|&n| n <= max)
+            .collect();
+        if counts.last() != Some(&max) {
+            counts.push(max);
+        }
+        return Ok(ThreadCounts(counts));
+    }
+    if let Some((lo, hi)) = s.split_once("..") {
+        let (lo, hi) = (parse_threads(lo)?, parse_threads(hi)?);
+        if lo > hi {
+            return Err(format!("empty thread range: {}", s));
+        }
+        return Ok(ThreadCounts((lo..=hi).collect()));
+    }
+    parse_threads(s).map(|n| ThreadCounts(vec![n]))
+}
+
+// 指定順を保ったまま重複を除いたスレッド数の一覧
+fn expand_thread_counts(counts: &[ThreadCounts]) -> Vec<usize> {
+    let mut threads = Vec::new();
+    for &t in counts.iter().flat_map(|c| &c.0) {
+        if !threads.contains(&t) {
+            threads.push(t);
+        }
+    }
+    threads
+}
+
+// "4" / "max" -> スレッド数
+fn parse_threads(s: &str) -> Result<usize, String> {
+    if s == "max" {
+        return Ok(std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1));
+    }
+    match s.parse::<usize>() {
+        Ok(0) => Err("thread count must be greater than 0".to_string()),
+        Ok(n) => Ok(n),
+        Err(_) => Err(format!("invalid thread count: {}", s)),
+    }
+}
+
+fn format_duration(secs: f64) -> String {
+    let secs = secs.round() as u64;
+    match (secs / 3600, secs % 3600 / 60, secs % 60) {
+        (0, 0, s) => format!("{}s", s),
+        (0, m, s) => format!("{}m {}s", m, s),
+        (h, m, s) => format!("{}h {}m {}s", h, m, s),
+    }
+}
+
+// 1セルで計測したイテレーション数（スレッドごとに同じ回数だけ計測する）
+fn measured_iterations(timings: &[Vec<BenchTiming>]) -> u32 {
+    timings.first().map_or(0, |t| t.len() as u32)
+}
+
+// セルの計測にかかる時間の枠の数（--jobsで並行に計測する1コアのセルは1/jobs枠）
+fn slots<'a>(cells: impl IntoIterator<Item = &'a Cell>, jobs: usize) -> f64 {
+    cells
+        .into_iter()
+        .map(|c| {
+            if jobs > 1 && c.is_single_core() {
+                1.0 / jobs as f64
+            } else {
+                1.0
+            }
+        })
+        .sum()
+}
+
+// 計測したセルの1操作あたりの時間（ns/op）の要約を表示する
+fn log_summary(summaries: &BTreeMap<stats::CellKey, stats::Summary>) {
+    if summaries.is_empty() {
+        return;
+    }
+    info!("Summary (ns/op, n = iterations x threads):");
+    info!(
+        "{:<16} {:<10} {:>6} {:>7} {:<6} {:<8} {:>6} {:>9} {:>9} {:>9} {:>9} {:>19}",
+        "allocator",
+        "pattern",
+        "size",
+        "threads",
+        "numa",
+        "smt",
+        "n",
+        "median",
+        "mean",
+        "stddev",
+        "p99",
+        "median 95% CI"
+    );
+    for ((allocator, pattern, size, threads, numa, smt), summary) in summaries {
+        let s = summary.per(OPS_PER_ITERATION as f64);
+        info!(
+            "{:<16} {:<10} {:>6} {:>7} {:<6} {:<8} {:>6} {:>9.3} {:>9.3} {:>9.3} {:>9.3} {:>9.3}..{:.3}",
+            allocator,
+            pattern,
+            size,
+            threads,
+            numa,
+            smt,
+            s.count,
+            s.median,
+            s.mean,
+            s.stddev,
+            s.p99,
+            s.median_ci.0,
+            s.median_ci.1
+        );
+    }
+}
+
+// 実行計画を表示する（--dry-run用）
+// dimensionsは絞り込み前の行列の各次元の (要素数, 名前)
+fn print_plan(
+    cells: &[Cell],
+    dimensions: &[(usize, &str)],
+    jobs: usize,
+    max_duration: Option<Duration>,
+    until_stable: Option<stable::UntilStable>,
+    output_path: &std::path::Path,
+    format: output::Format,
+) {
+    // 選択されたセルに現れる値だけを、定義順のまま表示する
+    let allocators: Vec<&str> = Allocator::all()
+        .iter()
+        .filter(|&&a| cells.iter().any(|c| c.allocator == a))
+        .map(|a| a.as_str())
+        .collect();
+    let patterns: Vec<&str> = Pattern::all()
+        .iter()
+        .filter(|&&p| cells.iter().any(|c| c.pattern == p))
+        .map(|p| p.as_str())
+        .collect();
+    let sizes: BTreeSet<usize> = cells.iter().map(|c| c.size).collect();
+    let threads: BTreeSet<usize> = cells.iter().map(|c| c.threads).collect();
+    let placements: Vec<&str> = numa::Placement::value_variants()
+        .iter()
+        .filter(|&&p| cells.iter().any(|c| c.numa == p))
+        .map(|p| p.as_str())
+        .collect();
+    let smt: Vec<&str> = topology::Smt::value_variants()
+        .iter()
+        .filter(|&&p| cells.iter().any(|c| c.smt == p))
+        .map(|p| p.as_str())
+        .collect();
+    // 複数スレッドのセルは並行に走るので、所要時間はセル数で見積もる
+    // --jobsで並行に計測するセルはジョブ数で割る
+    let concurrent = cells
+        .iter()
+        .filter(|c| jobs > 1 && c.is_single_core())
+        .count();
+    // --max-duration: 見積もりの時間で予算を等分したイテレーション数
+    // --until-stable: 所要時間とレコード数は上限まで計測した場合で見積もる
+    let iterations = match until_stable {
+        Some(until) => until.max_iterations,
+        None => {
+            budget::Budget::new(max_duration, slots(cells, jobs), ESTIMATED_NS_PER_OP).iterations()
+        }
+    };
+    let measurements = (cells.len() - concurrent + concurrent.div_ceil(jobs)) * iterations as usize;
+    let records: usize = cells.iter().map(|c| c.threads).sum::<usize>() * iterations as usize;
+
+    let ops = measurements as f64 * OPS_PER_ITERATION as f64;
+    let estimated_secs = ops * ESTIMATED_NS_PER_OP * 1e-9;
+
+    println!("Planned matrix:");
+    println!("  Allocators: {}", allocators.join(", "));
+    println!("  Patterns:   {}", patterns.join(", "));
+    println!(
+        "  Sizes:      {} bytes",
+        sizes
+            .iter()
+            .map(|s| s.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    println!(
+        "  Threads:    {}",
+        threads
+            .iter()
+            .map(|t| t.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    if placements != ["none"] {
+        println!("  NUMA:       {}", placements.join(", "));
+    }
+    if smt != ["any"] {
+        println!("  SMT:        {}", smt.join(", "));
+    }
+    println!(
+        "  Cells:      {} selected of {} ({})",
+        cells.len(),
+        dimensions.iter().map(|(n, _)| n).product::<usize>(),
+        dimensions
+            .iter()
+            .map(|(n, name)| format!("{} {}", n, name))
+            .collect::<Vec<_>>()
+            .join(" x ")
+    );
+    match until_stable {
+        Some(until) => println!(
+            "  Iterations: {}..{} per cell until the median 95% CI is within {:.2}% (inner loop {} x batch {})",
+            budget::MIN_ITERATIONS.min(until.max_iterations),
+            until.max_iterations,
+            until.target * 100.0,
+            INNER_LOOP,
+            BATCH_SIZE
+        ),
+        None => println!(
+            "  Iterations: {} per cell (inner loop {} x batch {})",
+            iterations, INNER_LOOP, BATCH_SIZE
+        ),
+    }
+    if let Some(limit) = max_duration {
+        println!(
+            "  Budget:     {} (iterations rescaled per cell as measured, at least {})",
+            format_duration(limit.as_secs_f64()),
+            budget::MIN_ITERATIONS
+        );
+    }
+    let up_to = if until_stable.is_some() { "up to " } else { "" };
+    println!("  Records:    {}{}", up_to, records);
+    if jobs > 1 {
+        println!(
+            "  Jobs:       {} ({} cells run concurrently)",
+            jobs, concurrent
+        );
+    }
+    println!(
+        "  Output:     {} ({})",
+        output_path.display(),
+        format.extension()
+    );
+    println!(
+        "Estimated duration: {}~{} (assuming {} ns per alloc/dealloc)",
+        up_to,
+        format_duration(estimated_secs),
+        ESTIMATED_NS_PER_OP
+    );
+}
+
+pub fn run(args: &RunArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let platform = args
+        .platform
+        .as_ref()
+        .expect("platform is required without a subcommand");
+
+    let sizes = match &args.sizes {
+        Some(requested) => resolve_sizes(requested)?,
+        None => SIZES.to_vec(),
+    };
+    if args.layout == output::Layout::Hive && args.format.appends() {
+        return Err(format!(
+            "--layout hive cannot be used with --format {:?}",
+            args.format
+        )
+        .into());
+    }
+    let output_path = args.output.clone().unwrap_or_else(|| match args.layout {
+        output::Layout::File => PathBuf::from(format!(
+            "results/benchmark_{}.{}",
+            platform,
+            args.format.extension()
+        )),
+        output::Layout::Hive => PathBuf::from("results/dataset"),
+    });
+
+    let threads = expand_thread_counts(&args.threads);
+    let mut placements = Vec::with_capacity(args.numa.len());
+    for &p in &args.numa {
+        if !placements.contains(&p) {
+            placements.push(p);
+        }
+    }
+    let mut smt_placements = Vec::with_capacity(args.smt.len());
+    for &p in &args.smt {
+        if !smt_placements.contains(&p) {
+            smt_placements.push(p);
+        }
+    }
+
+    // --only / --exclude で計測するセルを絞り込む
+    let cells: Vec<Cell> = Allocator::all()
+        .iter()
+        .flat_map(|&a| Pattern::all().iter().map(move |&p| (a, p)))
+        .flat_map(|(a, p)| sizes.iter().map(move |&s| (a, p, s)))
+        .flat_map(|(a, p, s)| threads.iter().map(move |&t| (a, p, s, t)))
+        .flat_map(|(a, p, s, t)| placements.iter().map(move |&n| (a, p, s, t, n)))
+        .flat_map(|(allocator, pattern, size, threads, numa)| {
+            smt_placements.iter().map(move |&smt| Cell {
+                allocator,
+                pattern,
+                size,
+                threads,
+                numa,
+                smt,
+            })
+        })
+        // SMTの使い方は複数スレッドのセルでしか意味がない
+        .filter(|cell| cell.threads > 1 || cell.smt == topology::Smt::Any)
+        .filter(|cell| cell.allocator.supports(cell.pattern))
+        .filter(|cell| {
+            (args.only.is_empty() || args.only.iter().any(|sel| sel.matches(cell)))
+                && !args.exclude.iter().any(|sel| sel.matches(cell))
+        })
+        .collect();
+    if cells.is_empty() {
+        return Err(
+            "no cells selected (check --sizes, --threads, --numa, --smt, --only and --exclude)"
+                .into(),
+        );
+    }
+
+    // --numa local/remote: スレッドを固定するノードと、メモリを割り当てるノードを決める
+    let numa_nodes = numa::nodes();
+    let mut numa_cores = Vec::new();
+    let mut remote_node = None;
+    if cells.iter().any(|c| c.numa != numa::Placement::None) {
+        let node = numa_nodes
+            .iter()
+            .find(|n| n.id == args.numa_node)
+            .ok_or_else(|| {
+                format!(
+                    "NUMA node {} not found (available: {:?})",
+                    args.numa_node,
+                    numa_nodes.iter().map(|n| n.id).collect::<Vec<_>>()
+                )
+            })?;
+        numa_cores = node
+            .cpus
+            .iter()
+            .map(|&id| core_affinity::CoreId { id })
+            .collect();
+        if cells.iter().any(|c| c.numa == numa::Placement::Remote) {
+            // --numa-nodeの次のノードをリモートとして使う
+            let index = numa_nodes.iter().position(|n| n.id == node.id).unwrap_or(0);
+            let remote = &numa_nodes[(index + 1) % numa_nodes.len()];
+            if remote.id == node.id {
+                return Err("--numa remote requires at least 2 NUMA nodes".into());
+            }
+            remote_node = Some(remote.id);
+        }
+    }
+
+    // --smt siblings/separate: 物理コアのトポロジに合わせて固定先を並べ替える
+    let mut core_ids = core_affinity::get_core_ids().unwrap_or_default();
+    let physical_cores = topology::physical_cores();
+
+    // --core-type: ハイブリッドCPUで性能コア / 効率コアの一方だけに固定する
+    let core_types = topology::core_types();
+    if let Some(ty) = args.core_type {
+        if core_types.is_empty() {
+            return Err(
+                "--core-type requires a hybrid CPU with performance and efficiency cores".into(),
+            );
+        }
+        core_ids = topology::filter_core_type(&core_ids, ty, &core_types);
+        numa_cores = topology::filter_core_type(&numa_cores, ty, &core_types);
+        if core_ids.is_empty() {
+            return Err(format!("no {} cores available for pinning", ty.as_str()).into());
+        }
+        if cells.iter().any(|c| c.numa != numa::Placement::None) && numa_cores.is_empty() {
+            return Err(
+                format!("NUMA node {} has no {} cores", args.numa_node, ty.as_str()).into(),
+            );
+        }
+    }
+    for &smt in &smt_placements {
+        for &numa in &placements {
+            let cores = match numa {
+                numa::Placement::None => &core_ids,
+                _ => &numa_cores,
+            };
+            let max_threads = cells
+                .iter()
+                .filter(|c| c.smt == smt && c.numa == numa)
+                .map(|c| c.threads)
+                .max();
+            let Some(max_threads) = max_threads else {
+                continue;
+            };
+            let arranged = topology::arrange(cores, smt, &physical_cores)?;
+            if smt != topology::Smt::Any && arranged.len() < max_threads {
+                warn!(
+                    "--smt {}: only {} cores available for {} threads, some threads will share a core",
+                    smt.as_str(),
+                    arranged.len(),
+                    max_threads
+                );
+            }
+        }
+    }
+
+    // --until-stable: 中央値の信頼区間が目標の幅に収まるまで計測し直す
+    let until_stable = args.until_stable.map(|target| stable::UntilStable {
+        target,
+        max_iterations: args.max_iterations,
+    });
+
+    if args.dry_run {
+        print_plan(
+            &cells,
+            &[
+                (Allocator::all().len(), "allocators"),
+                (Pattern::all().len(), "patterns"),
+                (sizes.len(), "sizes"),
+                (threads.len(), "thread counts"),
+                (placements.len(), "NUMA placements"),
+                (smt_placements.len(), "SMT placements"),
+            ],
+            args.jobs,
+            args.max_duration,
+            until_stable,
+            &output_path,
+            args.format,
+        );
+        return Ok(());
+    }
+
+    info!("Platform: {}", platform);
+    let env = environment::Environment::collect();
+    info!(
+        "Host: {} ({} {}, kernel {}), CPU: {} x{} ({} physical cores, {} threads per core)",
+        env.hostname,
+        env.os,
+        env.arch,
+        env.kernel,
+        env.cpu_model,
+        env.cores,
+        env.physical_cores,
+        env.threads_per_core
+    );
+    if env.performance_cores > 0 {
+        info!(
+            "Hybrid CPU: {} performance cores, {} efficiency cores",
+            env.performance_cores, env.efficiency_cores
+        );
+    }
+    info!(
+        "Build: memalloc-bench {} ({}), {}",
+        env.crate_version, env.profile, env.rustc
+    );
+    let mut metadata = env.to_metadata();
+    if let Some(limit) = args.max_duration {
+        metadata.insert(
+            budget::MAX_DURATION_KEY.to_string(),
+            limit.as_secs().to_string(),
+        );
+    }
+    if let Some(target) = args.until_stable {
+        metadata.insert(stable::UNTIL_STABLE_KEY.to_string(), target.to_string());
+    }
+    info!("Inner loop: {} iterations per measurement", INNER_LOOP);
+
+    let clock = Clock::new();
+    let mut rng = rand::rngs::StdRng::seed_from_u64(42);
+
+    // --core-type指定時は、メインスレッドで計測するセルも指定した種類のコアで動かす
+    let main_core: Vec<core_affinity::CoreId> = match args.core_type {
+        Some(ty) => {
+            let core = core_ids[0];
+            pin_thread(0, Some(core), None);
+            info!("Pinned the main thread to {} core {}", ty.as_str(), core.id);
+            vec![core]
+        }
+        None => Vec::new(),
+    };
+
+    info!("Warming up...");
+    warmup(&clock);
+
+    let max_threads = threads.iter().copied().max().unwrap_or(1);
+    if max_threads > 1 && core_ids.len() < max_threads {
+        warn!(
+            "{} threads requested but only {} cores are available for pinning",
+            max_threads,
+            core_ids.len()
+        );
+    }
+
+    let total = cells.len();
+
+    let mut writer = output::ResultWriter::create(
+        &output_path,
+        args.layout,
+        args.format,
+        &args.partition_by,
+        &args.parquet,
+        &metadata,
+    )?;
+
+    // --resume / --append: 既存の結果を引き継ぐ（--resumeでは計測済みのセルをスキップする）
+    let mut completed = HashSet::new();
+    if (args.resume || args.append) && output_path.exists() {
+        let existing = output::read_results(&output_path, args.format)?;
+        if args.resume {
+            for r in existing.iter().filter(|r| &r.platform == platform) {
+                completed.insert(r.cell_id());
+            }
+            info!(
+                "Resuming from {}: {} records, {} cells already measured",
+                output_path.display(),
+                existing.len(),
+                completed.len()
+            );
+        } else {
+            info!(
+                "Appending to {}: {} existing records",
+                output_path.display(),
+                existing.len()
+            );
+        }
+        // 追記形式の出力以外では既存の行を書き直す
+        if !(args.format.appends() || args.layout == output::Layout::Hive) {
+            writer.write(&existing)?;
+        }
+    }
+
+    info!("Writing results to {}...", output_path.display());
+
+    // SIGINT / SIGTERMを受けたら、計測中のセルを終えたところで止めて途中までの結果を書き出す
+    interrupt::install()?;
+
+    // この実行で計測したセルの要約（最後に表示する）
+    let mut summaries = BTreeMap::new();
+
+    // --checkpoint-every: セルをN個書くごとに、書いた分を途中で落ちても読めるファイルとして置く
+    let checkpoint = |writer: &mut output::ResultWriter, measured: usize| {
+        if args.checkpoint_every > 0 && measured.is_multiple_of(args.checkpoint_every) {
+            debug!("Checkpoint after {} cells", measured);
+            writer.checkpoint()
+        } else {
+            Ok(())
+        }
+    };
+
+    // 計測済みのセルを除く
+    let mut pending = Vec::with_capacity(cells.len());
+    for (index, &cell) in cells.iter().enumerate() {
+        if completed.contains(&cell.id()) {
+            info!(
+                "[{}/{}] {} (skipped, already measured)",
+                index + 1,
+                total,
+                cell
+            );
+        } else {
+            pending.push((index + 1, cell));
+        }
+    }
+
+    // --max-duration: 残りの時間を残りのセルで分け、セルごとのイテレーション数を決める
+    let budget = Mutex::new(budget::Budget::new(
+        args.max_duration,
+        slots(pending.iter().map(|(_, cell)| cell), args.jobs),
+        ESTIMATED_NS_PER_OP,
+    ));
+    if let Some(limit) = args.max_duration {
+        info!(
+            "Time budget: {}, ~{} iterations per cell planned (at least {})",
+            format_duration(limit.as_secs_f64()),
+            budget.lock().unwrap().iterations(),
+            budget::MIN_ITERATIONS
+        );
+    }
+
+    // --jobs: 1コアで完結するセルを別々のコアで並行に計測し、それ以外は1つずつ計測する
+    let (concurrent, sequential): (Vec<_>, Vec<_>) = pending
+        .into_iter()
+        .partition(|(_, cell)| args.jobs > 1 && cell.is_single_core());
+    if !concurrent.is_empty() {
+        if core_ids.len() < args.jobs {
+            warn!(
+                "{} jobs requested but only {} cores are available; some jobs will share a core",
+                args.jobs,
+                core_ids.len()
+            );
+        }
+        info!(
+            "Running {} cells on {} concurrent jobs",
+            concurrent.len(),
+            args.jobs
+        );
+
+        // シードはセルの順に決めておき、ジョブの実行順に依存しないようにする
+        let queue: Mutex<VecDeque<(usize, Cell, u64)>> = Mutex::new(
+            concurrent
+                .into_iter()
+                .map(|(index, cell)| (index, cell, rng.gen()))
+                .collect(),
+        );
+        let (tx, rx) = mpsc::channel();
+        std::thread::scope(|s| -> Result<(), Box<dyn std::error::Error>> {
+            for job in 0..args.jobs {
+                let queue = &queue;
+                let budget = &budget;
+                let clock = clock.clone();
+                let tx = tx.clone();
+                // 各ジョブは1つのコアを専有し、そのコアに固定したスレッドでセルを計測する
+                let cores: Vec<core_affinity::CoreId> =
+                    core_for(&core_ids, job).into_iter().collect();
+                s.spawn(move || loop {
+                    if interrupt::requested() {
+                        break;
+                    }
+                    let Some((index, cell, seed)) = queue.lock().unwrap().pop_front() else {
+                        break;
+                    };
+                    let iterations = budget.lock().unwrap().iterations();
+                    // --until-stableで計測し直すときは、回ごとにシードを変える
+                    let mut round = 0;
+                    let mut run = |iterations| {
+                        let seed = seed.wrapping_add(round << 32);
+                        round += 1;
+                        if cell.allocator.is_shared() {
+                            shared::run_shared(&clock, cell, &cores, None, seed, iterations)
+                        } else {
+                            run_parallel(&clock, cell, &cores, None, seed, iterations)
+                        }
+                    };
+                    let timings = match &until_stable {
+                        Some(until) => until.measure(&mut run),
+                        None => run(iterations),
+                    };
+                    budget
+                        .lock()
+                        .unwrap()
+                        .finish(1.0 / args.jobs as f64, measured_iterations(&timings));
+                    if tx.send((index, cell, cores.clone(), timings)).is_err() {
+                        break;
+                    }
+                });
+            }
+            drop(tx);
+
+            // 終わったセルから順に書き出す
+            for (index, cell, cores, timings) in rx {
+                match cores.first() {
+                    Some(core) => info!("[{}/{}] {} (core {})", index, total, cell, core.id),
+                    None => info!("[{}/{}] {}", index, total, cell),
+                }
+                if let Some(until) = &until_stable {
+                    until.log_result(&timings);
+                }
+                let rows = to_results(platform, cell, timings, &cores, &core_types);
+                summaries.extend(stats::summarize(&rows));
+                writer.write(&rows)?;
+                checkpoint(&mut writer, summaries.len())?;
+            }
+            Ok(())
+        })?;
+    }
+
+    for (index, cell) in sequential {
+        if interrupt::requested() {
+            break;
+        }
+        let iterations = budget.lock().unwrap().iterations();
+        if args.max_duration.is_some() {
+            info!("[{}/{}] {} ({} iterations)", index, total, cell, iterations);
+        } else {
+            info!("[{}/{}] {}", index, total, cell);
+        }
+
+        let (cores, mem_node) = match cell.numa {
+            numa::Placement::None => (&core_ids, None),
+            numa::Placement::Local => (&numa_cores, Some(args.numa_node)),
+            numa::Placement::Remote => (&numa_cores, remote_node),
+        };
+        let cores = topology::arrange(cores, cell.smt, &physical_cores)?;
+        // 1スレッドでNUMA配置の指定もなければ、従来どおりメインスレッドで計測する
+        let on_main = !cell.allocator.is_shared() && cell.is_single_core();
+        let mut run = |iterations| {
+            if cell.allocator.is_shared() {
+                shared::run_shared(&clock, cell, &cores, mem_node, rng.gen(), iterations)
+            } else if on_main {
+                vec![(0..iterations)
+                    .map(|_| {
+                        run_benchmark(&clock, cell.allocator, cell.pattern, cell.size, &mut rng)
+                    })
+                    .collect()]
+            } else {
+                run_parallel(&clock, cell, &cores, mem_node, rng.gen(), iterations)
+            }
+        };
+        let timings = match &until_stable {
+            Some(until) => {
+                let timings = until.measure(&mut run);
+                until.log_result(&timings);
+                timings
+            }
+            None => run(iterations),
+        };
+        let cores = if on_main {
+            main_core.as_slice()
+        } else {
+            cores.as_slice()
+        };
+
+        budget
+            .lock()
+            .unwrap()
+            .finish(1.0, measured_iterations(&timings));
+
+        // セルが終わるごとに書き出す
+        let rows = to_results(platform, cell, timings, cores, &core_types);
+        summaries.extend(stats::summarize(&rows));
+        writer.write(&rows)?;
+        checkpoint(&mut writer, summaries.len())?;
+    }
+
+    if budget.lock().unwrap().exceeded() {
+        warn!(
+            "The run exceeded --max-duration; every cell is measured at least {} times",
+            budget::MIN_ITERATIONS
+        );
+    }
+
+    let interrupted = interrupt::requested();
+    if !writer.set_complete(!interrupted) && interrupted {
+        warn!(
+            "{} output has no metadata to mark it incomplete",
+            args.format.extension()
+        );
+    }
+    let written = writer.written();
+    let paths = writer.finish()?;
+    for path in &paths {
+        info!("  - {}", path.display());
+    }
+    if interrupted {
+        log_summary(&summaries);
+        return Err(format!(
+            "interrupted after measuring {} cells, {} records written (rerun with --resume to measure the rest)",
+            summaries.len(),
+            written
+        )
+        .into());
+    }
+    info!("Done! {} records written.", written);
+    log_summary(&summaries);
+
+    #[cfg(feature = "upload")]
+    if let Some(url) = &args.upload {
+        let root = match args.layout {
+            output::Layout::File => output_path.parent().unwrap_or(&output_path),
+            output::Layout::Hive => &output_path,
+        };
+        upload::upload_results(url, root, &paths, args.format, platform)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,87 @@
+// NUMAノードに対するメモリの配置
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Placement {
+    /// Do not pin threads or bind memory
+    None,
+    /// Pin threads to --numa-node and bind memory to the same node
+    Local,
+    /// Pin threads to --numa-node and bind memory to another node
+    Remote,
+}
+
+impl Placement {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Placement::None => "none",
+            Placement::Local => "local",
+            Placement::Remote => "remote",
+        }
+    }
+}
+
+pub struct Node {
+    pub id: usize,
+    pub cpus: Vec<usize>,
+}
+
+// /sys/devices/system/node からNUMAノードとそのCPUを列挙する（Linux以外では空）
+pub fn nodes() -> Vec<Node> {
+    let Ok(entries) = std::fs::read_dir("/sys/devices/system/node") else {
+        return Vec::new();
+    };
+    let mut nodes: Vec<Node> = entries
+        .filter_map(|e| e.ok())
+        .filter_map(|e| {
+            let name = e.file_name();
+            let id = name.to_str()?.strip_prefix("node")?.parse().ok()?;
+            let cpus = parse_cpulist(&std::fs::read_to_string(e.path().join("cpulist")).ok()?)?;
+            // メモリだけのノードは除く
+            (!cpus.is_empty()).then_some(Node { id, cpus })
+        })
+        .collect();
+    nodes.sort_by_key(|n| n.id);
+    nodes
+}
+
+// "0-3,8-11" -> [0, 1, 2, 3, 8, 9, 10, 11]
+pub fn parse_cpulist(s: &str) -> Option<Vec<usize>> {
+    let mut cpus = Vec::new();
+    for part in s.trim().split(',').filter(|p| !p.is_empty()) {
+        match part.split_once('-') {
+            Some((lo, hi)) => cpus.extend(lo.parse::<usize>().ok()?..=hi.parse().ok()?),
+            None => cpus.push(part.parse().ok()?),
+        }
+    }
+    Some(cpus)
+}
+
+// 呼び出したスレッドが以降に確保するメモリを指定ノードに割り当てる
+#[cfg(target_os = "linux")]
+pub fn bind_memory(node: usize) -> std::io::Result<()> {
+    const MPOL_BIND: libc::c_int = 2;
+    const BITS: usize = libc::c_ulong::BITS as usize;
+
+    let mut mask = vec![0 as libc::c_ulong; node / BITS + 1];
+    mask[node / BITS] |= 1 << (node % BITS);
+    // SAFETY: maskは (maxnode - 1) ビット分の有効なビットマスク
+    let ret = unsafe {
+        libc::syscall(
+            libc::SYS_set_mempolicy,
+            MPOL_BIND,
+            mask.as_ptr(),
+            mask.len() * BITS + 1,
+        )
+    };
+    if ret != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn bind_memory(_node: usize) -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "memory binding is only supported on Linux",
+    ))
+}
@@ -0,0 +1,199 @@
+use crate::backend::BenchAllocator;
+use crate::{BenchTiming, BATCH_SIZE, INNER_LOOP};
+use quanta::Clock;
+use rand::Rng;
+use std::hint::black_box;
+use std::sync::mpsc;
+
+// 確保と解放の順序（パターン）をBenchAllocatorの上に1度だけ書く
+// パターンを足すときはこのモジュールにPatternを実装し、crate::Patternにバリアントを、
+// backend::benchに対応を1行足せばよい（アロケータやデータサイズごとの分岐には触れない）
+
+/// allocatorに対して確保と解放を流すパターン
+pub trait Pattern {
+    /// allocatorで1イテレーション（INNER_LOOP回のラウンド）計測する
+    fn run<A: BenchAllocator>(&mut self, clock: &Clock, allocator: &A) -> BenchTiming;
+}
+
+// 1回目のラウンドをレイテンシとして、残りINNER_LOOP-1回と合わせて計測する
+#[inline(always)]
+pub(crate) fn measure(clock: &Clock, mut round: impl FnMut()) -> BenchTiming {
+    let lat_start = clock.raw();
+    round();
+    let lat_end = clock.raw();
+    let latency_ns = clock.delta(lat_start, lat_end).as_nanos() as u64;
+
+    let start = clock.raw();
+    for _ in 1..INNER_LOOP {
+        round();
+    }
+    let end = clock.raw();
+    let rest_ns = clock.delta(start, end).as_nanos() as u64;
+
+    BenchTiming {
+        total_ns: latency_ns + rest_ns,
+        latency_ns,
+    }
+}
+
+/// alloc→deallocをBATCH_SIZE回繰り返す
+/// coldなアロケータは確保のたびにインスタンスを作り直す
+pub struct Immediate;
+
+impl Pattern for Immediate {
+    fn run<A: BenchAllocator>(&mut self, clock: &Clock, allocator: &A) -> BenchTiming {
+        if allocator.cold() {
+            return measure(clock, || {
+                for _ in 0..BATCH_SIZE {
+                    let instance = allocator.setup(1);
+                    let handle = allocator.alloc(&instance);
+                    allocator.dealloc(&instance, black_box(handle));
+                    allocator.teardown(instance);
+                }
+            });
+        }
+        measure(clock, || {
+            let instance = allocator.setup(1);
+            for _ in 0..BATCH_SIZE {
+                let handle = allocator.alloc(&instance);
+                allocator.dealloc(&instance, black_box(handle));
+            }
+            allocator.teardown(instance);
+        })
+    }
+}
+
+/// BATCH_SIZE個alloc → 逆順dealloc
+pub struct Lifo;
+
+impl Pattern for Lifo {
+    fn run<A: BenchAllocator>(&mut self, clock: &Clock, allocator: &A) -> BenchTiming {
+        measure(clock, || {
+            let instance = allocator.setup(BATCH_SIZE);
+            let mut handles = Vec::with_capacity(BATCH_SIZE);
+            for _ in 0..BATCH_SIZE {
+                handles.push(allocator.alloc(&instance));
+            }
+            while let Some(handle) = handles.pop() {
+                allocator.dealloc(&instance, black_box(handle));
+            }
+            drop(handles);
+            allocator.teardown(instance);
+        })
+    }
+}
+
+/// BATCH_SIZE個alloc → 順番dealloc
+pub struct Fifo;
+
+impl Pattern for Fifo {
+    fn run<A: BenchAllocator>(&mut self, clock: &Clock, allocator: &A) -> BenchTiming {
+        measure(clock, || {
+            let instance = allocator.setup(BATCH_SIZE);
+            let mut handles = Vec::with_capacity(BATCH_SIZE);
+            for _ in 0..BATCH_SIZE {
+                handles.push(allocator.alloc(&instance));
+            }
+            for handle in handles.into_iter() {
+                allocator.dealloc(&instance, black_box(handle));
+            }
+            allocator.teardown(instance);
+        })
+    }
+}
+
+/// スロットをランダムに選んでalloc済みならdealloc、空ならallocする
+pub struct Random<R>(pub R);
+
+impl<R: Rng> Pattern for Random<R> {
+    fn run<A: BenchAllocator>(&mut self, clock: &Clock, allocator: &A) -> BenchTiming {
+        let rng = &mut self.0;
+        measure(clock, || {
+            let instance = allocator.setup(BATCH_SIZE);
+            let mut slots: Vec<Option<A::Handle<'_>>> = (0..BATCH_SIZE).map(|_| None).collect();
+            for _ in 0..(BATCH_SIZE * 2) {
+                let idx = rng.gen_range(0..BATCH_SIZE);
+                match slots[idx].take() {
+                    Some(handle) => allocator.dealloc(&instance, black_box(handle)),
+                    None => {
+                        slots[idx] = Some(allocator.alloc(&instance));
+                        black_box(&slots[idx]);
+                    }
+                }
+            }
+            // 残りを解放
+            for handle in slots.into_iter().flatten() {
+                allocator.dealloc(&instance, black_box(handle));
+            }
+            allocator.teardown(instance);
+        })
+    }
+}
+
+/// 確保したスレッドとは別のスレッドで解放する（計測はBenchAllocator::remoteに任せる）
+pub struct Remote;
+
+impl Pattern for Remote {
+    fn run<A: BenchAllocator>(&mut self, clock: &Clock, allocator: &A) -> BenchTiming {
+        allocator.remote(clock)
+    }
+}
+
+// スラブやリースは所有スレッドでしか返せないので、スロット番号を解放側スレッドへ渡し、
+// 送り返されたスロットのハンドルを所有側で解放する（BenchAllocator::remoteの既定）
+pub(crate) fn remote_round_trip<A: BenchAllocator>(clock: &Clock, allocator: &A) -> BenchTiming {
+    let (tx, rx) = mpsc::sync_channel::<usize>(BATCH_SIZE);
+    let (back_tx, back_rx) = mpsc::sync_channel::<usize>(BATCH_SIZE);
+    std::thread::scope(move |s| {
+        // 解放側スレッド: 受け取ったスロット番号を所有側へ送り返す
+        s.spawn(move || {
+            for key in rx {
+                if back_tx.send(black_box(key)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let timing = measure(clock, || {
+            let instance = allocator.setup(BATCH_SIZE);
+            let mut handles = Vec::with_capacity(BATCH_SIZE);
+            for i in 0..BATCH_SIZE {
+                handles.push(Some(allocator.alloc(&instance)));
+                tx.send(i).unwrap();
+            }
+            for _ in 0..BATCH_SIZE {
+                let key = back_rx.recv().unwrap();
+                let handle = handles[key].take().expect("slot was sent back twice");
+                allocator.dealloc(&instance, black_box(handle));
+            }
+            drop(handles);
+            allocator.teardown(instance);
+        });
+
+        // 送信側を閉じて解放側スレッドを終了させる
+        drop(tx);
+        timing
+    })
+}
+
+/// 計測の外で作って温めたインスタンスから1つ取り出し、触って返すのをBATCH_SIZE回繰り返す（触るのはallocの中）
+/// 生成と最初の確保を含めず、空きリストから取り出して返す経路（bufpoolはleaseとDeref、Drop）だけを測る
+pub struct Hot;
+
+impl Pattern for Hot {
+    fn run<A: BenchAllocator>(&mut self, clock: &Clock, allocator: &A) -> BenchTiming {
+        let instance = allocator.setup(1);
+        // ウォームアップ: 1つ確保して返す
+        let handle = allocator.alloc(&instance);
+        allocator.dealloc(&instance, handle);
+
+        let timing = measure(clock, || {
+            for _ in 0..BATCH_SIZE {
+                let handle = allocator.alloc(&instance);
+                allocator.dealloc(&instance, black_box(handle));
+            }
+        });
+        allocator.teardown(instance);
+        timing
+    }
+}
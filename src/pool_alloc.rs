@@ -0,0 +1,149 @@
+use crate::bufpool::{
+    ArrayAllocator, BoxedSliceAllocator, Buffer, BufferAllocator, BufferPool, VecAllocator,
+};
+use crate::touch::touch;
+use crate::{dispatch_size, resolve_sizes};
+use log::info;
+use memalloc_results::stats::quantile;
+use quanta::Clock;
+use std::hint::black_box;
+
+#[derive(clap::Args, Debug)]
+pub struct PoolAllocArgs {
+    /// Comma-separated buffer sizes in bytes (the sizes the main benchmark supports)
+    #[arg(long, value_delimiter = ',', num_args = 1.., default_value = "64,1024,4096")]
+    sizes: Vec<usize>,
+
+    /// Buffers leased at once per iteration
+    #[arg(long, default_value_t = 100)]
+    batch: usize,
+
+    /// Iterations per cell
+    #[arg(long, default_value_t = 1000)]
+    iterations: usize,
+}
+
+// 比べる組み込みアロケータ
+#[derive(Clone, Copy, Debug)]
+enum Backing {
+    Vec,
+    BoxedSlice,
+    Array,
+}
+
+impl Backing {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Backing::Vec => "vec",
+            Backing::BoxedSlice => "boxed_slice",
+            Backing::Array => "array",
+        }
+    }
+
+    fn all() -> &'static [Backing] {
+        &[Backing::Vec, Backing::BoxedSlice, Backing::Array]
+    }
+}
+
+// 1セルの結果（どちらもイテレーションごとの所要時間、ソート済み）
+struct PoolAllocResult {
+    cold_ns: Vec<u64>, // 空のプールからbatch個借りる（すべて新しく確保する）
+    warm_ns: Vec<u64>, // reserve済みのプールからbatch個借りて返す
+}
+
+fn measure<A>(clock: &Clock, allocator: A, args: &PoolAllocArgs) -> PoolAllocResult
+where
+    A: BufferAllocator<Error = std::convert::Infallible> + Clone,
+    A::Buffer: Buffer,
+{
+    let mut cold = Vec::with_capacity(args.iterations);
+    let mut warm = Vec::with_capacity(args.iterations);
+
+    // 最初の1回はウォームアップとして捨てる
+    for i in 0..=args.iterations {
+        let pool = BufferPool::new(allocator.clone());
+        let mut leases = Vec::with_capacity(args.batch);
+        let start = clock.raw();
+        for _ in 0..args.batch {
+            let mut lease = pool.lease().unwrap();
+            touch(lease.as_mut_slice());
+            leases.push(lease);
+        }
+        let end = clock.raw();
+        drop(leases);
+        if i > 0 {
+            cold.push(clock.delta(start, end).as_nanos() as u64);
+        }
+    }
+
+    let pool = BufferPool::new(allocator);
+    let Ok(()) = pool.reserve(args.batch);
+    let mut leases = Vec::with_capacity(args.batch);
+    for i in 0..=args.iterations {
+        let start = clock.raw();
+        for _ in 0..args.batch {
+            let mut lease = pool.lease().unwrap();
+            touch(lease.as_mut_slice());
+            leases.push(lease);
+        }
+        // 逆順に返す
+        while let Some(lease) = leases.pop() {
+            drop(black_box(lease));
+        }
+        let end = clock.raw();
+        if i > 0 {
+            warm.push(clock.delta(start, end).as_nanos() as u64);
+        }
+    }
+
+    cold.sort_unstable();
+    warm.sort_unstable();
+    PoolAllocResult {
+        cold_ns: cold,
+        warm_ns: warm,
+    }
+}
+
+// サイズに対応するArrayAllocator<N>で測る（dispatch_size!で呼ぶ）
+fn measure_array<const N: usize>(clock: &Clock, args: &PoolAllocArgs) -> PoolAllocResult {
+    measure(clock, ArrayAllocator::<N>, args)
+}
+
+pub fn run(args: &PoolAllocArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let sizes = resolve_sizes(&args.sizes)?;
+    if args.batch == 0 || args.iterations == 0 {
+        return Err("--batch and --iterations must be greater than 0".into());
+    }
+    let clock = Clock::new();
+
+    info!(
+        "Lease {} buffers per iteration from a BufferPool with each built-in allocator ({} iterations)",
+        args.batch, args.iterations
+    );
+    println!(
+        "{:>6} {:<12} {:>16} {:>16} {:>16}",
+        "size", "allocator", "cold median(ns)", "warm median(ns)", "warm p99(ns)"
+    );
+
+    for &size in &sizes {
+        for &backing in Backing::all() {
+            let result = match backing {
+                Backing::Vec => measure(&clock, VecAllocator::new(size), args),
+                Backing::BoxedSlice => measure(&clock, BoxedSliceAllocator::new(size), args),
+                Backing::Array => dispatch_size!(size, measure_array(&clock, args)),
+            };
+            // 1リースあたりの時間にする
+            let batch = args.batch as f64;
+            println!(
+                "{:>6} {:<12} {:>16.2} {:>16.2} {:>16.2}",
+                size,
+                backing.as_str(),
+                quantile(&result.cold_ns, 0.5) / batch,
+                quantile(&result.warm_ns, 0.5) / batch,
+                quantile(&result.warm_ns, 0.99) / batch
+            );
+        }
+    }
+
+    Ok(())
+}
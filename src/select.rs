@@ -0,0 +1,263 @@
+use crate::{numa, topology, Allocator, Cell, Pattern};
+use clap::ValueEnum;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+// --only / --exclude で指定するセルの選択式
+// "allocator=slab_warm|box,pattern=random,size=64..512" のように、カンマ区切りの条件をすべて満たすセルにマッチする
+// 同じキーの中では | 区切りのいずれかにマッチすればよい
+// "id=box/immediate/64/1/none/any" のように、結果のcell_id列の値でセルを直接指定することもできる
+#[derive(Clone, Debug, Default)]
+pub struct Selector {
+    ids: Option<Vec<String>>,
+    allocators: Option<Vec<String>>,
+    patterns: Option<Vec<String>>,
+    sizes: Option<Vec<RangeInclusive<usize>>>,
+    threads: Option<Vec<RangeInclusive<usize>>>,
+    numa: Option<Vec<String>>,
+    smt: Option<Vec<String>>,
+}
+
+impl Selector {
+    pub fn matches(&self, cell: &Cell) -> bool {
+        self.ids.as_ref().is_none_or(|ids| ids.contains(&cell.id()))
+            && self
+                .allocators
+                .as_ref()
+                .is_none_or(|names| names.iter().any(|n| n == cell.allocator.as_str()))
+            && self
+                .patterns
+                .as_ref()
+                .is_none_or(|names| names.iter().any(|n| n == cell.pattern.as_str()))
+            && self
+                .sizes
+                .as_ref()
+                .is_none_or(|ranges| ranges.iter().any(|r| r.contains(&cell.size)))
+            && self
+                .threads
+                .as_ref()
+                .is_none_or(|ranges| ranges.iter().any(|r| r.contains(&cell.threads)))
+            && self
+                .numa
+                .as_ref()
+                .is_none_or(|names| names.iter().any(|n| n == cell.numa.as_str()))
+            && self
+                .smt
+                .as_ref()
+                .is_none_or(|names| names.iter().any(|n| n == cell.smt.as_str()))
+    }
+}
+
+impl FromStr for Selector {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut selector = Selector::default();
+        for term in s.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+            let (key, values) = term
+                .split_once('=')
+                .ok_or_else(|| format!("expected key=value, got '{}'", term))?;
+            let values: Vec<&str> = values.split('|').map(str::trim).collect();
+            match key.trim() {
+                "id" => {
+                    selector.ids = Some(
+                        values
+                            .iter()
+                            .map(|v| parse_id(v))
+                            .collect::<Result<_, _>>()?,
+                    );
+                }
+                "allocator" => {
+                    let known: Vec<&str> = Allocator::all().iter().map(|a| a.as_str()).collect();
+                    selector.allocators = Some(parse_names(&values, &known, "allocator")?);
+                }
+                "pattern" => {
+                    let known: Vec<&str> = Pattern::all().iter().map(|p| p.as_str()).collect();
+                    selector.patterns = Some(parse_names(&values, &known, "pattern")?);
+                }
+                "size" => {
+                    selector.sizes = Some(
+                        values
+                            .iter()
+                            .map(|v| parse_range(v))
+                            .collect::<Result<_, _>>()?,
+                    );
+                }
+                "numa" => {
+                    let known: Vec<&str> = numa::Placement::value_variants()
+                        .iter()
+                        .map(|p| p.as_str())
+                        .collect();
+                    selector.numa = Some(parse_names(&values, &known, "numa placement")?);
+                }
+                "smt" => {
+                    let known: Vec<&str> = topology::Smt::value_variants()
+                        .iter()
+                        .map(|p| p.as_str())
+                        .collect();
+                    selector.smt = Some(parse_names(&values, &known, "smt placement")?);
+                }
+                "threads" => {
+                    selector.threads = Some(
+                        values
+                            .iter()
+                            .map(|v| parse_range(v))
+                            .collect::<Result<_, _>>()?,
+                    );
+                }
+                other => {
+                    return Err(format!(
+                    "unknown key '{}' (expected id, allocator, pattern, size, threads, numa or smt)",
+                    other
+                ))
+                }
+            }
+        }
+        Ok(selector)
+    }
+}
+
+// "allocator/pattern/size/threads/numa/smt" の各要素を確かめる（知らない名前の打ち間違いで何も選ばれないのを防ぐ）
+fn parse_id(value: &str) -> Result<String, String> {
+    let parts: Vec<&str> = value.split('/').collect();
+    let [allocator, pattern, size, threads, numa, smt] = parts[..] else {
+        return Err(format!(
+            "invalid cell id '{}' (expected allocator/pattern/size/threads/numa/smt)",
+            value
+        ));
+    };
+    let known: Vec<&str> = Allocator::all().iter().map(|a| a.as_str()).collect();
+    parse_names(&[allocator], &known, "allocator")?;
+    let known: Vec<&str> = Pattern::all().iter().map(|p| p.as_str()).collect();
+    parse_names(&[pattern], &known, "pattern")?;
+    for n in [size, threads] {
+        n.parse::<usize>()
+            .map_err(|_| format!("invalid number '{}' in cell id '{}'", n, value))?;
+    }
+    let known: Vec<&str> = numa::Placement::value_variants()
+        .iter()
+        .map(|p| p.as_str())
+        .collect();
+    parse_names(&[numa], &known, "numa placement")?;
+    let known: Vec<&str> = topology::Smt::value_variants()
+        .iter()
+        .map(|p| p.as_str())
+        .collect();
+    parse_names(&[smt], &known, "smt placement")?;
+    Ok(value.to_string())
+}
+
+fn parse_names(values: &[&str], known: &[&str], kind: &str) -> Result<Vec<String>, String> {
+    values
+        .iter()
+        .map(|v| {
+            if known.contains(v) {
+                Ok(v.to_string())
+            } else {
+                Err(format!(
+                    "unknown {} '{}' (expected one of: {})",
+                    kind,
+                    v,
+                    known.join(", ")
+                ))
+            }
+        })
+        .collect()
+}
+
+// "64" / "64..512" / "..256" / "1024.." （両端を含む）
+fn parse_range(s: &str) -> Result<RangeInclusive<usize>, String> {
+    let parse = |v: &str| {
+        v.parse::<usize>()
+            .map_err(|_| format!("invalid number '{}' in '{}'", v, s))
+    };
+    match s.split_once("..") {
+        Some((lo, hi)) => {
+            let lo = if lo.is_empty() { 0 } else { parse(lo)? };
+            let hi = if hi.is_empty() {
+                usize::MAX
+            } else {
+                parse(hi)?
+            };
+            if lo > hi {
+                return Err(format!("empty range '{}'", s));
+            }
+            Ok(lo..=hi)
+        }
+        None => {
+            let size = parse(s)?;
+            Ok(size..=size)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cell(allocator: Allocator, size: usize, threads: usize) -> Cell {
+        Cell {
+            allocator,
+            pattern: Pattern::Immediate,
+            size,
+            threads,
+            numa: numa::Placement::None,
+            smt: topology::Smt::Any,
+        }
+    }
+
+    fn select(s: &str) -> Selector {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn open_ranges() {
+        assert_eq!(parse_range("..256"), Ok(0..=256));
+        assert_eq!(parse_range("1024.."), Ok(1024..=usize::MAX));
+        assert_eq!(parse_range("64..512"), Ok(64..=512));
+        assert_eq!(parse_range("64"), Ok(64..=64));
+        let upto = select("size=..256");
+        assert!(upto.matches(&cell(Allocator::Box, 256, 1)));
+        assert!(!upto.matches(&cell(Allocator::Box, 384, 1)));
+        assert!(select("size=1024..").matches(&cell(Allocator::Box, 4096, 1)));
+    }
+
+    #[test]
+    fn inverted_range_is_an_error() {
+        assert!(parse_range("512..64").unwrap_err().contains("empty range"));
+        assert!("size=512..64".parse::<Selector>().is_err());
+    }
+
+    #[test]
+    fn unknown_key_and_name_are_errors() {
+        let err = "color=red".parse::<Selector>().unwrap_err();
+        assert!(err.contains("unknown key 'color'"), "{}", err);
+        let err = "allocator=jemalloc".parse::<Selector>().unwrap_err();
+        assert!(err.contains("unknown allocator 'jemalloc'"), "{}", err);
+        assert!("size".parse::<Selector>().is_err());
+    }
+
+    #[test]
+    fn id_round_trips_cell_id() {
+        let target = cell(Allocator::SlabWarm, 64, 4);
+        let selector = select(&format!("id={}", target.id()));
+        assert!(selector.matches(&target));
+        assert!(!selector.matches(&cell(Allocator::SlabWarm, 64, 1)));
+        assert!("id=slab_warm/immediate/64".parse::<Selector>().is_err());
+        assert!("id=slab_warm/immediate/x/4/none/any"
+            .parse::<Selector>()
+            .is_err());
+    }
+
+    #[test]
+    fn keys_are_and_alternatives_are_or() {
+        let selector = select("allocator=box|slab_warm, size=64..128, threads=1");
+        assert!(selector.matches(&cell(Allocator::Box, 64, 1)));
+        assert!(selector.matches(&cell(Allocator::SlabWarm, 128, 1)));
+        assert!(!selector.matches(&cell(Allocator::SlabCold, 64, 1)));
+        assert!(!selector.matches(&cell(Allocator::Box, 256, 1)));
+        assert!(!selector.matches(&cell(Allocator::Box, 64, 2)));
+        // 空の選択式はすべてにマッチする
+        assert!(select("").matches(&cell(Allocator::SlabCold, 8, 16)));
+    }
+}
@@ -0,0 +1,379 @@
+use crate::bufpool::{
+    DeferredBufferPool, DeferredLease, LockFreeBufferPool, MutexBufferPool, SyncLease,
+};
+use crate::pattern::measure;
+use crate::touch::{touch, touched, Touch};
+use crate::treiber::TreiberPool;
+use crate::{
+    core_for, dispatch_size, pin_thread, Allocator, BenchTiming, Cell, Data, DataAllocator,
+    Pattern, BATCH_SIZE,
+};
+use quanta::Clock;
+use rand::{Rng, SeedableRng};
+use slab::Slab;
+use std::hint::black_box;
+use std::sync::{mpsc, Barrier, Mutex};
+
+// 全スレッドで1つのインスタンスを共有するアロケータ
+// (slab_mutex / sharded_slab / treiber_pool / bufpool_mutex / bufpool_lockfree / bufpool_deferred)
+// スレッドごとにスラブやプールを持つslab_warm / bufpool_warmと同じパターンで比べ、共有による競合のコストを測る
+
+// 複数スレッドから挿入・削除できるスラブ
+pub(crate) trait SharedSlab<T>: Sync {
+    fn insert(&self, value: T) -> usize;
+    // insertしてから、入れた値に触る（確保を省かせないため。touch.rsを参照）
+    fn insert_touched(&self, value: T) -> usize
+    where
+        T: Touch;
+    fn remove(&self, key: usize) -> T;
+}
+
+impl<T: Send> SharedSlab<T> for Mutex<Slab<T>> {
+    fn insert(&self, value: T) -> usize {
+        self.lock().unwrap().insert(value)
+    }
+
+    fn insert_touched(&self, value: T) -> usize
+    where
+        T: Touch,
+    {
+        let mut slab = self.lock().unwrap();
+        let key = slab.insert(value);
+        touch(&mut slab[key]);
+        key
+    }
+
+    fn remove(&self, key: usize) -> T {
+        self.lock().unwrap().remove(key)
+    }
+}
+
+impl<T: Send + Sync> SharedSlab<T> for sharded_slab::Slab<T> {
+    fn insert(&self, value: T) -> usize {
+        sharded_slab::Slab::insert(self, value).expect("sharded slab is full")
+    }
+
+    // 入れた値は&Tでしか参照できないので、入れる前の値に触る
+    fn insert_touched(&self, value: T) -> usize
+    where
+        T: Touch,
+    {
+        SharedSlab::insert(self, touched(value))
+    }
+
+    fn remove(&self, key: usize) -> T {
+        self.take(key)
+            .expect("sharded slab entry was already removed")
+    }
+}
+
+impl<T: Send> SharedSlab<T> for TreiberPool<T> {
+    fn insert(&self, value: T) -> usize {
+        TreiberPool::insert(self, value).expect("treiber pool is full")
+    }
+
+    fn insert_touched(&self, value: T) -> usize
+    where
+        T: Touch,
+    {
+        self.insert_with(value, |value| touch(value))
+            .expect("treiber pool is full")
+    }
+
+    fn remove(&self, key: usize) -> T {
+        // SAFETY: キーはinsertが返したもので、各キーは1度だけ削除する
+        unsafe { TreiberPool::remove(self, key) }
+    }
+}
+
+// 複数スレッドから確保・解放できるアロケータ
+pub(crate) trait SharedAllocator<T>: Sync {
+    // 確保したオブジェクトへのハンドル（スラブのキーやプールのリース）
+    type Handle<'a>: Send
+    where
+        Self: 'a;
+
+    fn alloc(&self) -> Self::Handle<'_>;
+    fn free(&self, handle: Self::Handle<'_>);
+}
+
+// スラブはキーをハンドルにする
+macro_rules! impl_shared_allocator_for_slab {
+    ($($slab:ty where $($bound:path),+);* $(;)?) => {
+        $(
+            impl<T: Default + Touch $(+ $bound)+> SharedAllocator<T> for $slab {
+                type Handle<'a>
+                    = usize
+                where
+                    Self: 'a;
+
+                fn alloc(&self) -> usize {
+                    SharedSlab::insert_touched(self, T::default())
+                }
+
+                fn free(&self, key: usize) {
+                    drop(black_box(SharedSlab::remove(self, key)));
+                }
+            }
+        )*
+    };
+}
+
+impl_shared_allocator_for_slab! {
+    Mutex<Slab<T>> where Send;
+    sharded_slab::Slab<T> where Send, Sync;
+    TreiberPool<T> where Send;
+}
+
+impl<T: Default + Send + Sync + Touch> SharedAllocator<T> for MutexBufferPool<DataAllocator<T>> {
+    type Handle<'a>
+        = SyncLease<'a, Self>
+    where
+        Self: 'a;
+
+    fn alloc(&self) -> Self::Handle<'_> {
+        let mut lease = self.lease().unwrap();
+        touch(&mut *lease);
+        lease
+    }
+
+    fn free(&self, lease: Self::Handle<'_>) {
+        drop(lease);
+    }
+}
+
+impl<T: Default + Send + Sync + Touch> SharedAllocator<T> for LockFreeBufferPool<DataAllocator<T>> {
+    type Handle<'a>
+        = SyncLease<'a, Self>
+    where
+        Self: 'a;
+
+    fn alloc(&self) -> Self::Handle<'_> {
+        let mut lease = self.lease().unwrap();
+        touch(&mut *lease);
+        lease
+    }
+
+    fn free(&self, lease: Self::Handle<'_>) {
+        drop(lease);
+    }
+}
+
+impl<T: Default + Send + Sync + Touch> SharedAllocator<T> for DeferredBufferPool<DataAllocator<T>> {
+    type Handle<'a>
+        = DeferredLease<'a, DataAllocator<T>>
+    where
+        Self: 'a;
+
+    fn alloc(&self) -> Self::Handle<'_> {
+        let mut lease = self.lease().unwrap();
+        touch(&mut *lease);
+        lease
+    }
+
+    fn free(&self, lease: Self::Handle<'_>) {
+        drop(lease);
+    }
+}
+
+fn bench<T, S: SharedAllocator<T>>(
+    clock: &Clock,
+    shared: &S,
+    pattern: Pattern,
+    rng: &mut rand::rngs::StdRng,
+) -> BenchTiming {
+    let mut handles = Vec::with_capacity(BATCH_SIZE);
+    let mut slots: Vec<Option<S::Handle<'_>>> = (0..BATCH_SIZE).map(|_| None).collect();
+    match pattern {
+        Pattern::Immediate => measure(clock, || {
+            for _ in 0..BATCH_SIZE {
+                let handle = shared.alloc();
+                shared.free(black_box(handle));
+            }
+        }),
+        Pattern::Lifo => measure(clock, || {
+            for _ in 0..BATCH_SIZE {
+                handles.push(shared.alloc());
+            }
+            while let Some(handle) = handles.pop() {
+                shared.free(black_box(handle));
+            }
+        }),
+        Pattern::Fifo => measure(clock, || {
+            for _ in 0..BATCH_SIZE {
+                handles.push(shared.alloc());
+            }
+            for handle in handles.drain(..) {
+                shared.free(black_box(handle));
+            }
+        }),
+        Pattern::Random => measure(clock, || {
+            for _ in 0..(BATCH_SIZE * 2) {
+                let idx = rng.gen_range(0..BATCH_SIZE);
+                match slots[idx].take() {
+                    Some(handle) => shared.free(black_box(handle)),
+                    None => slots[idx] = Some(black_box(shared.alloc())),
+                }
+            }
+            // 残りを解放
+            for handle in slots.iter_mut().filter_map(Option::take) {
+                shared.free(black_box(handle));
+            }
+        }),
+        // 共有しているので、解放側スレッドがハンドルを受け取ってそのまま解放できる
+        Pattern::Remote => {
+            let (tx, rx) = mpsc::sync_channel::<Option<S::Handle<'_>>>(BATCH_SIZE);
+            let (done_tx, done_rx) = mpsc::sync_channel::<()>(1);
+            std::thread::scope(|s| {
+                // 解放側スレッド: Noneでバッチの終わりを受け取り、完了を通知する
+                s.spawn(move || {
+                    for msg in rx {
+                        match msg {
+                            Some(handle) => shared.free(black_box(handle)),
+                            None => {
+                                if done_tx.send(()).is_err() {
+                                    break;
+                                }
+                            }
+                        }
+                    }
+                });
+
+                let timing = measure(clock, || {
+                    for _ in 0..BATCH_SIZE {
+                        tx.send(Some(shared.alloc())).unwrap();
+                    }
+                    tx.send(None).unwrap();
+                    done_rx.recv().unwrap();
+                });
+
+                // 送信側を閉じて解放側スレッドを終了させる
+                drop(tx);
+                timing
+            })
+        }
+        Pattern::Hot => unreachable!("shared allocators do not support the hot pattern"),
+    }
+}
+
+// run_parallelと同じくスレッドを固定してバリアで開始を揃えるが、スラブは全スレッドで共有する
+// スラブはセルの最初に1度だけ作るので、2回目以降のイテレーションは確保済みの領域を再利用する
+fn run_threads<T, S: SharedAllocator<T>>(
+    clock: &Clock,
+    cell: Cell,
+    core_ids: &[core_affinity::CoreId],
+    mem_node: Option<usize>,
+    seed: u64,
+    iterations: u32,
+    shared: &S,
+) -> Vec<Vec<BenchTiming>> {
+    let barrier = Barrier::new(cell.threads);
+    std::thread::scope(|s| {
+        let handles: Vec<_> = (0..cell.threads)
+            .map(|t| {
+                let barrier = &barrier;
+                let clock = clock.clone();
+                let core = core_for(core_ids, t);
+                s.spawn(move || {
+                    pin_thread(t, core, mem_node);
+                    let mut rng = rand::rngs::StdRng::seed_from_u64(seed.wrapping_add(t as u64));
+                    (0..iterations)
+                        .map(|_| {
+                            barrier.wait();
+                            bench::<T, S>(&clock, shared, cell.pattern, &mut rng)
+                        })
+                        .collect()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|h| h.join().expect("benchmark thread panicked"))
+            .collect()
+    })
+}
+
+fn run_with<const N: usize>(
+    clock: &Clock,
+    cell: Cell,
+    core_ids: &[core_affinity::CoreId],
+    mem_node: Option<usize>,
+    seed: u64,
+    iterations: u32,
+) -> Vec<Vec<BenchTiming>> {
+    match cell.allocator {
+        Allocator::SlabMutex => run_threads::<Data<N>, _>(
+            clock,
+            cell,
+            core_ids,
+            mem_node,
+            seed,
+            iterations,
+            &Mutex::new(Slab::new()),
+        ),
+        Allocator::ShardedSlab => run_threads::<Data<N>, _>(
+            clock,
+            cell,
+            core_ids,
+            mem_node,
+            seed,
+            iterations,
+            &sharded_slab::Slab::new(),
+        ),
+        // 同時に確保したままになるのは1スレッドあたり最大BATCH_SIZE個
+        Allocator::TreiberPool => run_threads::<Data<N>, _>(
+            clock,
+            cell,
+            core_ids,
+            mem_node,
+            seed,
+            iterations,
+            &TreiberPool::with_capacity(cell.threads * BATCH_SIZE),
+        ),
+        Allocator::BufpoolMutex => run_threads::<Data<N>, _>(
+            clock,
+            cell,
+            core_ids,
+            mem_node,
+            seed,
+            iterations,
+            &MutexBufferPool::new(DataAllocator::<Data<N>>::new()),
+        ),
+        // 空きバッファはスレッドごとのバッチ分だけ保持すれば足りる
+        Allocator::BufpoolLockfree => run_threads::<Data<N>, _>(
+            clock,
+            cell,
+            core_ids,
+            mem_node,
+            seed,
+            iterations,
+            &LockFreeBufferPool::new(DataAllocator::<Data<N>>::new(), cell.threads * BATCH_SIZE),
+        ),
+        // シャードは計測スレッドごとに1つ（remoteパターンの解放側スレッドは持ち主のキューへ返す）
+        Allocator::BufpoolDeferred => run_threads::<Data<N>, _>(
+            clock,
+            cell,
+            core_ids,
+            mem_node,
+            seed,
+            iterations,
+            &DeferredBufferPool::new(DataAllocator::<Data<N>>::new(), cell.threads),
+        ),
+        other => unreachable!("{} is not a shared allocator", other.as_str()),
+    }
+}
+
+// 共有アロケータのセルを実行する（1スレッドでも別スレッドで実行する）
+pub fn run_shared(
+    clock: &Clock,
+    cell: Cell,
+    core_ids: &[core_affinity::CoreId],
+    mem_node: Option<usize>,
+    seed: u64,
+    iterations: u32,
+) -> Vec<Vec<BenchTiming>> {
+    dispatch_size!(
+        cell.size,
+        run_with(clock, cell, core_ids, mem_node, seed, iterations)
+    )
+}
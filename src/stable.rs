@@ -0,0 +1,81 @@
+// --until-stable: 決まった回数ではなく、中央値の95%信頼区間が目標の幅に収まるまでセルを計測し直す
+// ばらつきの小さいセルは少ない回数で切り上げ、大きいセルには上限まで回数を足す
+// 1回目はbudget::MIN_ITERATIONS回、以降はそれまでと同じ回数を足して倍々に増やす（スレッドの起動やインスタンスの生成を減らすため）
+
+use crate::budget::MIN_ITERATIONS;
+use crate::BenchTiming;
+use log::{debug, info};
+use memalloc_results::stats::Summary;
+
+/// --until-stableの目標の幅を記録するparquetのメタデータのキー
+pub const UNTIL_STABLE_KEY: &str = "memalloc_bench.until_stable";
+
+/// 目標とする中央値の信頼区間の相対幅と、1セルあたりのイテレーション数の上限
+#[derive(Clone, Copy, Debug)]
+pub struct UntilStable {
+    pub target: f64,
+    pub max_iterations: u32,
+}
+
+impl UntilStable {
+    /// runにイテレーション数を渡してスレッドごとの計測値を受け取り、安定するか上限に達するまで繰り返す
+    pub fn measure(
+        &self,
+        mut run: impl FnMut(u32) -> Vec<Vec<BenchTiming>>,
+    ) -> Vec<Vec<BenchTiming>> {
+        let mut iterations = MIN_ITERATIONS.min(self.max_iterations);
+        let mut timings = run(iterations);
+        loop {
+            let width = relative_width(&timings);
+            debug!(
+                "  {} iterations: median 95% CI width {:.2}%",
+                iterations,
+                width * 100.0
+            );
+            if width <= self.target || iterations >= self.max_iterations {
+                return timings;
+            }
+            let more = iterations.min(self.max_iterations - iterations);
+            for (all, round) in timings.iter_mut().zip(run(more)) {
+                all.extend(round);
+            }
+            iterations += more;
+        }
+    }
+
+    /// measureで計測し終えたセルの回数と信頼区間の幅を表示する（--jobsでも書き出す順に表示するため、measureとは分ける）
+    pub fn log_result(&self, timings: &[Vec<BenchTiming>]) {
+        let width = relative_width(timings);
+        let verdict = if width <= self.target {
+            "stable"
+        } else {
+            "not stable, reached --max-iterations"
+        };
+        info!(
+            "  {} after {} iterations (median 95% CI width {:.2}%)",
+            verdict,
+            timings.first().map_or(0, Vec::len),
+            width * 100.0
+        );
+    }
+}
+
+// 全スレッドの計測値をまとめたときの、中央値に対する信頼区間の幅
+fn relative_width(timings: &[Vec<BenchTiming>]) -> f64 {
+    let mut totals: Vec<u64> = timings.iter().flatten().map(|t| t.total_ns).collect();
+    totals.sort_unstable();
+    Summary::of(&totals).median_ci_width()
+}
+
+// "0.02" / "2%" -> 0.02
+pub fn parse_width(s: &str) -> Result<f64, String> {
+    let width = match s.strip_suffix('%') {
+        Some(percent) => percent.parse::<f64>().map(|p| p / 100.0),
+        None => s.parse::<f64>(),
+    }
+    .map_err(|_| format!("invalid width: {} (e.g. 0.02 or 2%)", s))?;
+    if !(width > 0.0 && width < 1.0) {
+        return Err("width must be between 0 and 1 (exclusive)".to_string());
+    }
+    Ok(width)
+}
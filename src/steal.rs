@@ -0,0 +1,232 @@
+use crate::shared::SharedSlab;
+use crate::touch::touched;
+use crate::{
+    core_for, dispatch_size, expand_thread_counts, parse_thread_counts, pin_thread, resolve_sizes,
+    Data, ThreadCounts, BATCH_SIZE,
+};
+use crossbeam_channel::{Receiver, Sender};
+use log::{info, warn};
+use memalloc_results::stats::quantile;
+use quanta::Clock;
+use rand::{Rng, SeedableRng};
+use slab::Slab;
+use std::hint::black_box;
+use std::sync::{Barrier, Mutex};
+
+#[derive(clap::Args, Debug)]
+pub struct StealArgs {
+    /// Comma-separated data sizes in bytes (the sizes the main benchmark supports)
+    #[arg(long, value_delimiter = ',', num_args = 1.., default_value = "64,1024,4096")]
+    sizes: Vec<usize>,
+
+    /// Comma-separated thread counts ("max", "1..8" or "sweep" as in the main benchmark)
+    #[arg(long, value_delimiter = ',', num_args = 1.., default_value = "2,4", value_parser = parse_thread_counts)]
+    threads: Vec<ThreadCounts>,
+
+    /// Rounds per cell. Each thread allocates a batch of objects per round
+    #[arg(long, default_value_t = 10_000)]
+    rounds: usize,
+
+    /// Seed for choosing the freeing thread
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+}
+
+// どのスレッドからでも解放できるアロケータ（スレッドごとのslab / bufpoolは所有スレッドでしか解放できない）
+#[derive(Clone, Copy, Debug)]
+enum StealAllocator {
+    Box,
+    SlabMutex,   // 全スレッドで共有するMutex<Slab>
+    ShardedSlab, // 全スレッドで共有するsharded_slab::Slab
+}
+
+impl StealAllocator {
+    fn as_str(&self) -> &'static str {
+        match self {
+            StealAllocator::Box => "box",
+            StealAllocator::SlabMutex => "slab_mutex",
+            StealAllocator::ShardedSlab => "sharded_slab",
+        }
+    }
+
+    fn all() -> &'static [StealAllocator] {
+        &[
+            StealAllocator::Box,
+            StealAllocator::SlabMutex,
+            StealAllocator::ShardedSlab,
+        ]
+    }
+}
+
+// 1セルの結果
+struct StealResult {
+    rounds_ns: Vec<u64>, // ラウンドごとの所要時間（ソート済み）
+    remote_ratio: f64,   // 確保したスレッド以外で解放したオブジェクトの割合
+}
+
+// 1ラウンド: 各スレッドがBATCH_SIZE個確保し、1個ずつ一様乱数で選んだスレッド（自分を含む）の受信箱へ送る
+// 全スレッドが送り終えたら、それぞれ自分の受信箱に届いたものを解放する
+// ラウンドの所要時間はバリアで揃えた開始から全スレッドの解放完了までで、スレッド0が計る
+fn run_threads<P: Send>(
+    clock: &Clock,
+    threads: usize,
+    core_ids: &[core_affinity::CoreId],
+    args: &StealArgs,
+    alloc: impl Fn() -> P + Sync,
+    free: impl Fn(P) + Sync,
+) -> StealResult {
+    let (senders, receivers): (Vec<Sender<P>>, Vec<Receiver<P>>) =
+        (0..threads).map(|_| crossbeam_channel::unbounded()).unzip();
+    let barrier = Barrier::new(threads);
+    let per_thread: Vec<(Vec<u64>, usize)> = std::thread::scope(|s| {
+        let handles: Vec<_> = receivers
+            .into_iter()
+            .enumerate()
+            .map(|(t, inbox)| {
+                let (senders, barrier, alloc, free) = (&senders, &barrier, &alloc, &free);
+                let clock = clock.clone();
+                let core = core_for(core_ids, t);
+                s.spawn(move || {
+                    pin_thread(t, core, None);
+                    let mut rng =
+                        rand::rngs::StdRng::seed_from_u64(args.seed.wrapping_add(t as u64));
+                    let mut samples = Vec::with_capacity(if t == 0 { args.rounds } else { 0 });
+                    let mut remote = 0;
+                    for _ in 0..args.rounds {
+                        barrier.wait();
+                        let start = clock.raw();
+                        for _ in 0..BATCH_SIZE {
+                            let target = rng.gen_range(0..threads);
+                            if target != t {
+                                remote += 1;
+                            }
+                            senders[target].send(alloc()).unwrap();
+                        }
+                        barrier.wait();
+                        for obj in inbox.try_iter() {
+                            free(black_box(obj));
+                        }
+                        barrier.wait();
+                        let end = clock.raw();
+                        if t == 0 {
+                            samples.push(clock.delta(start, end).as_nanos() as u64);
+                        }
+                    }
+                    (samples, remote)
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|h| h.join().expect("benchmark thread panicked"))
+            .collect()
+    });
+
+    let remote: usize = per_thread.iter().map(|(_, r)| r).sum();
+    let mut rounds_ns = per_thread
+        .into_iter()
+        .next()
+        .map(|(s, _)| s)
+        .unwrap_or_default();
+    rounds_ns.sort_unstable();
+    StealResult {
+        rounds_ns,
+        remote_ratio: remote as f64 / (threads * args.rounds * BATCH_SIZE) as f64,
+    }
+}
+
+fn run_size<const N: usize>(
+    allocator: StealAllocator,
+    clock: &Clock,
+    threads: usize,
+    core_ids: &[core_affinity::CoreId],
+    args: &StealArgs,
+) -> StealResult {
+    match allocator {
+        StealAllocator::Box => run_threads(
+            clock,
+            threads,
+            core_ids,
+            args,
+            || touched(Box::new(Data::<N>::default())),
+            drop,
+        ),
+        StealAllocator::SlabMutex => {
+            let slab = Mutex::new(Slab::<Data<N>>::new());
+            run_threads(
+                clock,
+                threads,
+                core_ids,
+                args,
+                || SharedSlab::insert_touched(&slab, Data::default()),
+                |key| {
+                    let _ = SharedSlab::remove(&slab, key);
+                },
+            )
+        }
+        StealAllocator::ShardedSlab => {
+            let slab = sharded_slab::Slab::<Data<N>>::new();
+            run_threads(
+                clock,
+                threads,
+                core_ids,
+                args,
+                || SharedSlab::insert_touched(&slab, Data::default()),
+                |key| {
+                    let _ = SharedSlab::remove(&slab, key);
+                },
+            )
+        }
+    }
+}
+
+pub fn run(args: &StealArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let sizes = resolve_sizes(&args.sizes)?;
+    if args.rounds == 0 {
+        return Err("--rounds must be greater than 0".into());
+    }
+    let clock = Clock::new();
+    let core_ids = core_affinity::get_core_ids().unwrap_or_default();
+    let thread_counts = expand_thread_counts(&args.threads);
+    let max_threads = thread_counts.iter().copied().max().unwrap_or(1);
+    if core_ids.len() < max_threads {
+        warn!(
+            "Only {} cores available for up to {} threads; threads will share cores",
+            core_ids.len(),
+            max_threads
+        );
+    }
+
+    info!(
+        "Each thread allocates {} objects per round and frees them on random threads ({} rounds)",
+        BATCH_SIZE, args.rounds
+    );
+    println!(
+        "{:>6} {:>7} {:<14} {:>7} {:>14} {:>14} {:>12}",
+        "size", "threads", "allocator", "remote", "median(ns/op)", "p99(ns/op)", "Mops/s"
+    );
+
+    for &size in &sizes {
+        for &threads in &thread_counts {
+            // 1ラウンドの操作数 = 全スレッドの alloc + dealloc
+            let ops = (threads * BATCH_SIZE * 2) as f64;
+            for &allocator in StealAllocator::all() {
+                let result =
+                    dispatch_size!(size, run_size(allocator, &clock, threads, &core_ids, args));
+                let median = quantile(&result.rounds_ns, 0.5);
+                println!(
+                    "{:>6} {:>7} {:<14} {:>6.1}% {:>14.2} {:>14.2} {:>12.2}",
+                    size,
+                    threads,
+                    allocator.as_str(),
+                    result.remote_ratio * 100.0,
+                    median / ops,
+                    quantile(&result.rounds_ns, 0.99) / ops,
+                    ops / median * 1e3
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,190 @@
+use crate::shared::SharedSlab;
+use crate::touch::{touch, touched, Touch};
+use crate::{bufpool, dispatch_size, resolve_sizes, Data, DataAllocator, BATCH_SIZE};
+use log::info;
+use memalloc_results::stats::quantile;
+use quanta::Clock;
+use slab::Slab;
+use std::hint::black_box;
+use std::sync::{Arc, Mutex};
+
+// 1バッチ = BATCH_SIZE回の alloc + dealloc
+const OPS_PER_BATCH: usize = BATCH_SIZE * 2;
+
+#[derive(clap::Args, Debug)]
+pub struct TokioArgs {
+    /// Comma-separated data sizes in bytes (the sizes the main benchmark supports)
+    #[arg(long, value_delimiter = ',', num_args = 1.., default_value = "64,1024,4096")]
+    sizes: Vec<usize>,
+
+    /// Worker threads of the multi-threaded runtime (default: number of cores)
+    #[arg(long)]
+    workers: Option<usize>,
+
+    /// Number of tasks spawned concurrently
+    #[arg(long, default_value_t = 64)]
+    tasks: usize,
+
+    /// Batches per task. Each batch allocates and frees a batch of objects, then yields
+    #[arg(long, default_value_t = 1000)]
+    batches: usize,
+}
+
+// タスク内から使うアロケータ
+#[derive(Clone, Copy, Debug)]
+enum TaskAllocator {
+    Box,
+    SlabMutex,   // 全タスクで共有するMutex<Slab>
+    ShardedSlab, // 全タスクで共有するsharded_slab::Slab
+    Bufpool,     // タスクごとのBufferPool（リースは.awaitをまたいで保持できない）
+}
+
+impl TaskAllocator {
+    fn as_str(&self) -> &'static str {
+        match self {
+            TaskAllocator::Box => "box",
+            TaskAllocator::SlabMutex => "slab_mutex",
+            TaskAllocator::ShardedSlab => "sharded_slab",
+            TaskAllocator::Bufpool => "bufpool_warm",
+        }
+    }
+
+    fn all() -> &'static [TaskAllocator] {
+        &[
+            TaskAllocator::Box,
+            TaskAllocator::SlabMutex,
+            TaskAllocator::ShardedSlab,
+            TaskAllocator::Bufpool,
+        ]
+    }
+}
+
+// LIFO: BATCH_SIZE個確保して逆順に解放する
+fn lifo_shared<T: Default, S: SharedSlab<T>>(slab: &S, keys: &mut Vec<usize>) {
+    for _ in 0..BATCH_SIZE {
+        keys.push(slab.insert(T::default()));
+    }
+    while let Some(key) = keys.pop() {
+        drop(black_box(slab.remove(key)));
+    }
+}
+
+// 1タスク分: バッチごとの所要時間を計り、バッチの間でyieldして他のタスクに譲る
+async fn run_task<T: Default + Send + Sync + Touch + 'static>(
+    allocator: TaskAllocator,
+    slab_mutex: Arc<Mutex<Slab<T>>>,
+    sharded: Arc<sharded_slab::Slab<T>>,
+    clock: Clock,
+    batches: usize,
+) -> Vec<u64> {
+    let pool = bufpool::BufferPool::new(DataAllocator::<T>::new());
+    let mut keys = Vec::with_capacity(BATCH_SIZE);
+    let mut samples = Vec::with_capacity(batches);
+    for _ in 0..batches {
+        let start = clock.raw();
+        match allocator {
+            TaskAllocator::Box => {
+                let mut boxes = Vec::with_capacity(BATCH_SIZE);
+                for _ in 0..BATCH_SIZE {
+                    boxes.push(touched(Box::new(T::default())));
+                }
+                while let Some(b) = boxes.pop() {
+                    drop(black_box(b));
+                }
+            }
+            TaskAllocator::SlabMutex => lifo_shared(&*slab_mutex, &mut keys),
+            TaskAllocator::ShardedSlab => lifo_shared(&*sharded, &mut keys),
+            TaskAllocator::Bufpool => {
+                let mut leases = Vec::with_capacity(BATCH_SIZE);
+                for _ in 0..BATCH_SIZE {
+                    let mut lease = pool.lease().unwrap();
+                    touch(&mut *lease);
+                    leases.push(lease);
+                }
+                while let Some(lease) = leases.pop() {
+                    drop(black_box(lease));
+                }
+            }
+        }
+        let end = clock.raw();
+        samples.push(clock.delta(start, end).as_nanos() as u64);
+        tokio::task::yield_now().await;
+    }
+    samples
+}
+
+// 全タスクを同時に起動し、(ソート済みのバッチ所要時間, 全体の所要時間 (ns)) を返す
+fn run_size<const N: usize>(
+    runtime: &tokio::runtime::Runtime,
+    allocator: TaskAllocator,
+    clock: &Clock,
+    args: &TokioArgs,
+) -> Result<(Vec<u64>, u64), Box<dyn std::error::Error>> {
+    let slab_mutex = Arc::new(Mutex::new(Slab::<Data<N>>::new()));
+    let sharded = Arc::new(sharded_slab::Slab::<Data<N>>::new());
+    runtime.block_on(async {
+        let start = clock.raw();
+        let handles: Vec<_> = (0..args.tasks)
+            .map(|_| {
+                tokio::spawn(run_task(
+                    allocator,
+                    slab_mutex.clone(),
+                    sharded.clone(),
+                    clock.clone(),
+                    args.batches,
+                ))
+            })
+            .collect();
+        let mut samples = Vec::with_capacity(args.tasks * args.batches);
+        for handle in handles {
+            samples.extend(handle.await?);
+        }
+        let end = clock.raw();
+        samples.sort_unstable();
+        Ok((samples, clock.delta(start, end).as_nanos() as u64))
+    })
+}
+
+pub fn run(args: &TokioArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let sizes = resolve_sizes(&args.sizes)?;
+    if args.tasks == 0 || args.batches == 0 {
+        return Err("--tasks and --batches must be greater than 0".into());
+    }
+
+    let mut builder = tokio::runtime::Builder::new_multi_thread();
+    if let Some(workers) = args.workers {
+        builder.worker_threads(workers);
+    }
+    let runtime = builder.build()?;
+    let clock = Clock::new();
+
+    info!(
+        "Tokio runtime: {} workers, {} tasks x {} batches of {} objects",
+        runtime.metrics().num_workers(),
+        args.tasks,
+        args.batches,
+        BATCH_SIZE
+    );
+    println!(
+        "{:>6} {:<14} {:>14} {:>14} {:>12}",
+        "size", "allocator", "median(ns/op)", "p99(ns/op)", "Mops/s"
+    );
+
+    for &size in &sizes {
+        for &allocator in TaskAllocator::all() {
+            let (samples, wall_ns) =
+                dispatch_size!(size, run_size(&runtime, allocator, &clock, args))?;
+            let total_ops = (args.tasks * args.batches * OPS_PER_BATCH) as f64;
+            println!(
+                "{:>6} {:<14} {:>14.2} {:>14.2} {:>12.2}",
+                size,
+                allocator.as_str(),
+                quantile(&samples, 0.5) / OPS_PER_BATCH as f64,
+                quantile(&samples, 0.99) / OPS_PER_BATCH as f64,
+                total_ops / wall_ns as f64 * 1e3
+            );
+        }
+    }
+
+    Ok(())
+}
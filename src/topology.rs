@@ -0,0 +1,161 @@
+use crate::numa::parse_cpulist;
+use core_affinity::CoreId;
+use std::collections::{BTreeMap, BTreeSet};
+
+// 複数スレッドのセルでのSMT (ハイパースレッディング) の使い方
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Smt {
+    /// Pin threads to cores in enumeration order
+    Any,
+    /// Pin threads to hyperthread siblings of the same physical core first
+    Siblings,
+    /// Pin each thread to a different physical core
+    Separate,
+}
+
+impl Smt {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Smt::Any => "any",
+            Smt::Siblings => "siblings",
+            Smt::Separate => "separate",
+        }
+    }
+}
+
+// ハイブリッドCPU (ARMのbig.LITTLE、IntelのPコア/Eコア) のコアの種類
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CoreType {
+    /// Performance (big / P) cores
+    Performance,
+    /// Efficiency (LITTLE / E) cores
+    Efficiency,
+}
+
+impl CoreType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CoreType::Performance => "performance",
+            CoreType::Efficiency => "efficiency",
+        }
+    }
+}
+
+// 論理CPUごとのコアの種類（ハイブリッドCPUでなければ空）
+// Intelは/sys/devices/cpu_core, cpu_atomのCPU一覧、ARMはcpu_capacityが最大のものを性能コアとみなす
+pub fn core_types() -> BTreeMap<usize, CoreType> {
+    let read_cpus = |path: &str| {
+        std::fs::read_to_string(path)
+            .ok()
+            .and_then(|s| parse_cpulist(&s))
+            .unwrap_or_default()
+    };
+    let (p_cores, e_cores) = (
+        read_cpus("/sys/devices/cpu_core/cpus"),
+        read_cpus("/sys/devices/cpu_atom/cpus"),
+    );
+    if !p_cores.is_empty() && !e_cores.is_empty() {
+        let p = p_cores.into_iter().map(|id| (id, CoreType::Performance));
+        let e = e_cores.into_iter().map(|id| (id, CoreType::Efficiency));
+        return p.chain(e).collect();
+    }
+
+    let Ok(entries) = std::fs::read_dir("/sys/devices/system/cpu") else {
+        return BTreeMap::new();
+    };
+    let capacities: BTreeMap<usize, u32> = entries
+        .filter_map(|e| e.ok())
+        .filter_map(|e| {
+            let name = e.file_name();
+            let id = name.to_str()?.strip_prefix("cpu")?.parse::<usize>().ok()?;
+            let capacity = std::fs::read_to_string(e.path().join("cpu_capacity")).ok()?;
+            Some((id, capacity.trim().parse().ok()?))
+        })
+        .collect();
+    let max = capacities.values().copied().max();
+    if max.is_none() || capacities.values().all(|&c| Some(c) == max) {
+        return BTreeMap::new();
+    }
+    capacities
+        .into_iter()
+        .map(|(id, capacity)| {
+            let ty = if Some(capacity) == max {
+                CoreType::Performance
+            } else {
+                CoreType::Efficiency
+            };
+            (id, ty)
+        })
+        .collect()
+}
+
+// 指定した種類のコアだけを残す
+pub fn filter_core_type(
+    cores: &[CoreId],
+    ty: CoreType,
+    types: &BTreeMap<usize, CoreType>,
+) -> Vec<CoreId> {
+    cores
+        .iter()
+        .copied()
+        .filter(|c| types.get(&c.id) == Some(&ty))
+        .collect()
+}
+
+// 物理コアごとの論理CPUの一覧（/sys/devices/system/cpu/cpu*/topology/thread_siblings_list、Linux以外では空）
+pub fn physical_cores() -> Vec<Vec<usize>> {
+    let Ok(entries) = std::fs::read_dir("/sys/devices/system/cpu") else {
+        return Vec::new();
+    };
+    let groups: BTreeSet<Vec<usize>> = entries
+        .filter_map(|e| e.ok())
+        .filter_map(|e| {
+            let name = e.file_name();
+            name.to_str()?.strip_prefix("cpu")?.parse::<usize>().ok()?;
+            let siblings =
+                std::fs::read_to_string(e.path().join("topology/thread_siblings_list")).ok()?;
+            parse_cpulist(&siblings)
+        })
+        .filter(|cpus| !cpus.is_empty())
+        .collect();
+    groups.into_iter().collect()
+}
+
+// 物理コアあたりの最大論理CPU数（トポロジが取れなければ1）
+pub fn threads_per_core(physical: &[Vec<usize>]) -> usize {
+    physical.iter().map(|cpus| cpus.len()).max().unwrap_or(1)
+}
+
+// 固定先のコアをSMTの使い方に合わせて並べ替える
+// siblings: 同じ物理コアの論理CPUを続けて並べる（2スレッドなら同じ物理コアに載る）
+// separate: 物理コアごとに1つの論理CPUだけを使う
+pub fn arrange(cores: &[CoreId], smt: Smt, physical: &[Vec<usize>]) -> Result<Vec<CoreId>, String> {
+    // 候補のコアだけに絞った物理コアごとのグループ
+    let groups: Vec<Vec<CoreId>> = physical
+        .iter()
+        .map(|cpus| {
+            cpus.iter()
+                .filter(|&&id| cores.iter().any(|c| c.id == id))
+                .map(|&id| CoreId { id })
+                .collect::<Vec<_>>()
+        })
+        .filter(|group| !group.is_empty())
+        .collect();
+
+    let arranged: Vec<CoreId> = match smt {
+        Smt::Any => return Ok(cores.to_vec()),
+        Smt::Siblings => groups
+            .into_iter()
+            .filter(|group| group.len() > 1)
+            .flatten()
+            .collect(),
+        Smt::Separate => groups.into_iter().map(|group| group[0]).collect(),
+    };
+    if arranged.is_empty() {
+        return Err(match smt {
+            Smt::Siblings => "--smt siblings requires SMT (hyperthreading) to be enabled".into(),
+            _ => "--smt separate: CPU topology is not available on this platform".into(),
+        });
+    }
+    Ok(arranged)
+}
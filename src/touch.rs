@@ -0,0 +1,100 @@
+// 確保したオブジェクトに必ず触れるための補助
+// black_box(Box::new(..))だけでは、opt-level 3のLLVMが中身を使わない確保と解放の組を丸ごと消すことがあり、
+// 結果が何も言わずに速くなる。touchは確保した領域にvolatileで書き込み、アドレスをasmに渡すので、確保を消せない
+// 計測のバックエンドはallocで、サブコマンドは確保の直後に、確保したオブジェクトをtouchに渡す
+
+use std::mem::MaybeUninit;
+
+// 書き込む値（0以外にしておくと、ゼロ埋めされたページのままかどうかをデバッガで見分けられる）
+const TOUCH_BYTE: u8 = 0xA5;
+
+/// touchで書き込める型
+///
+/// # Safety
+/// touch_ptrは、selfの中のどんな値を書いても型として壊れない1バイトを指すポインタ
+/// （大きさ0の型ならnull）を返す
+pub unsafe trait Touch {
+    fn touch_ptr(&mut self) -> *mut u8;
+}
+
+// SAFETY: MaybeUninit<u8>にはどんな値を書いてもよい
+unsafe impl<const N: usize> Touch for crate::Data<N> {
+    #[inline(always)]
+    fn touch_ptr(&mut self) -> *mut u8 {
+        self._data.as_mut_ptr().cast()
+    }
+}
+
+// SAFETY: u8にはどんな値を書いてもよい
+unsafe impl Touch for [u8] {
+    #[inline(always)]
+    fn touch_ptr(&mut self) -> *mut u8 {
+        self.as_mut_ptr()
+    }
+}
+
+// SAFETY: MaybeUninit<u8>にはどんな値を書いてもよい
+unsafe impl Touch for [MaybeUninit<u8>] {
+    #[inline(always)]
+    fn touch_ptr(&mut self) -> *mut u8 {
+        self.as_mut_ptr().cast()
+    }
+}
+
+// SAFETY: 中身の約束をそのまま引き継ぐ
+unsafe impl<T: Touch + ?Sized> Touch for Box<T> {
+    #[inline(always)]
+    fn touch_ptr(&mut self) -> *mut u8 {
+        (**self).touch_ptr()
+    }
+}
+
+/// valueの先頭の1バイトにvolatileで書き込み、そのアドレスをsinkに渡す
+/// 確保したばかりのオブジェクトに呼ぶと、コンパイラはその確保を省けなくなる
+#[inline(always)]
+pub fn touch<T: Touch + ?Sized>(value: &mut T) {
+    let ptr = value.touch_ptr();
+    if !ptr.is_null() {
+        // SAFETY: Touchの約束により、ptrは何を書いてもよいself内の1バイトを指す
+        unsafe { ptr.write_volatile(TOUCH_BYTE) };
+    }
+    sink(ptr);
+}
+
+/// valueに触ってから返す（Box::newの直後に使う）
+#[inline(always)]
+pub fn touched<T: Touch>(mut value: T) -> T {
+    touch(&mut value);
+    value
+}
+
+/// ptrを命令を生成しないasmの入力として渡し、指す先が読み書きされたものとコンパイラに扱わせる
+/// asmを使えないアーキテクチャとMiriではstd::hint::black_boxで代える
+#[inline(always)]
+pub fn sink<T: ?Sized>(ptr: *const T) {
+    #[cfg(all(
+        not(miri),
+        any(
+            target_arch = "x86_64",
+            target_arch = "x86",
+            target_arch = "aarch64",
+            target_arch = "arm",
+            target_arch = "riscv64"
+        )
+    ))]
+    // SAFETY: asmはコメントだけで命令を生成せず、レジスタでポインタを受け取るだけ
+    unsafe {
+        std::arch::asm!("/* {0} */", in(reg) ptr.cast::<u8>(), options(nostack, preserves_flags));
+    }
+    #[cfg(not(all(
+        not(miri),
+        any(
+            target_arch = "x86_64",
+            target_arch = "x86",
+            target_arch = "aarch64",
+            target_arch = "arm",
+            target_arch = "riscv64"
+        )
+    )))]
+    std::hint::black_box(ptr);
+}
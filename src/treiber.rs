@@ -0,0 +1,180 @@
+use std::cell::UnsafeCell;
+use std::mem::MaybeUninit;
+// loomのテストではアトミック変数をloomのものに差し替えて、CASの全インターリーブを検査する
+#[cfg(all(test, feature = "loom"))]
+use loom::sync::atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering};
+#[cfg(not(all(test, feature = "loom")))]
+use std::sync::atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering};
+
+// ロックフリーのTreiberスタックで空きスロットを管理する固定容量のプール
+// Mutexで守ったプールと汎用アロケータの中間の参照点として、複数スレッドから共有して使う
+//
+// スロットは最初に容量分だけ確保し、一度も使っていないスロットはバンプポインタで払い出す
+// 先頭は (更新回数 << 32 | スロット番号) の64bitで持ち、CASのABA問題を更新回数で避ける
+
+// 空きスタックの終端
+const NIL: u32 = u32::MAX;
+
+struct Node<T> {
+    next: AtomicU32,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+pub struct TreiberPool<T> {
+    nodes: Box<[Node<T>]>,
+    head: AtomicU64,
+    // まだ一度も払い出していない最初のスロット
+    unused: AtomicUsize,
+}
+
+// SAFETY: スロットの値には、スタックから取り出したスレッドだけがアクセスする
+unsafe impl<T: Send> Sync for TreiberPool<T> {}
+
+impl<T> TreiberPool<T> {
+    pub fn with_capacity(capacity: usize) -> Self {
+        assert!(
+            capacity < NIL as usize,
+            "treiber pool capacity is too large"
+        );
+        TreiberPool {
+            nodes: (0..capacity)
+                .map(|_| Node {
+                    next: AtomicU32::new(NIL),
+                    value: UnsafeCell::new(MaybeUninit::uninit()),
+                })
+                .collect(),
+            head: AtomicU64::new(NIL as u64),
+            unused: AtomicUsize::new(0),
+        }
+    }
+
+    fn pop(&self) -> Option<u32> {
+        let mut head = self.head.load(Ordering::Acquire);
+        loop {
+            let index = head as u32;
+            if index == NIL {
+                return None;
+            }
+            // 他のスレッドが先に取り出していても、更新回数が変わるのでCASが失敗する
+            let next = self.nodes[index as usize].next.load(Ordering::Relaxed);
+            let new = ((head >> 32).wrapping_add(1) << 32) | next as u64;
+            match self
+                .head
+                .compare_exchange_weak(head, new, Ordering::Acquire, Ordering::Acquire)
+            {
+                Ok(_) => return Some(index),
+                Err(current) => head = current,
+            }
+        }
+    }
+
+    fn push(&self, index: u32) {
+        let mut head = self.head.load(Ordering::Relaxed);
+        loop {
+            self.nodes[index as usize]
+                .next
+                .store(head as u32, Ordering::Relaxed);
+            let new = ((head >> 32).wrapping_add(1) << 32) | index as u64;
+            match self
+                .head
+                .compare_exchange_weak(head, new, Ordering::Release, Ordering::Relaxed)
+            {
+                Ok(_) => return,
+                Err(current) => head = current,
+            }
+        }
+    }
+
+    // 空きスロットに値を入れてスロット番号を返す（容量を使い切っていればNone）
+    pub fn insert(&self, value: T) -> Option<usize> {
+        self.insert_with(value, |_| {})
+    }
+
+    // insertと同じだが、入れた値をスロットの中でfに渡してから返す（ベンチマークで確保した値に触るため）
+    pub fn insert_with(&self, value: T, f: impl FnOnce(&mut T)) -> Option<usize> {
+        let index = match self.pop() {
+            Some(index) => index as usize,
+            None => {
+                let index = self.unused.fetch_add(1, Ordering::Relaxed);
+                if index >= self.nodes.len() {
+                    self.unused.fetch_sub(1, Ordering::Relaxed);
+                    return None;
+                }
+                index
+            }
+        };
+        // SAFETY: 取り出したスロットはpushで戻すまでこのスレッドだけが使う
+        f(unsafe { (*self.nodes[index].value.get()).write(value) });
+        Some(index)
+    }
+
+    /// スロットから値を取り出し、スロットを空きスタックへ戻す
+    ///
+    /// # Safety
+    /// indexはinsertが返したもので、まだremoveしていないこと
+    pub unsafe fn remove(&self, index: usize) -> T {
+        // SAFETY: 呼び出し側がスロットに値が入っていることを保証する
+        let value = unsafe { (*self.nodes[index].value.get()).assume_init_read() };
+        self.push(index as u32);
+        value
+    }
+}
+
+#[cfg(all(test, feature = "loom"))]
+mod loom_tests {
+    use super::TreiberPool;
+    use loom::sync::Arc;
+    use loom::thread;
+
+    // 空きスタックからの取り出しと戻しが競合しても、同じスロットを2つのスレッドへ払い出さない
+    // （払い出しが重なると、相手の書いた値を読んでassertが失敗する）
+    #[test]
+    fn concurrent_pop_push() {
+        loom::model(|| {
+            let pool = Arc::new(TreiberPool::with_capacity(2));
+            // 2つのスロットを空きスタックに積んでおく
+            let a = pool.insert(0).unwrap();
+            let b = pool.insert(0).unwrap();
+            unsafe {
+                pool.remove(a);
+                pool.remove(b);
+            }
+            let handles: Vec<_> = (1..=2)
+                .map(|value| {
+                    let pool = Arc::clone(&pool);
+                    thread::spawn(move || {
+                        let index = pool.insert(value).unwrap();
+                        assert_eq!(unsafe { pool.remove(index) }, value);
+                    })
+                })
+                .collect();
+            for handle in handles {
+                handle.join().unwrap();
+            }
+            // 両方のスロットが空きスタックに戻っている
+            let x = pool.insert(0).unwrap();
+            let y = pool.insert(0).unwrap();
+            assert_ne!(x, y);
+            assert_eq!(pool.insert(0), None);
+        });
+    }
+
+    // 未使用スロットのバンプポインタを取り合っても、容量を超えて払い出さない
+    #[test]
+    fn concurrent_bump_at_capacity() {
+        loom::model(|| {
+            let pool = Arc::new(TreiberPool::with_capacity(1));
+            let handles: Vec<_> = (0..2)
+                .map(|value| {
+                    let pool = Arc::clone(&pool);
+                    thread::spawn(move || pool.insert(value))
+                })
+                .collect();
+            let inserted = handles
+                .into_iter()
+                .filter_map(|handle| handle.join().unwrap())
+                .count();
+            assert_eq!(inserted, 1);
+        });
+    }
+}
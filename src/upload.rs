@@ -0,0 +1,52 @@
+use crate::output::Format;
+use log::info;
+use std::fs::File;
+use std::path::Path;
+use std::time::Duration;
+
+// 接続確立までのタイムアウト（転送自体は大きなファイルもあるので制限しない）
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+// 書き出した結果ファイルを1つずつPOSTする
+// ファイル名はrootからの相対パス（Hiveレイアウトではパーティションのディレクトリを含む）
+pub fn upload_results(
+    url: &str,
+    root: &Path,
+    paths: &[impl AsRef<Path>],
+    format: Format,
+    platform: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let agent = ureq::AgentBuilder::new()
+        .timeout_connect(CONNECT_TIMEOUT)
+        .build();
+
+    for path in paths {
+        let path = path.as_ref();
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+        let file_name = path.strip_prefix(root).unwrap_or(path).to_string_lossy();
+
+        let response = agent
+            .post(url)
+            .set("Content-Type", format.content_type())
+            .set("Content-Length", &len.to_string())
+            .set(
+                "Content-Disposition",
+                &format!("attachment; filename=\"{}\"", file_name),
+            )
+            .set("X-Memalloc-Bench-Platform", platform)
+            .send(file)
+            .map_err(|e| format!("failed to upload {} to {}: {}", path.display(), url, e))?;
+
+        info!(
+            "Uploaded {} ({} bytes) to {}: {} {}",
+            path.display(),
+            len,
+            url,
+            response.status(),
+            response.status_text()
+        );
+    }
+
+    Ok(())
+}
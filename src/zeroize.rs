@@ -0,0 +1,108 @@
+use crate::bufpool::{
+    BoxedSliceAllocator, Buffer, BufferAllocator, BufferPool, ResetPolicy, ZeroizingAllocator,
+};
+use log::info;
+use memalloc_results::stats::quantile;
+use quanta::Clock;
+use std::hint::black_box;
+
+#[derive(clap::Args, Debug)]
+pub struct ZeroizeArgs {
+    /// Comma-separated buffer sizes in bytes
+    #[arg(long, value_delimiter = ',', num_args = 1.., default_value = "64,1024,4096,65536")]
+    sizes: Vec<usize>,
+
+    /// Lease/write/return cycles per iteration
+    #[arg(long, default_value_t = 1000)]
+    cycles: usize,
+
+    /// Iterations per cell
+    #[arg(long, default_value_t = 100)]
+    iterations: usize,
+}
+
+// 比べるプールの設定
+#[derive(Clone, Copy, Debug)]
+enum Mode {
+    Plain,   // resetしない（返されたバッファに中身が残る）
+    Zeroize, // ZeroizingBufferをResetPolicy::OnReturnで返却時に消去する
+}
+
+impl Mode {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Mode::Plain => "plain",
+            Mode::Zeroize => "zeroize",
+        }
+    }
+}
+
+// 借りたバッファ全体に書き込んで返すのをcycles回繰り返し、イテレーションごとの所要時間を返す（ソート済み）
+fn measure<A>(clock: &Clock, pool: &BufferPool<A>, args: &ZeroizeArgs) -> Vec<u64>
+where
+    A: BufferAllocator<Error = std::convert::Infallible>,
+    A::Buffer: Buffer,
+{
+    pool.reserve(1).unwrap();
+    let mut samples = Vec::with_capacity(args.iterations);
+    // 最初の1回はウォームアップとして捨てる
+    for i in 0..=args.iterations {
+        let start = clock.raw();
+        for c in 0..args.cycles {
+            let mut lease = pool.lease().unwrap();
+            lease.as_mut_slice().fill(c as u8);
+            drop(black_box(lease));
+        }
+        let end = clock.raw();
+        if i > 0 {
+            samples.push(clock.delta(start, end).as_nanos() as u64);
+        }
+    }
+    samples.sort_unstable();
+    samples
+}
+
+pub fn run(args: &ZeroizeArgs) -> Result<(), Box<dyn std::error::Error>> {
+    if args.sizes.contains(&0) || args.cycles == 0 || args.iterations == 0 {
+        return Err("--sizes, --cycles and --iterations must be greater than 0".into());
+    }
+    let clock = Clock::new();
+
+    info!(
+        "Lease, fill and return a pooled buffer {} times per iteration ({} iterations)",
+        args.cycles, args.iterations
+    );
+    println!(
+        "{:>9} {:<8} {:>14} {:>14} {:>10}",
+        "size", "mode", "median(ns)", "p99(ns)", "overhead"
+    );
+
+    for &size in &args.sizes {
+        let mut plain_median = None;
+        for mode in [Mode::Plain, Mode::Zeroize] {
+            let allocator = BoxedSliceAllocator::new(size);
+            let samples = match mode {
+                Mode::Plain => measure(&clock, &BufferPool::new(allocator), args),
+                Mode::Zeroize => {
+                    let pool = BufferPool::new(ZeroizingAllocator::new(allocator))
+                        .with_reset_policy(ResetPolicy::OnReturn);
+                    measure(&clock, &pool, args)
+                }
+            };
+            // 1サイクルあたりの時間にする
+            let cycles = args.cycles as f64;
+            let median = quantile(&samples, 0.5) / cycles;
+            let plain = *plain_median.get_or_insert(median);
+            println!(
+                "{:>9} {:<8} {:>14.2} {:>14.2} {:>9.2}x",
+                size,
+                mode.as_str(),
+                median,
+                quantile(&samples, 0.99) / cycles,
+                median / plain
+            );
+        }
+    }
+
+    Ok(())
+}